@@ -1,3 +1,6 @@
+use crate::logging::Session;
+use crate::metrics::{LoginFailure, METRICS};
+use crate::shutdown;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::{Arc, Mutex};
@@ -21,23 +24,29 @@ pub async fn auth(users: Arc<Mutex<HashMap<String, SrpServer>>>) {
     let listener = TcpListener::bind("0.0.0.0:3724").await.unwrap();
 
     loop {
-        let (stream, _) = listener.accept().await.unwrap();
+        let (stream, _) = tokio::select! {
+            r = listener.accept() => r.unwrap(),
+            _ = shutdown::stopped() => break,
+        };
 
         tokio::spawn(handle(stream, users.clone()));
     }
 }
 
 async fn handle(mut stream: TcpStream, users: Arc<Mutex<HashMap<String, SrpServer>>>) {
+    let _guard = METRICS.auth_session();
+    let session = Session::new(None, stream.peer_addr().ok());
+
     let opcode = tokio_read_initial_message(&mut stream).await;
     let opcode = match opcode {
         Ok(o) => o,
         Err(e) => {
             match e {
                 ExpectedOpcodeError::Opcode(o) => {
-                    println!("invalid opcode {}", o)
+                    warn!(session: &session, "Invalid opcode {o}")
                 }
                 ExpectedOpcodeError::Parse(e) => {
-                    println!("parse error {:#?}", e)
+                    warn!(session: &session, "Unable to parse message: {e:?}")
                 }
                 ExpectedOpcodeError::Io(i) => panic!("{}", i),
             }
@@ -67,7 +76,8 @@ async fn reconnect_version_8(
 ) {
     use wow_login_messages::version_8::*;
 
-    println!("Reconnect version: {}", r.protocol_version);
+    let session = Session::new(Some(r.account_name.clone()), stream.peer_addr().ok());
+    info!(session: &session, "Reconnect version: {}", r.protocol_version);
 
     let server_reconnect_challenge_data = *users
         .lock()
@@ -98,6 +108,9 @@ async fn reconnect_version_8(
     };
 
     if !success {
+        warn!(session: &session, "Reconnect proof did not match");
+        METRICS.record_login_failure(LoginFailure::Banned);
+
         CMD_AUTH_RECONNECT_PROOF_Server {
             result: LoginResult::FailBanned,
         }
@@ -115,7 +128,7 @@ async fn reconnect_version_8(
     .await
     .unwrap();
 
-    print_version_8_realm_list(stream).await;
+    print_version_8_realm_list(stream, &session).await;
 }
 
 async fn reconnect_version_2(
@@ -125,7 +138,8 @@ async fn reconnect_version_2(
 ) {
     use wow_login_messages::version_2::*;
 
-    println!("Reconnect version: {}", r.protocol_version);
+    let session = Session::new(Some(r.account_name.clone()), stream.peer_addr().ok());
+    info!(session: &session, "Reconnect version: {}", r.protocol_version);
 
     let server_reconnect_challenge_data = *users
         .lock()
@@ -156,6 +170,9 @@ async fn reconnect_version_2(
     };
 
     if !success {
+        warn!(session: &session, "Reconnect proof did not match");
+        METRICS.record_login_failure(LoginFailure::Banned);
+
         CMD_AUTH_RECONNECT_PROOF_Server {
             result: LoginResult::FailBanned,
         }
@@ -173,7 +190,7 @@ async fn reconnect_version_2(
     .await
     .unwrap();
 
-    print_version_2_3_realm_list(stream).await;
+    print_version_2_3_realm_list(stream, &session).await;
 }
 
 async fn login_version_2(
@@ -183,7 +200,8 @@ async fn login_version_2(
 ) {
     use wow_login_messages::version_2::*;
 
-    println!("Login version: {}", l.protocol_version);
+    let session = Session::new(Some(l.account_name.clone()), stream.peer_addr().ok());
+    info!(session: &session, "Login version: {}", l.protocol_version);
     let p = get_proof(&l.account_name);
 
     let username = l.account_name;
@@ -200,19 +218,29 @@ async fn login_version_2(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Challenge");
+    debug!(session: &session, "Sent Logon Challenge");
 
     let l = tokio_expect_client_message::<CMD_AUTH_LOGON_PROOF_Client, _>(&mut stream)
         .await
         .unwrap();
 
-    let (p, proof) = p
-        .into_server(
-            PublicKey::from_le_bytes(l.client_public_key).unwrap(),
-            l.client_proof,
-        )
+    let Ok((p, proof)) = p.into_server(
+        PublicKey::from_le_bytes(l.client_public_key).unwrap(),
+        l.client_proof,
+    ) else {
+        warn!(session: &session, "Logon proof did not match");
+        METRICS.record_login_failure(LoginFailure::IncorrectPassword);
+
+        CMD_AUTH_LOGON_PROOF_Server {
+            result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::FailIncorrectPassword,
+        }
+        .tokio_write(&mut stream)
+        .await
         .unwrap();
 
+        return;
+    };
+
     CMD_AUTH_LOGON_PROOF_Server {
         result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::Success {
             server_proof: proof,
@@ -222,11 +250,11 @@ async fn login_version_2(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Proof");
+    debug!(session: &session, "Sent Logon Proof");
 
     users.lock().unwrap().insert(username, p);
 
-    print_version_2_3_realm_list(stream).await;
+    print_version_2_3_realm_list(stream, &session).await;
 }
 
 fn get_proof(username: &str) -> SrpProof {
@@ -242,7 +270,8 @@ async fn login_version_3(
 ) {
     use wow_login_messages::version_3::*;
 
-    println!("Login version: {}", l.protocol_version);
+    let session = Session::new(Some(l.account_name.clone()), stream.peer_addr().ok());
+    info!(session: &session, "Login version: {}", l.protocol_version);
     let p = get_proof(&l.account_name);
     let username = l.account_name;
 
@@ -259,19 +288,29 @@ async fn login_version_3(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Challenge");
+    debug!(session: &session, "Sent Logon Challenge");
 
     let l = tokio_expect_client_message::<CMD_AUTH_LOGON_PROOF_Client, _>(&mut stream)
         .await
         .unwrap();
 
-    let (p, proof) = p
-        .into_server(
-            PublicKey::from_le_bytes(l.client_public_key).unwrap(),
-            l.client_proof,
-        )
+    let Ok((p, proof)) = p.into_server(
+        PublicKey::from_le_bytes(l.client_public_key).unwrap(),
+        l.client_proof,
+    ) else {
+        warn!(session: &session, "Logon proof did not match");
+        METRICS.record_login_failure(LoginFailure::IncorrectPassword);
+
+        CMD_AUTH_LOGON_PROOF_Server {
+            result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::FailIncorrectPassword,
+        }
+        .tokio_write(&mut stream)
+        .await
         .unwrap();
 
+        return;
+    };
+
     CMD_AUTH_LOGON_PROOF_Server {
         result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::Success {
             server_proof: proof,
@@ -281,11 +320,11 @@ async fn login_version_3(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Proof");
+    debug!(session: &session, "Sent Logon Proof");
 
     users.lock().unwrap().insert(username.to_string(), p);
 
-    print_version_2_3_realm_list(stream).await;
+    print_version_2_3_realm_list(stream, &session).await;
 }
 async fn login_version_8(
     mut stream: TcpStream,
@@ -294,7 +333,8 @@ async fn login_version_8(
 ) {
     use wow_login_messages::version_8::*;
 
-    println!("Login version: {}", l.protocol_version);
+    let session = Session::new(Some(l.account_name.clone()), stream.peer_addr().ok());
+    info!(session: &session, "Login version: {}", l.protocol_version);
     let p = get_proof(&l.account_name);
     let username = l.account_name;
 
@@ -311,19 +351,29 @@ async fn login_version_8(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Challenge");
+    debug!(session: &session, "Sent Logon Challenge");
 
     let l = tokio_expect_client_message::<CMD_AUTH_LOGON_PROOF_Client, _>(&mut stream)
         .await
         .unwrap();
 
-    let (p, server_proof) = p
-        .into_server(
-            PublicKey::from_le_bytes(l.client_public_key).unwrap(),
-            l.client_proof,
-        )
+    let Ok((p, server_proof)) = p.into_server(
+        PublicKey::from_le_bytes(l.client_public_key).unwrap(),
+        l.client_proof,
+    ) else {
+        warn!(session: &session, "Logon proof did not match");
+        METRICS.record_login_failure(LoginFailure::IncorrectPassword);
+
+        CMD_AUTH_LOGON_PROOF_Server {
+            result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::FailIncorrectPassword,
+        }
+        .tokio_write(&mut stream)
+        .await
         .unwrap();
 
+        return;
+    };
+
     CMD_AUTH_LOGON_PROOF_Server {
         result: CMD_AUTH_LOGON_PROOF_Server_LoginResult::Success {
             account_flag: AccountFlag::empty(),
@@ -335,11 +385,11 @@ async fn login_version_8(
     .tokio_write(&mut stream)
     .await
     .unwrap();
-    println!("Sent Logon Proof");
+    debug!(session: &session, "Sent Logon Proof");
 
     users.lock().unwrap().insert(username.to_string(), p);
 
-    print_version_8_realm_list(stream).await;
+    print_version_8_realm_list(stream, &session).await;
 }
 
 fn get_world_server_string(ip: &IpAddr) -> String {
@@ -354,7 +404,7 @@ fn get_world_server_string(ip: &IpAddr) -> String {
         IpAddr::V6(_) => EXTERNAL_WORLD_STRING.to_string(),
     }
 }
-async fn print_version_2_3_realm_list(mut stream: TcpStream) {
+async fn print_version_2_3_realm_list(mut stream: TcpStream, session: &Session) {
     use wow_login_messages::version_2::*;
 
     let addr = get_world_server_string(&stream.peer_addr().unwrap().ip());
@@ -375,11 +425,11 @@ async fn print_version_2_3_realm_list(mut stream: TcpStream) {
         .tokio_write(&mut stream)
         .await
         .unwrap();
-        println!("Sent Version 2/3 Realm List");
+        debug!(session: session, "Sent Version 2/3 Realm List");
     }
 }
 
-async fn print_version_8_realm_list(mut stream: TcpStream) {
+async fn print_version_8_realm_list(mut stream: TcpStream, session: &Session) {
     use wow_login_messages::version_8::*;
 
     let addr = get_world_server_string(&stream.peer_addr().unwrap().ip());
@@ -404,6 +454,6 @@ async fn print_version_8_realm_list(mut stream: TcpStream) {
             .tokio_write(&mut stream)
             .await
             .unwrap();
-        println!("Sent Version 8 Realm List");
+        debug!(session: session, "Sent Version 8 Realm List");
     }
 }
@@ -0,0 +1,107 @@
+use crate::logging::{LogFilter, LogFormat};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// What to do with a client that sends movement which fails validation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MovementViolationAction {
+    /// Only print the violation, the movement is still accepted.
+    Log,
+    /// Teleport the client back to the last accepted position.
+    RubberBand,
+    /// Disconnect the client.
+    Kick,
+}
+
+impl MovementViolationAction {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "log" => Self::Log,
+            "rubberband" | "rubber-band" => Self::RubberBand,
+            "kick" => Self::Kick,
+            _ => return None,
+        })
+    }
+}
+
+/// Runtime configuration read from environment variables on first use.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `WOW_VANILLA_MOVEMENT_VIOLATION`, one of `log`, `rubberband` or `kick`.
+    pub movement_violation: MovementViolationAction,
+    /// `WOW_VANILLA_METRICS_PORT`, the metrics endpoint is disabled when unset.
+    pub metrics_port: Option<u16>,
+    /// `WOW_VANILLA_LOG`, like `info,world::world_map=debug`.
+    pub log_filter: LogFilter,
+    /// `WOW_VANILLA_LOG_FORMAT`, either `text` or `json`.
+    pub log_format: LogFormat,
+    /// `WOW_VANILLA_LOG_FILE`, logs go to stderr when unset.
+    pub log_file: Option<PathBuf>,
+    /// `WOW_VANILLA_DATA_DIR`, directory characters are saved in.
+    /// Defaults to `characters`, an empty value keeps characters in memory only.
+    pub data_directory: Option<PathBuf>,
+    /// `WOW_VANILLA_AUTOSAVE_SECONDS`, `0` disables autosaving. Defaults to 5 minutes.
+    pub autosave_interval: Option<Duration>,
+    /// `WOW_VANILLA_CREATURE_FILE`, creature templates and spawns. Defaults to `creatures.txt`.
+    pub creature_file: PathBuf,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        let movement_violation = env_var(
+            "WOW_VANILLA_MOVEMENT_VIOLATION",
+            MovementViolationAction::parse,
+        )
+        .unwrap_or(MovementViolationAction::RubberBand);
+
+        let metrics_port = env_var("WOW_VANILLA_METRICS_PORT", |s| s.parse().ok());
+
+        let log_filter = env_var("WOW_VANILLA_LOG", LogFilter::parse).unwrap_or_default();
+        let log_format =
+            env_var("WOW_VANILLA_LOG_FORMAT", LogFormat::parse).unwrap_or(LogFormat::Text);
+        let log_file = env_var("WOW_VANILLA_LOG_FILE", |s| Some(PathBuf::from(s)));
+
+        let data_directory = match std::env::var("WOW_VANILLA_DATA_DIR") {
+            Ok(s) if s.is_empty() => None,
+            Ok(s) => Some(PathBuf::from(s)),
+            Err(_) => Some(PathBuf::from("characters")),
+        };
+        let autosave_interval =
+            env_var("WOW_VANILLA_AUTOSAVE_SECONDS", |s| s.parse::<u64>().ok()).unwrap_or(5 * 60);
+        let autosave_interval =
+            (autosave_interval != 0).then(|| Duration::from_secs(autosave_interval));
+        let creature_file = env_var("WOW_VANILLA_CREATURE_FILE", |s| Some(PathBuf::from(s)))
+            .unwrap_or_else(|| PathBuf::from("creatures.txt"));
+
+        Self {
+            movement_violation,
+            metrics_port,
+            log_filter,
+            log_format,
+            log_file,
+            data_directory,
+            autosave_interval,
+            creature_file,
+        }
+    }
+}
+
+fn env_var<T>(name: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+
+    match parse(&value) {
+        Some(v) => Some(v),
+        None => {
+            // Logging is configured from here, so it can not be used yet
+            eprintln!("Invalid value '{value}' for '{name}', using default.");
+            None
+        }
+    }
+}
+
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+
+    CONFIG.get_or_init(Config::from_env)
+}
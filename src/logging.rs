@@ -0,0 +1,312 @@
+use crate::config::config;
+use std::fmt::{Arguments, Display, Formatter, Write as _};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wow_world_base::shared::Guid;
+
+/// Writes a log line with [`module_path!`] as the module.
+///
+/// Prefix the message with `session: <&Session>,` to attach the account, character and peer address.
+macro_rules! log {
+    ($level:expr, session: $session:expr, $($arg:tt)+) => {
+        $crate::logging::log($level, module_path!(), Some($session), format_args!($($arg)+))
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::logging::log($level, module_path!(), None, format_args!($($arg)+))
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            "trace" => Self::Trace,
+            _ => return None,
+        })
+    }
+
+    const fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+/// Maximum level per module, in the form `info,world::world_map=debug,auth=warn`.
+///
+/// Module names are the Rust module paths, with or without the crate name.
+/// The longest matching module wins.
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    default: Level,
+    modules: Vec<(String, Level)>,
+}
+
+impl LogFilter {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut filter = Self::default();
+
+        for directive in s.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()) {
+            match directive.split_once('=') {
+                None => filter.default = Level::parse(directive)?,
+                Some((module, level)) => {
+                    let module = module.trim();
+                    let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+                    filter
+                        .modules
+                        .push((module.to_string(), Level::parse(level.trim())?));
+                }
+            }
+        }
+
+        Some(filter)
+    }
+
+    fn enabled(&self, level: Level, module: &str) -> bool {
+        let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+
+        let max = self
+            .modules
+            .iter()
+            .filter(|(m, _)| {
+                module == m
+                    || (module.starts_with(m.as_str()) && module[m.len()..].starts_with("::"))
+            })
+            .max_by_key(|(m, _)| m.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default);
+
+        level <= max
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: Level::Info,
+            modules: vec![],
+        }
+    }
+}
+
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            _ => return None,
+        })
+    }
+}
+
+/// Who a log line is about.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub account: Option<String>,
+    pub character: Option<Guid>,
+    pub peer: Option<SocketAddr>,
+}
+
+impl Session {
+    pub fn new(account: Option<String>, peer: Option<SocketAddr>) -> Self {
+        Self {
+            account,
+            character: None,
+            peer,
+        }
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut separator = "";
+        f.write_str("[")?;
+
+        if let Some(account) = &self.account {
+            write!(f, "account={account}")?;
+            separator = " ";
+        }
+        if let Some(character) = &self.character {
+            write!(f, "{separator}guid={character}")?;
+            separator = " ";
+        }
+        if let Some(peer) = &self.peer {
+            write!(f, "{separator}peer={peer}")?;
+        }
+
+        f.write_str("]")
+    }
+}
+
+struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Logger {
+    fn from_config() -> Self {
+        let config = config();
+
+        let output: Box<dyn Write + Send> = match &config.log_file {
+            Some(path) => match open_log_file(path) {
+                Ok(f) => Box::new(f),
+                Err(e) => {
+                    eprintln!("Unable to open log file '{}': {e}", path.display());
+                    Box::new(std::io::stderr())
+                }
+            },
+            None => Box::new(std::io::stderr()),
+        };
+
+        Self {
+            filter: config.log_filter.clone(),
+            format: config.log_format,
+            output: Mutex::new(output),
+        }
+    }
+}
+
+fn open_log_file(path: &Path) -> std::io::Result<impl Write + Send> {
+    let f = File::options().create(true).append(true).open(path)?;
+    Ok(LineWriter(BufWriter::new(f)))
+}
+
+/// Flushes after every line so that nothing is lost on a crash.
+struct LineWriter<W: Write>(W);
+
+impl<W: Write> Write for LineWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.0.write(buf)?;
+        if buf.ends_with(b"\n") {
+            self.0.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn logger() -> &'static Logger {
+    static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+    LOGGER.get_or_init(Logger::from_config)
+}
+
+/// Use the [`log!`] family of macros instead of calling this directly.
+pub fn log(level: Level, module: &str, session: Option<&Session>, args: Arguments) {
+    let logger = logger();
+    if !logger.filter.enabled(level, module) {
+        return;
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    let mut line = String::with_capacity(128);
+
+    match logger.format {
+        LogFormat::Text => {
+            write!(line, "{timestamp:.3} {:<5} {module}", level.name()).unwrap();
+            if let Some(session) = session {
+                write!(line, " {session}").unwrap();
+            }
+            writeln!(line, ": {args}").unwrap();
+        }
+        LogFormat::Json => {
+            write!(
+                line,
+                "{{\"timestamp\":{timestamp:.3},\"level\":\"{}\",\"module\":{}",
+                level.name(),
+                json_string(module)
+            )
+            .unwrap();
+
+            if let Some(session) = session {
+                if let Some(account) = &session.account {
+                    write!(line, ",\"account\":{}", json_string(account)).unwrap();
+                }
+                if let Some(character) = &session.character {
+                    write!(line, ",\"guid\":\"{character}\"").unwrap();
+                }
+                if let Some(peer) = &session.peer {
+                    write!(line, ",\"peer\":\"{peer}\"").unwrap();
+                }
+            }
+
+            writeln!(line, ",\"message\":{}}}", json_string(&args.to_string())).unwrap();
+        }
+    }
+
+    let mut output = logger.output.lock().unwrap();
+    let _ = output.write_all(line.as_bytes());
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
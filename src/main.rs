@@ -1,5 +1,11 @@
+#[macro_use]
+mod logging;
+
 mod auth;
+mod config;
 mod file_utils;
+mod metrics;
+mod shutdown;
 mod world;
 
 use std::collections::HashMap;
@@ -13,6 +19,9 @@ async fn main() {
 
     let world_server = tokio::spawn(world::world(users.clone()));
 
+    tokio::spawn(metrics::metrics_server());
+    tokio::spawn(shutdown::handle_signals());
+
     let s = tokio::join!(auth_server, world_server);
     s.0.unwrap();
     s.1.unwrap();
@@ -0,0 +1,392 @@
+use crate::config::config;
+use crate::world::tick_metrics::{Histogram, TickPhase, TICK_METRICS};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Counters and gauges shared by the auth and world servers.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Players and creatures on a map, summed over every instance of the map.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MapCounts {
+    pub instances: u64,
+    pub clients: u64,
+    pub creatures: u64,
+}
+
+/// Why a login on the auth server failed.
+///
+/// Every protocol version has its own `LoginResult`, so failures are recorded with this instead.
+#[derive(Debug, Copy, Clone)]
+pub enum LoginFailure {
+    Banned,
+    IncorrectPassword,
+}
+
+impl LoginFailure {
+    const fn label(self) -> &'static str {
+        match self {
+            LoginFailure::Banned => "banned",
+            LoginFailure::IncorrectPassword => "incorrect_password",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    auth_sessions: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    character_screen_clients: AtomicU64,
+    maps: Mutex<BTreeMap<String, MapCounts>>,
+    opcodes: Mutex<BTreeMap<&'static str, u64>>,
+    login_failures: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            auth_sessions: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            character_screen_clients: AtomicU64::new(0),
+            maps: Mutex::new(BTreeMap::new()),
+            opcodes: Mutex::new(BTreeMap::new()),
+            login_failures: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts an auth connection until the returned guard is dropped.
+    pub fn auth_session(&'static self) -> AuthSessionGuard {
+        self.auth_sessions.fetch_add(1, Ordering::Relaxed);
+        AuthSessionGuard { metrics: self }
+    }
+
+    pub fn record_opcode(&self, name: &'static str) {
+        *self.opcodes.lock().unwrap().entry(name).or_default() += 1;
+    }
+
+    pub fn record_login_failure(&self, failure: LoginFailure) {
+        *self
+            .login_failures
+            .lock()
+            .unwrap()
+            .entry(failure.label())
+            .or_default() += 1;
+    }
+
+    /// Replaces the world gauges, called once per tick.
+    pub fn set_world(&self, character_screen_clients: usize, maps: BTreeMap<String, MapCounts>) {
+        self.character_screen_clients
+            .store(character_screen_clients as u64, Ordering::Relaxed);
+        *self.maps.lock().unwrap() = maps;
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut s = String::with_capacity(4096);
+
+        gauge(
+            &mut s,
+            "wow_auth_sessions",
+            "Connections to the auth server",
+            self.auth_sessions.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut s,
+            "wow_character_screen_clients",
+            "Clients on the character screen",
+            self.character_screen_clients.load(Ordering::Relaxed),
+        );
+
+        {
+            let maps = self.maps.lock().unwrap();
+            let labelled = |f: fn(&MapCounts) -> u64| {
+                maps.iter()
+                    .map(|(map, counts)| (format!("map=\"{}\"", escape(map)), f(counts)))
+                    .collect::<Vec<_>>()
+            };
+
+            labelled_metric(
+                &mut s,
+                "wow_world_clients",
+                "gauge",
+                "Clients in the world",
+                labelled(|c| c.clients),
+            );
+            labelled_metric(
+                &mut s,
+                "wow_creatures",
+                "gauge",
+                "Creatures in the world",
+                labelled(|c| c.creatures),
+            );
+            labelled_metric(
+                &mut s,
+                "wow_map_instances",
+                "gauge",
+                "Loaded instances",
+                labelled(|c| c.instances),
+            );
+        }
+
+        {
+            let tick = TICK_METRICS.lock().unwrap();
+            histogram(
+                &mut s,
+                "wow_tick_duration_seconds",
+                "Duration of a world tick",
+                &[(String::new(), tick.total())],
+            );
+
+            let phases = TickPhase::ALL
+                .iter()
+                .map(|p| (format!("phase=\"{}\"", p.name()), tick.phase(*p)))
+                .collect::<Vec<_>>();
+            histogram(
+                &mut s,
+                "wow_tick_phase_duration_seconds",
+                "Duration of a part of a world tick, summed over maps",
+                &phases,
+            );
+        }
+
+        let opcodes = self
+            .opcodes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, count)| (format!("opcode=\"{name}\""), *count))
+            .collect();
+        labelled_metric(
+            &mut s,
+            "wow_client_opcodes_total",
+            "counter",
+            "Opcodes received from world clients",
+            opcodes,
+        );
+
+        let failures = self
+            .login_failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(result, count)| (format!("result=\"{}\"", escape(result)), *count))
+            .collect();
+        labelled_metric(
+            &mut s,
+            "wow_login_failures_total",
+            "counter",
+            "Failed logins on the auth server",
+            failures,
+        );
+
+        labelled_metric(
+            &mut s,
+            "wow_world_bytes_total",
+            "counter",
+            "Bytes sent and received by world clients",
+            vec![
+                (
+                    "direction=\"in\"".to_string(),
+                    self.bytes_in.load(Ordering::Relaxed),
+                ),
+                (
+                    "direction=\"out\"".to_string(),
+                    self.bytes_out.load(Ordering::Relaxed),
+                ),
+            ],
+        );
+
+        s
+    }
+}
+
+pub struct AuthSessionGuard {
+    metrics: &'static Metrics,
+}
+
+impl Drop for AuthSessionGuard {
+    fn drop(&mut self) {
+        self.metrics.auth_sessions.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn gauge(s: &mut String, name: &str, help: &str, value: u64) {
+    labelled_metric(s, name, "gauge", help, vec![(String::new(), value)]);
+}
+
+fn labelled_metric(s: &mut String, name: &str, kind: &str, help: &str, values: Vec<(String, u64)>) {
+    writeln!(s, "# HELP {name} {help}").unwrap();
+    writeln!(s, "# TYPE {name} {kind}").unwrap();
+
+    for (labels, value) in values {
+        if labels.is_empty() {
+            writeln!(s, "{name} {value}").unwrap();
+        } else {
+            writeln!(s, "{name}{{{labels}}} {value}").unwrap();
+        }
+    }
+}
+
+fn histogram(s: &mut String, name: &str, help: &str, values: &[(String, &Histogram)]) {
+    writeln!(s, "# HELP {name} {help}").unwrap();
+    writeln!(s, "# TYPE {name} histogram").unwrap();
+
+    for (labels, h) in values {
+        let separator = if labels.is_empty() { "" } else { "," };
+
+        for (bound, count) in h.cumulative_buckets() {
+            writeln!(
+                s,
+                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {count}"
+            )
+            .unwrap();
+        }
+        writeln!(
+            s,
+            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {}",
+            h.count()
+        )
+        .unwrap();
+
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        writeln!(s, "{name}_sum{labels} {}", h.sum().as_secs_f64()).unwrap();
+        writeln!(s, "{name}_count{labels} {}", h.count()).unwrap();
+    }
+}
+
+/// Serves [`METRICS`] on localhost if `WOW_VANILLA_METRICS_PORT` is set.
+pub async fn metrics_server() {
+    let Some(port) = config().metrics_port else {
+        return;
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Unable to serve metrics on port {port}: {e}");
+            return;
+        }
+    };
+    info!("Serving metrics on http://127.0.0.1:{port}/metrics");
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Unable to accept metrics connection: {e}");
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(stream).await {
+                warn!("Metrics request failed: {e}");
+            }
+        });
+    }
+}
+
+async fn serve_metrics(mut stream: TcpStream) -> io::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+    let mut buf = [0_u8; 1024];
+
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if request.starts_with("GET ") && path == "/metrics" {
+        ("200 OK", METRICS.render())
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Wrapper around a socket half that counts the bytes going through it.
+#[derive(Debug)]
+pub struct Counted<T> {
+    inner: T,
+}
+
+impl<T> Counted<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Counted<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = result {
+            let read = buf.filled().len() - before;
+            METRICS.bytes_in.fetch_add(read as u64, Ordering::Relaxed);
+        }
+
+        result
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Counted<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = result {
+            METRICS
+                .bytes_out
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
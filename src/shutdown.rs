@@ -0,0 +1,78 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Server wide shutdown state, shared by the listeners, the world and GM commands.
+struct Shutdown {
+    deadline: Mutex<Option<Instant>>,
+    stopped: watch::Sender<bool>,
+}
+
+fn state() -> &'static Shutdown {
+    static SHUTDOWN: OnceLock<Shutdown> = OnceLock::new();
+
+    SHUTDOWN.get_or_init(|| Shutdown {
+        deadline: Mutex::new(None),
+        stopped: watch::channel(false).0,
+    })
+}
+
+/// Schedules a shutdown, replacing any earlier schedule.
+pub fn request(after: Duration) {
+    *state().deadline.lock().unwrap() = Some(Instant::now() + after);
+}
+
+/// Returns false if no shutdown was scheduled.
+pub fn cancel() -> bool {
+    state().deadline.lock().unwrap().take().is_some()
+}
+
+pub fn deadline() -> Option<Instant> {
+    *state().deadline.lock().unwrap()
+}
+
+/// Called by the world once every character has been saved.
+pub fn stop() {
+    state().stopped.send_replace(true);
+}
+
+/// Completes once [`stop`] has been called.
+pub async fn stopped() {
+    let mut receiver = state().stopped.subscribe();
+    let _ = receiver.wait_for(|stopped| *stopped).await;
+}
+
+/// Schedules an immediate shutdown on SIGINT or SIGTERM.
+pub async fn handle_signals() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.unwrap();
+
+    info!("Received shutdown signal");
+    request(Duration::ZERO);
+}
+
+/// Formats the time left as shown after "Shutdown in" by the client.
+pub fn format_remaining(seconds: u64) -> String {
+    match (seconds / 60, seconds % 60) {
+        (0, s) => format!("{s} second(s)"),
+        (m, 0) => format!("{m} minute(s)"),
+        (m, s) => format!("{m} minute(s) {s} second(s)"),
+    }
+}
+
+/// Whether a countdown warning should be sent with this many seconds left.
+pub fn should_announce(seconds: u64) -> bool {
+    matches!(seconds, 1..=5 | 10 | 15 | 30) || (seconds != 0 && seconds % 60 == 0)
+}
@@ -1,9 +1,12 @@
+use crate::metrics::METRICS;
 use crate::world::database::WorldDatabase;
 use crate::world::world::client::character_screen_client::{
     CharacterScreenClient, CharacterScreenProgress,
 };
 use crate::world::world::get_client_login_messages;
+use crate::world::world::tick_metrics::TickProfile;
 use crate::world::world_opcode_handler::write_client_test;
+use std::time::Instant;
 use wow_world_messages::vanilla::opcodes::ClientOpcodeMessage;
 use wow_world_messages::vanilla::{
     Character, WorldResult, SMSG_CHAR_CREATE, SMSG_CHAR_ENUM, SMSG_PONG,
@@ -14,8 +17,13 @@ mod char_create;
 pub async fn handle_character_screen_opcodes(
     client: &mut CharacterScreenClient,
     db: &mut WorldDatabase,
+    profile: &mut TickProfile,
 ) {
     while let Ok(opcode) = client.received_messages().try_recv() {
+        let start = Instant::now();
+        let opcode_name = opcode.message_name();
+        METRICS.record_opcode(opcode_name);
+
         match opcode {
             ClientOpcodeMessage::CMSG_PING(c) => {
                 client
@@ -65,9 +73,11 @@ pub async fn handle_character_screen_opcodes(
                 }
             }
             e => {
-                dbg!(&e);
+                debug!(session: client.session(), "Unhandled character screen opcode: {e:?}");
                 write_client_test(&e);
             }
         }
+
+        profile.record_opcode(client.account_name(), opcode_name, start.elapsed());
     }
 }
@@ -0,0 +1,575 @@
+use crate::world::world::instances::InstanceBinding;
+use crate::world::world_opcode_handler::aura::{Aura, Auras};
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::death::{Corpse, DeathState, RESURRECTION_SICKNESS};
+use crate::world::world_opcode_handler::inventory::{Inventory, ItemPosition};
+use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::spell::cooldown::Cooldown;
+use crate::world::world_opcode_handler::spell::data::lookup_spell;
+use crate::world::world_opcode_handler::update_fields::DirtyFields;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use wow_items::vanilla::lookup_item;
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::{ItemSlot, Level, Map, PlayerGender, RaceClass, Skill, Vector3d};
+use wow_world_messages::vanilla::{Area, Class, Gender, MovementInfo, Race};
+use wow_world_messages::Guid;
+
+/// Bumped whenever a key changes meaning, new keys do not need a new version.
+const VERSION: u32 = 1;
+
+/// Serializes a character as `key=value` lines.
+///
+/// Repeated keys, like `item`, hold comma separated values.
+pub(super) fn write_character(c: &Character) -> String {
+    let mut s = String::with_capacity(1024);
+    let race: Race = c.race_class.race().into();
+    let gender: Gender = c.gender.into();
+
+    writeln!(s, "version={VERSION}").unwrap();
+    writeln!(s, "guid={}", c.guid.guid()).unwrap();
+    writeln!(s, "name={}", c.name).unwrap();
+    writeln!(s, "race={}", race.as_int()).unwrap();
+    writeln!(s, "class={}", c.race_class.class().as_int()).unwrap();
+    writeln!(s, "gender={}", gender.as_int()).unwrap();
+    writeln!(s, "skin={}", c.skin).unwrap();
+    writeln!(s, "face={}", c.face).unwrap();
+    writeln!(s, "hairstyle={}", c.hairstyle).unwrap();
+    writeln!(s, "haircolor={}", c.haircolor).unwrap();
+    writeln!(s, "facialhair={}", c.facialhair).unwrap();
+    writeln!(s, "level={}", c.level.as_int()).unwrap();
+    writeln!(s, "area={}", c.area.as_int()).unwrap();
+    writeln!(s, "map={}", c.map.as_int()).unwrap();
+    let p = &c.info.position;
+    writeln!(s, "position={},{},{},{}", p.x, p.y, p.z, c.info.orientation).unwrap();
+    writeln!(s, "movement_speed={}", c.movement_speed).unwrap();
+    writeln!(s, "health={}", c.health).unwrap();
+    writeln!(s, "power={}", c.power).unwrap();
+    writeln!(s, "death_state={}", c.death_state.name()).unwrap();
+    writeln!(s, "money={}", c.money).unwrap();
+    let skills: Vec<String> = c.skills.iter().map(|s| s.as_int().to_string()).collect();
+    writeln!(s, "skills={}", skills.join(",")).unwrap();
+
+    if let Some(corpse) = &c.corpse {
+        let p = &corpse.position;
+        writeln!(
+            s,
+            "corpse={},{},{},{},{},{}",
+            corpse.guid.guid(),
+            p.map.as_int(),
+            p.x,
+            p.y,
+            p.z,
+            p.orientation
+        )
+        .unwrap();
+    }
+
+    for aura in c.auras.iter() {
+        // Auras that last until cancelled are written with no time left,
+        // so timed auras that are about to run out keep at least a millisecond
+        let remaining = aura
+            .remaining
+            .map(|r| ((r * 1000.0) as u32).max(1))
+            .unwrap_or(0);
+
+        writeln!(
+            s,
+            "aura={},{},{},{remaining}",
+            aura.spell,
+            aura.caster.guid(),
+            aura.stacks
+        )
+        .unwrap();
+    }
+
+    for (position, item) in c.inventory.items() {
+        let fields = format!(
+            "{},{},{},{},{}",
+            item.item.entry(),
+            item.guid.guid(),
+            item.amount,
+            item.creator.guid(),
+            item.durability
+        );
+
+        match position.item_slot() {
+            Some(slot) => writeln!(s, "item={},{fields}", slot.as_int()).unwrap(),
+            None => writeln!(s, "bag_item={},{},{fields}", position.bag, position.slot).unwrap(),
+        }
+    }
+
+    for cooldown in c.cooldowns.iter().filter(|c| c.is_persistent()) {
+        writeln!(
+            s,
+            "cooldown={},{},{},{},{}",
+            cooldown.spell,
+            cooldown.item,
+            unix_seconds(cooldown.until),
+            cooldown.category,
+            unix_seconds(cooldown.category_until)
+        )
+        .unwrap();
+    }
+
+    for binding in &c.instance_bindings {
+        let reset = binding.reset_time.map(unix_seconds).unwrap_or(0);
+
+        writeln!(
+            s,
+            "instance={},{},{reset},{}",
+            binding.map.as_int(),
+            binding.instance_id,
+            binding.leader.guid()
+        )
+        .unwrap();
+    }
+
+    s
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses a character written by [`write_character`].
+pub(super) fn read_character(contents: &str) -> Result<Character, String> {
+    let mut file = CharacterFile::default();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: missing '='", line_number + 1));
+        };
+
+        file.read_value(key, value)
+            .map_err(|e| format!("line {}: {e}", line_number + 1))?;
+    }
+
+    file.into_character()
+}
+
+#[derive(Default)]
+struct CharacterFile<'a> {
+    values: Vec<(&'a str, &'a str)>,
+    items: Vec<(ItemPosition, Item)>,
+    /// The leader is only known once the guid of the character is read.
+    instance_bindings: Vec<(InstanceBinding, Option<Guid>)>,
+    cooldowns: Vec<Cooldown>,
+    auras: Auras,
+    /// Guid and position, the rest comes from the owner.
+    corpse: Option<(Guid, Position)>,
+}
+
+impl<'a> CharacterFile<'a> {
+    fn read_value(&mut self, key: &'a str, value: &'a str) -> Result<(), String> {
+        match key {
+            "item" => {
+                let [slot, item] = split_n(value)?;
+                let slot = ItemSlot::try_from(parse::<u8>(slot)?)
+                    .map_err(|_| format!("invalid item slot '{slot}'"))?;
+
+                self.items
+                    .push((ItemPosition::slot(slot), parse_item(item)?));
+            }
+            "bag_item" => {
+                let [bag, slot, item] = split_n(value)?;
+                let position = ItemPosition::new(parse(bag)?, parse(slot)?);
+
+                self.items.push((position, parse_item(item)?));
+            }
+            "corpse" => {
+                let [guid, map, x, y, z, orientation] = split(value)?;
+
+                self.corpse = Some((
+                    Guid::new(parse(guid)?),
+                    Position {
+                        map: parse_map(map)?,
+                        x: parse(x)?,
+                        y: parse(y)?,
+                        z: parse(z)?,
+                        orientation: parse(orientation)?,
+                    },
+                ));
+            }
+            "cooldown" => {
+                let [spell, item, until, category, category_until] = split(value)?;
+
+                self.cooldowns.push(Cooldown {
+                    spell: parse(spell)?,
+                    item: parse(item)?,
+                    until: SystemTime::UNIX_EPOCH + Duration::from_secs(parse(until)?),
+                    category: parse(category)?,
+                    category_until: SystemTime::UNIX_EPOCH
+                        + Duration::from_secs(parse(category_until)?),
+                });
+            }
+            "aura" => {
+                let [spell, caster, stacks, remaining] = split(value)?;
+                let remaining = parse::<u32>(remaining)?;
+
+                // Spells can be removed from the spell table after the character was saved
+                let id = parse::<u32>(spell)?;
+                let Some(mut aura) =
+                    lookup_spell(id).and_then(|s| Aura::from_spell(s, Guid::new(0)))
+                else {
+                    warn!("Skipping unknown aura '{id}'");
+                    return Ok(());
+                };
+                aura.caster = Guid::new(parse(caster)?);
+                aura.stacks = parse(stacks)?;
+                aura.remaining = (remaining != 0).then(|| remaining as f32 / 1000.0);
+
+                self.auras.apply(aura, u8::MAX);
+            }
+            "instance" => {
+                // Bindings saved before the leader was stored belong to the character
+                let (fields, leader) = match value.rsplit_once(',') {
+                    Some((fields, leader)) if value.split(',').count() == 4 => {
+                        (fields, Some(Guid::new(parse(leader)?)))
+                    }
+                    _ => (value, None),
+                };
+                let [map, instance_id, reset] = split(fields)?;
+                let reset = parse::<u64>(reset)?;
+
+                self.instance_bindings.push((
+                    InstanceBinding {
+                        map: parse_map(map)?,
+                        instance_id: parse(instance_id)?,
+                        reset_time: (reset != 0)
+                            .then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(reset)),
+                        leader: Guid::zero(),
+                    },
+                    leader,
+                ));
+            }
+            _ => self.values.push((key, value)),
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<&'a str, String> {
+        self.get_optional(key).ok_or(format!("missing '{key}'"))
+    }
+
+    /// For keys added after the first version.
+    fn get_optional(&self, key: &str) -> Option<&'a str> {
+        self.values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn into_character(mut self) -> Result<Character, String> {
+        let version = parse::<u32>(self.get("version")?)?;
+        if version > VERSION {
+            return Err(format!("unsupported version {version}"));
+        }
+
+        let race = Race::try_from(parse::<u8>(self.get("race")?)?)
+            .map_err(|_| "invalid race".to_string())?;
+        let class = Class::try_from(parse::<u8>(self.get("class")?)?)
+            .map_err(|_| "invalid class".to_string())?;
+        let race_class =
+            RaceClass::try_from((race, class)).map_err(|_| "invalid race and class".to_string())?;
+
+        let gender = Gender::try_from(parse::<u8>(self.get("gender")?)?)
+            .map_err(|_| "invalid gender".to_string())?;
+        let gender = PlayerGender::try_from(gender).map_err(|_| "invalid gender".to_string())?;
+
+        let area = Area::try_from(parse::<u32>(self.get("area")?)?)
+            .map_err(|_| "invalid area".to_string())?;
+
+        let [x, y, z, orientation] = split(self.get("position")?)?;
+
+        let death_state = match self.get_optional("death_state") {
+            Some(s) => DeathState::from_name(s).ok_or(format!("invalid death state '{s}'"))?,
+            None => DeathState::Alive,
+        };
+        // Resurrection sickness was saved as an end time before auras existed
+        if let Some(s) = self.get_optional("resurrection_sickness") {
+            let until = SystemTime::UNIX_EPOCH + Duration::from_secs(parse(s)?);
+            let aura =
+                lookup_spell(RESURRECTION_SICKNESS).and_then(|s| Aura::from_spell(s, Guid::zero()));
+
+            if let (Ok(remaining), Some(mut aura)) = (until.duration_since(SystemTime::now()), aura)
+            {
+                aura.remaining = Some(remaining.as_secs_f32());
+                self.auras.apply(aura, 1);
+            }
+        }
+        let power = match self.get_optional("power") {
+            Some(s) => parse(s)?,
+            None => 0,
+        };
+        let money = match self.get_optional("money") {
+            Some(s) => parse(s)?,
+            None => 0,
+        };
+        // Characters saved before skills were stored only know the skills they started with
+        let skills = match self.get_optional("skills") {
+            Some(s) => s
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    let skill = parse::<u16>(s)?;
+                    Skill::try_from(skill).map_err(|_| format!("invalid skill '{skill}'"))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => race_class.starter_skills().to_vec(),
+        };
+
+        // Bags have to be equipped before anything can be put in them
+        self.items
+            .sort_by_key(|(position, _)| position.item_slot().is_none());
+
+        let mut inventory = Inventory::empty();
+        for (position, item) in std::mem::take(&mut self.items) {
+            inventory.set(position, item);
+        }
+
+        let guid = Guid::new(parse(self.get("guid")?)?);
+        let instance_bindings = std::mem::take(&mut self.instance_bindings)
+            .into_iter()
+            .map(|(binding, leader)| InstanceBinding {
+                leader: leader.unwrap_or(guid),
+                ..binding
+            })
+            .collect();
+        let corpse = self.corpse.map(|(corpse, position)| Corpse {
+            guid: corpse,
+            owner: guid,
+            race_class,
+            gender,
+            position,
+        });
+
+        let mut character = Character {
+            guid,
+            name: self.get("name")?.to_string(),
+            race_class,
+            gender,
+            skin: parse(self.get("skin")?)?,
+            face: parse(self.get("face")?)?,
+            hairstyle: parse(self.get("hairstyle")?)?,
+            haircolor: parse(self.get("haircolor")?)?,
+            facialhair: parse(self.get("facialhair")?)?,
+            level: Level::new(parse(self.get("level")?)?),
+            area,
+            map: parse_map(self.get("map")?)?,
+            info: MovementInfo {
+                flags: Default::default(),
+                timestamp: 0,
+                position: Vector3d {
+                    x: parse(x)?,
+                    y: parse(y)?,
+                    z: parse(z)?,
+                },
+                orientation: parse(orientation)?,
+                fall_time: 0.0,
+            },
+            movement_speed: parse(self.get("movement_speed")?)?,
+            health: parse(self.get("health")?)?,
+            power,
+            death_state,
+            corpse,
+            target: Guid::zero(),
+            attacking: false,
+            auto_attack_timer: 0.0,
+            combat_timer: 0.0,
+            regeneration_timer: 0.0,
+            last_mana_use: None,
+            casting: None,
+            global_cooldown: 0.0,
+            cooldowns: std::mem::take(&mut self.cooldowns),
+            auras: std::mem::take(&mut self.auras),
+            inventory,
+            money,
+            buyback: vec![],
+            instance_bindings,
+            skills,
+            dirty: DirtyFields::default(),
+        };
+        // Also grants proficiencies that were added after the character was saved
+        character.learn_level_skills();
+        character.dirty.clear();
+
+        Ok(character)
+    }
+}
+
+pub(super) fn split<const N: usize>(value: &str) -> Result<[&str; N], String> {
+    let values: Vec<&str> = value.split(',').map(|a| a.trim()).collect();
+
+    values
+        .try_into()
+        .map_err(|_| format!("expected {N} values in '{value}'"))
+}
+
+/// Like [`split`], but the last value keeps the remaining commas.
+fn split_n<const N: usize>(value: &str) -> Result<[&str; N], String> {
+    let values: Vec<&str> = value.splitn(N, ',').map(|a| a.trim()).collect();
+
+    values
+        .try_into()
+        .map_err(|_| format!("expected {N} values in '{value}'"))
+}
+
+/// Parses `entry,guid,amount,creator,durability` of an item.
+fn parse_item(value: &str) -> Result<Item, String> {
+    // Durability was added later, items without it are undamaged
+    let (fields, durability) = match value.rsplit_once(',') {
+        Some((fields, durability)) if value.split(',').count() == 5 => {
+            (fields, Some(parse::<i32>(durability)?))
+        }
+        _ => (value, None),
+    };
+
+    let [entry, guid, amount, creator] = split(fields)?;
+    let entry = parse::<u32>(entry)?;
+    let item = lookup_item(entry).ok_or(format!("unknown item '{entry}'"))?;
+
+    Ok(Item {
+        item,
+        guid: Guid::new(parse(guid)?),
+        amount: parse(amount)?,
+        creator: Guid::new(parse(creator)?),
+        durability: durability.unwrap_or(item.max_durability()),
+        dirty: DirtyFields::default(),
+    })
+}
+
+pub(super) fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value '{value}'"))
+}
+
+pub(super) fn parse_map(value: &str) -> Result<Map, String> {
+    Map::try_from(parse::<u32>(value)?).map_err(|_| format!("invalid map '{value}'"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Written before money, item durability and auras were saved.
+    const LEGACY: &str = "\
+version=1
+guid=4
+name=Dev
+race=1
+class=1
+gender=1
+skin=0
+face=0
+hairstyle=0
+haircolor=0
+facialhair=0
+level=60
+area=12
+map=0
+position=-8949.95,-132.493,83.5312,0
+movement_speed=7
+health=100
+item=15,25,5,1,0
+item=23,159,6,5,0
+";
+
+    #[test]
+    fn legacy_file_uses_defaults() {
+        let c = read_character(LEGACY).unwrap();
+
+        assert_eq!(c.guid, Guid::new(4));
+        assert_eq!(c.name, "Dev");
+        assert_eq!(c.money, 0);
+        assert_eq!(c.power, 0);
+        assert_eq!(c.death_state, DeathState::Alive);
+        assert_eq!(c.auras.iter().count(), 0);
+        // Level 60 warriors have been trained in plate
+        assert!(c.skills.contains(&Skill::PlateMail));
+
+        let weapon = c.inventory.get(ItemSlot::MainHand).unwrap();
+        assert_eq!(weapon.guid, Guid::new(5));
+        assert_eq!(weapon.durability, weapon.item.max_durability());
+
+        let water = c.inventory.get_at(ItemPosition::new(255, 23)).unwrap();
+        assert_eq!(water.amount, 5);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut c = read_character(LEGACY).unwrap();
+        c.money = 12345;
+        c.power = 40;
+        c.death_state = DeathState::Ghost;
+        c.inventory
+            .get_at_mut(ItemPosition::slot(ItemSlot::MainHand))
+            .unwrap()
+            .durability = 3;
+        let aura = lookup_spell(RESURRECTION_SICKNESS)
+            .and_then(|s| Aura::from_spell(s, Guid::zero()))
+            .unwrap();
+        c.auras.apply(aura, 1);
+
+        let written = write_character(&c);
+        let read = read_character(&written).unwrap();
+
+        assert_eq!(read.money, 12345);
+        assert_eq!(read.power, 40);
+        assert_eq!(read.death_state, DeathState::Ghost);
+        assert_eq!(read.skills, c.skills);
+        assert_eq!(
+            read.inventory.get(ItemSlot::MainHand).unwrap().durability,
+            3
+        );
+        assert_eq!(
+            read.auras.iter().map(|a| a.spell).collect::<Vec<_>>(),
+            vec![RESURRECTION_SICKNESS]
+        );
+
+        assert_eq!(write_character(&read), written);
+    }
+
+    #[test]
+    fn nearly_expired_aura_stays_timed() {
+        let mut c = read_character(LEGACY).unwrap();
+        let mut aura = lookup_spell(RESURRECTION_SICKNESS)
+            .and_then(|s| Aura::from_spell(s, Guid::zero()))
+            .unwrap();
+        aura.remaining = Some(0.0001);
+        c.auras.apply(aura, 1);
+
+        let read = read_character(&write_character(&c)).unwrap();
+
+        let aura = read.auras.iter().next().unwrap();
+        assert!(aura.remaining.is_some());
+    }
+
+    #[test]
+    fn unknown_aura_is_skipped() {
+        let c = read_character(&format!("{LEGACY}aura=4294967295,0,1,1000\n")).unwrap();
+
+        assert_eq!(c.auras.iter().count(), 0);
+    }
+
+    #[test]
+    fn instance_binding_leader() {
+        let legacy = read_character(&format!("{LEGACY}instance=36,7,0\n")).unwrap();
+        assert_eq!(legacy.instance_bindings[0].leader, legacy.guid);
+
+        let c = read_character(&format!("{LEGACY}instance=36,7,0,9\n")).unwrap();
+        let binding = c.instance_bindings[0];
+        assert_eq!(binding.map, Map::Deadmines);
+        assert_eq!(binding.instance_id, 7);
+        assert_eq!(binding.leader, Guid::new(9));
+
+        let read = read_character(&write_character(&c)).unwrap();
+        assert_eq!(read.instance_bindings, c.instance_bindings);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::world::database::character_file::{parse, parse_map, split};
+use crate::world::world_opcode_handler::creature::{CreatureSpawn, CreatureTemplate};
+use std::time::Duration;
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::CreatureFamily;
+
+const DEFAULT_RESPAWN_TIME: Duration = Duration::from_secs(5 * 60);
+
+/// Creature templates and where creatures are spawned.
+#[derive(Debug, Default)]
+pub struct CreatureData {
+    pub templates: Vec<CreatureTemplate>,
+    pub spawns: Vec<CreatureSpawn>,
+}
+
+impl CreatureData {
+    pub fn template(&self, entry: u32) -> Option<&CreatureTemplate> {
+        self.templates.iter().find(|t| t.entry == entry)
+    }
+}
+
+/// Parses `key=value` lines, where `template=entry` starts a template
+/// and the keys after it belong to that template.
+///
+/// `spawn=entry,map,x,y,z,orientation,respawn_seconds` lines may be anywhere,
+/// lines starting with `#` are comments.
+pub(super) fn read_creatures(contents: &str) -> Result<CreatureData, String> {
+    let mut data = CreatureData::default();
+    let mut template: Option<TemplateFile> = None;
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {}: missing '='", line_number + 1));
+        };
+
+        let result = match key {
+            "template" => {
+                if let Some(t) = template.take() {
+                    data.templates.push(t.into_template()?);
+                }
+
+                parse(value).map(|entry| template = Some(TemplateFile::new(entry)))
+            }
+            "spawn" => parse_spawn(value).map(|spawn| data.spawns.push(spawn)),
+            _ => match &mut template {
+                Some(t) => {
+                    t.values.push((key, value));
+                    Ok(())
+                }
+                None => Err(format!("'{key}' outside of a template")),
+            },
+        };
+
+        result.map_err(|e| format!("line {}: {e}", line_number + 1))?;
+    }
+
+    if let Some(t) = template {
+        data.templates.push(t.into_template()?);
+    }
+
+    if let Some(spawn) = data
+        .spawns
+        .iter()
+        .find(|s| data.template(s.entry).is_none())
+    {
+        return Err(format!("spawn of unknown creature '{}'", spawn.entry));
+    }
+
+    Ok(data)
+}
+
+fn parse_spawn(value: &str) -> Result<CreatureSpawn, String> {
+    // Spawns without a respawn time use the default
+    let (fields, respawn_time) = match value.rsplit_once(',') {
+        Some((fields, seconds)) if value.split(',').count() == 7 => {
+            (fields, Duration::from_secs(parse(seconds)?))
+        }
+        _ => (value, DEFAULT_RESPAWN_TIME),
+    };
+
+    let [entry, map, x, y, z, orientation] = split(fields)?;
+
+    Ok(CreatureSpawn {
+        entry: parse(entry)?,
+        position: Position {
+            map: parse_map(map)?,
+            x: parse(x)?,
+            y: parse(y)?,
+            z: parse(z)?,
+            orientation: parse(orientation)?,
+        },
+        respawn_time,
+    })
+}
+
+struct TemplateFile<'a> {
+    entry: u32,
+    values: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> TemplateFile<'a> {
+    const fn new(entry: u32) -> Self {
+        Self {
+            entry,
+            values: Vec::new(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Result<&'a str, String> {
+        self.get_optional(key)
+            .ok_or(format!("creature '{}' is missing '{key}'", self.entry))
+    }
+
+    fn get_optional(&self, key: &str) -> Option<&'a str> {
+        self.values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    /// Optional numbers default to zero.
+    fn get_or_zero<T: std::str::FromStr + Default>(&self, key: &str) -> Result<T, String> {
+        match self.get_optional(key) {
+            Some(s) => parse(s),
+            None => Ok(T::default()),
+        }
+    }
+
+    fn into_template(self) -> Result<CreatureTemplate, String> {
+        // Either `level=5` or a range like `level=5,7`
+        let level = self.get("level")?;
+        let (min_level, max_level) = match level.split_once(',') {
+            Some((min, max)) => (parse(min)?, parse(max)?),
+            None => (parse(level)?, parse(level)?),
+        };
+        if min_level > max_level {
+            return Err(format!("creature '{}' has level {level}", self.entry));
+        }
+
+        let display_ids = self
+            .get("display_ids")?
+            .split(',')
+            .map(parse)
+            .collect::<Result<Vec<u16>, _>>()?;
+
+        let family = self.get_or_zero::<u32>("family")?;
+        let family = CreatureFamily::try_from(family)
+            .map_err(|_| format!("invalid creature family '{family}'"))?;
+
+        let [min_damage, max_damage] = split(self.get("damage")?)?;
+
+        Ok(CreatureTemplate {
+            entry: self.entry,
+            name: self.get("name")?.to_string(),
+            sub_name: self.get_optional("subname").unwrap_or("").to_string(),
+            min_level,
+            max_level,
+            display_ids,
+            faction_template: parse(self.get("faction")?)?,
+            npc_flags: self.get_or_zero("npc_flags")?,
+            creature_type: self.get_or_zero("type")?,
+            family,
+            rank: self.get_or_zero("rank")?,
+            health: parse(self.get("health")?)?,
+            min_damage: parse(min_damage)?,
+            max_damage: parse(max_damage)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wow_world_base::vanilla::Map;
+
+    const WOLF: &str = "\
+template=69
+name=Diseased Timber Wolf
+level=1,2
+display_ids=646
+faction=16
+family=1
+health=55
+damage=1,3
+";
+
+    #[test]
+    fn spawn_with_respawn_time() {
+        let spawn = parse_spawn("69,0,-8949.95,-132.49,83.53,0.5,90").unwrap();
+
+        assert_eq!(spawn.entry, 69);
+        assert_eq!(spawn.position.map, Map::EasternKingdoms);
+        assert_eq!(spawn.position.x, -8949.95);
+        assert_eq!(spawn.position.orientation, 0.5);
+        assert_eq!(spawn.respawn_time, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn spawn_without_respawn_time() {
+        let spawn = parse_spawn("69,1,1.0,2.0,3.0,0.5").unwrap();
+
+        assert_eq!(spawn.position.map, Map::Kalimdor);
+        assert_eq!(spawn.position.orientation, 0.5);
+        assert_eq!(spawn.respawn_time, DEFAULT_RESPAWN_TIME);
+    }
+
+    #[test]
+    fn spawn_with_wrong_field_count() {
+        assert!(parse_spawn("69,0,1.0,2.0,3.0").is_err());
+        assert!(parse_spawn("69,0,1.0,2.0,3.0,0.5,90,1").is_err());
+    }
+
+    #[test]
+    fn level_range() {
+        let data = read_creatures(WOLF).unwrap();
+
+        let wolf = data.template(69).unwrap();
+        assert_eq!(wolf.name, "Diseased Timber Wolf");
+        assert_eq!((wolf.min_level, wolf.max_level), (1, 2));
+        assert_eq!(wolf.sub_name, "");
+        assert_eq!(wolf.npc_flags, 0);
+    }
+
+    #[test]
+    fn single_level() {
+        let data = read_creatures(&WOLF.replace("level=1,2", "level=5")).unwrap();
+
+        let wolf = data.template(69).unwrap();
+        assert_eq!((wolf.min_level, wolf.max_level), (5, 5));
+    }
+
+    #[test]
+    fn reversed_level_range() {
+        assert!(read_creatures(&WOLF.replace("level=1,2", "level=3,2")).is_err());
+    }
+
+    #[test]
+    fn spawns_anywhere_in_the_file() {
+        let contents = format!(
+            "# Wolves\nspawn=69,0,1.0,2.0,3.0,0.0\n\n{WOLF}spawn=69,0,4.0,5.0,6.0,0.0,30\n"
+        );
+
+        let data = read_creatures(&contents).unwrap();
+
+        assert_eq!(data.templates.len(), 1);
+        assert_eq!(data.spawns.len(), 2);
+    }
+
+    #[test]
+    fn spawn_of_unknown_entry() {
+        let contents = format!("{WOLF}spawn=70,0,1.0,2.0,3.0,0.0\n");
+
+        assert!(read_creatures(&contents).is_err());
+    }
+
+    #[test]
+    fn key_outside_of_template() {
+        assert!(read_creatures("name=Wolf\n").is_err());
+    }
+
+    #[test]
+    fn shipped_file_loads() {
+        read_creatures(include_str!("../../../creatures.txt")).unwrap();
+    }
+}
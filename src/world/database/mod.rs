@@ -1,20 +1,104 @@
+mod character_file;
+mod creature_file;
+mod storage;
+
+use crate::config::config;
+use crate::world::database::creature_file::{read_creatures, CreatureData};
+use crate::world::database::storage::{LoadedCharacters, Storage, StorageWriter};
+use crate::world::world::instances::CONTINENT_INSTANCE_ID;
 use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::creature::{CreatureSpawn, CreatureTemplate};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use wow_world_base::vanilla::{PlayerGender, RaceClass};
 use wow_world_messages::Guid;
 
+/// Handle to the character storage and the creature data.
+///
+/// Cloning is cheap and every clone refers to the same storage,
+/// which allows each map to be ticked on its own task.
 #[derive(Debug, Clone)]
 pub struct WorldDatabase {
+    inner: Arc<Mutex<DatabaseInner>>,
+    /// Never changes after loading, so it does not need the lock.
+    creatures: Arc<CreatureData>,
+}
+
+#[derive(Debug)]
+struct DatabaseInner {
     characters_for_all_accounts: Vec<Character>,
     next_guid: u64,
+    next_instance_id: u32,
+    /// Characters are only kept in memory when there is no storage.
+    storage: Option<StorageWriter>,
+}
+
+impl DatabaseInner {
+    fn save(&self, character: &Character) {
+        if let Some(storage) = &self.storage {
+            storage.save(character);
+        }
+    }
 }
 
 impl WorldDatabase {
     pub fn new() -> Self {
+        let storage = config().data_directory.as_ref().and_then(|directory| {
+            match Storage::new(directory.clone()) {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    error!(
+                        "Unable to use '{}' for characters: {e}",
+                        directory.display()
+                    );
+                    None
+                }
+            }
+        });
+
+        // Starting without the characters would save new ones over them
+        let loaded = match storage.as_ref().map(|s| s.load_all()) {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(e)) => {
+                error!("Unable to load characters: {e}");
+                std::process::exit(1);
+            }
+            None => LoadedCharacters::default(),
+        };
+        let characters = loaded.characters;
+        info!("Loaded {} characters", characters.len());
+
+        let next_guid = characters
+            .iter()
+            .map(highest_guid)
+            .chain(loaded.highest_file_guid)
+            .max()
+            .map(|g| g + 1)
+            .unwrap_or(0);
+        // Zero is the instance id of the continents
+        let next_instance_id = characters
+            .iter()
+            .flat_map(|c| c.instance_bindings.iter().map(|b| b.instance_id))
+            .max()
+            .unwrap_or(CONTINENT_INSTANCE_ID)
+            + 1;
+        // Test characters are only created in a directory without any character files
+        let empty = characters.is_empty() && loaded.highest_file_guid.is_none();
+
         let mut db = Self {
-            characters_for_all_accounts: vec![],
-            next_guid: 0,
+            inner: Arc::new(Mutex::new(DatabaseInner {
+                characters_for_all_accounts: characters,
+                next_guid,
+                next_instance_id,
+                storage: storage.map(StorageWriter::spawn),
+            })),
+            creatures: Arc::new(load_creatures(&config().creature_file)),
         };
 
+        if !empty {
+            return db;
+        }
+
         let c = Character::test_character(
             &mut db,
             "Dev",
@@ -41,21 +125,47 @@ impl WorldDatabase {
     }
 
     pub fn get_characters_for_account(&self, _account_name: &str) -> Vec<Character> {
-        self.characters_for_all_accounts.clone()
+        self.inner
+            .lock()
+            .unwrap()
+            .characters_for_all_accounts
+            .clone()
     }
 
     pub fn create_character_in_account(&mut self, _account_name: &str, character: Character) {
-        self.characters_for_all_accounts.push(character);
+        let mut inner = self.inner.lock().unwrap();
+        inner.save(&character);
+        inner.characters_for_all_accounts.push(character);
+    }
+
+    pub fn creature_template(&self, entry: u32) -> Option<&CreatureTemplate> {
+        self.creatures.template(entry)
+    }
+
+    pub fn creature_spawns(&self) -> &[CreatureSpawn] {
+        &self.creatures.spawns
     }
 
     pub fn new_guid(&mut self) -> u64 {
-        let g = self.next_guid;
-        self.next_guid += 1;
+        let mut inner = self.inner.lock().unwrap();
+        let g = inner.next_guid;
+        inner.next_guid += 1;
         g
     }
 
+    /// Instance ids are saved with the characters bound to them, so they are never reused.
+    pub fn new_instance_id(&mut self) -> u32 {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_instance_id;
+        inner.next_instance_id += 1;
+        id
+    }
+
     pub fn get_character_by_guid(&self, guid: Guid) -> Character {
-        self.characters_for_all_accounts
+        self.inner
+            .lock()
+            .unwrap()
+            .characters_for_all_accounts
             .iter()
             .find(|a| a.guid == guid)
             .unwrap()
@@ -64,7 +174,10 @@ impl WorldDatabase {
 
     pub fn replace_character_data(&mut self, c: Character) {
         let guid = c.guid;
-        *self
+        let mut inner = self.inner.lock().unwrap();
+        inner.save(&c);
+
+        *inner
             .characters_for_all_accounts
             .iter_mut()
             .find(|a| a.guid == guid)
@@ -72,13 +185,85 @@ impl WorldDatabase {
     }
 
     pub fn delete_character_by_guid(&mut self, _username: &str, guid: Guid) {
-        let index = self
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner
             .characters_for_all_accounts
             .iter()
             .enumerate()
             .find(|a| a.1.guid == guid)
             .unwrap()
             .0;
-        self.characters_for_all_accounts.remove(index);
+        inner.characters_for_all_accounts.remove(index);
+
+        if let Some(storage) = &inner.storage {
+            storage.delete(guid);
+        }
+    }
+
+    /// Completes once every earlier save and delete has reached the disk.
+    pub async fn flush(&self) {
+        // The lock can not be held across the await
+        let storage = self.inner.lock().unwrap().storage.clone();
+
+        if let Some(storage) = storage {
+            storage.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+impl WorldDatabase {
+    /// Without characters, creatures or storage.
+    pub(crate) fn in_memory() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(DatabaseInner {
+                characters_for_all_accounts: vec![],
+                next_guid: 0,
+                next_instance_id: CONTINENT_INSTANCE_ID + 1,
+                storage: None,
+            })),
+            creatures: Arc::new(CreatureData::default()),
+        }
     }
 }
+
+/// Every guid handed out by [`WorldDatabase::new_guid`] that is stored on the character.
+fn highest_guid(c: &Character) -> u64 {
+    let items = c.inventory.items().map(|(_, i)| i.guid.guid());
+    let corpse = c.corpse.map(|corpse| corpse.guid.guid());
+
+    items
+        .chain(corpse)
+        .chain(std::iter::once(c.guid.guid()))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Continues without creatures if there is no file, but refuses to start with a broken one.
+///
+/// Exits the process, since the world is started on a task of its own.
+fn load_creatures(path: &Path) -> CreatureData {
+    let creatures = match std::fs::read_to_string(path) {
+        Ok(contents) => read_creatures(&contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("No creatures loaded, '{}' does not exist", path.display());
+            return CreatureData::default();
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let creatures = match creatures {
+        Ok(creatures) => creatures,
+        Err(e) => {
+            error!("Unable to load creatures from '{}': {e}", path.display());
+            std::process::exit(1);
+        }
+    };
+
+    info!(
+        "Loaded {} creature templates and {} spawns",
+        creatures.templates.len(),
+        creatures.spawns.len()
+    );
+    creatures
+}
@@ -0,0 +1,204 @@
+use crate::world::database::character_file::{read_character, write_character};
+use crate::world::world_opcode_handler::character::Character;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use tokio::sync::oneshot;
+use wow_world_messages::Guid;
+
+const EXTENSION: &str = "character";
+const TEMPORARY_EXTENSION: &str = "tmp";
+/// Characters that can not be read are moved aside so they are never saved over.
+const CORRUPT_EXTENSION: &str = "corrupt";
+
+/// The characters that could be read and the highest guid in the name of any character file.
+#[derive(Debug, Default)]
+pub(super) struct LoadedCharacters {
+    pub characters: Vec<Character>,
+    /// Includes files that could not be read, since their guids must not be handed out again.
+    pub highest_file_guid: Option<u64>,
+}
+
+/// One file per character in a directory.
+///
+/// Characters are written to a temporary file which is synced and then renamed over the old file,
+/// so a crash during a save leaves either the old or the new character, never a mix.
+#[derive(Debug)]
+pub(super) struct Storage {
+    directory: PathBuf,
+}
+
+impl Storage {
+    pub(super) fn new(directory: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    fn path(&self, guid: Guid, extension: &str) -> PathBuf {
+        self.directory.join(format!("{}.{extension}", guid.guid()))
+    }
+
+    /// Loads every character, moving the ones that can not be read to a `.corrupt` file.
+    ///
+    /// Leftover temporary files are from saves that never finished and are removed.
+    pub(super) fn load_all(&self) -> std::io::Result<LoadedCharacters> {
+        let mut loaded = LoadedCharacters::default();
+
+        for entry in std::fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+
+            let extension = path.extension().and_then(|e| e.to_str());
+            if matches!(extension, Some(EXTENSION | CORRUPT_EXTENSION)) {
+                let guid = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u64>().ok());
+                loaded.highest_file_guid = loaded.highest_file_guid.max(guid);
+            }
+
+            match extension {
+                Some(EXTENSION) => match load(&path) {
+                    Ok(c) => loaded.characters.push(c),
+                    Err(e) => {
+                        let corrupt = path.with_extension(CORRUPT_EXTENSION);
+                        error!(
+                            "Unable to load '{}', moving it to '{}': {e}",
+                            path.display(),
+                            corrupt.display()
+                        );
+                        std::fs::rename(&path, &corrupt)?;
+                    }
+                },
+                Some(TEMPORARY_EXTENSION) => {
+                    warn!("Removing unfinished save '{}'", path.display());
+                    std::fs::remove_file(&path)?;
+                }
+                _ => {}
+            }
+        }
+
+        sync_directory(&self.directory)?;
+
+        Ok(loaded)
+    }
+
+    fn save(&self, guid: Guid, contents: &str) -> std::io::Result<()> {
+        let temporary = self.path(guid, TEMPORARY_EXTENSION);
+
+        let mut f = File::create(&temporary)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+        drop(f);
+
+        std::fs::rename(&temporary, self.path(guid, EXTENSION))?;
+        sync_directory(&self.directory)
+    }
+
+    fn delete(&self, guid: Guid) -> std::io::Result<()> {
+        std::fs::remove_file(self.path(guid, EXTENSION))?;
+        sync_directory(&self.directory)
+    }
+
+    fn run(self, requests: mpsc::Receiver<Request>) {
+        for request in requests {
+            match request {
+                Request::Save {
+                    guid,
+                    name,
+                    contents,
+                } => {
+                    if let Err(e) = self.save(guid, &contents) {
+                        error!("Unable to save '{name}' ({guid}): {e}");
+                    }
+                }
+                Request::Delete(guid) => {
+                    if let Err(e) = self.delete(guid) {
+                        error!("Unable to delete character {guid}: {e}");
+                    }
+                }
+                Request::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Request {
+    Save {
+        guid: Guid,
+        name: String,
+        contents: String,
+    },
+    Delete(Guid),
+    /// Answered once every earlier request is done.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Hands characters to a thread that owns the [`Storage`],
+/// so the world never waits for the disk.
+///
+/// Requests are handled in the order they are sent.
+#[derive(Debug, Clone)]
+pub(super) struct StorageWriter {
+    requests: mpsc::Sender<Request>,
+}
+
+impl StorageWriter {
+    pub(super) fn spawn(storage: Storage) -> Self {
+        let (requests, receiver) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("storage".to_string())
+            .spawn(move || storage.run(receiver))
+            .unwrap();
+
+        Self { requests }
+    }
+
+    /// Only the serialization happens on the calling thread.
+    pub(super) fn save(&self, character: &Character) {
+        self.send(Request::Save {
+            guid: character.guid,
+            name: character.name.clone(),
+            contents: write_character(character),
+        });
+    }
+
+    pub(super) fn delete(&self, guid: Guid) {
+        self.send(Request::Delete(guid));
+    }
+
+    /// Completes once everything sent before has been written.
+    pub(super) async fn flush(&self) {
+        let (done, receiver) = oneshot::channel();
+        self.send(Request::Flush(done));
+
+        let _ = receiver.await;
+    }
+
+    fn send(&self, request: Request) {
+        if self.requests.send(request).is_err() {
+            error!("The storage thread has stopped, characters are no longer saved");
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Character, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    read_character(&contents)
+}
+
+/// Makes the rename itself durable.
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> std::io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> std::io::Result<()> {
+    Ok(())
+}
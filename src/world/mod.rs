@@ -3,7 +3,11 @@ mod database;
 mod world;
 pub mod world_opcode_handler;
 
+pub(crate) use world::tick_metrics;
+
+use crate::shutdown;
 use crate::world::database::WorldDatabase;
+use crate::world::tick_metrics::TICK_METRICS;
 use crate::world::world::World;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -23,13 +27,18 @@ pub async fn world(users: Arc<Mutex<HashMap<String, SrpServer>>>) {
     let listener = TcpListener::bind("0.0.0.0:8085").await.unwrap();
     let (world, clients_waiting_to_join) = mpsc::channel(32);
 
-    tokio::spawn(run_world(clients_waiting_to_join));
+    let world_handle = tokio::spawn(run_world(clients_waiting_to_join));
 
     loop {
-        let (stream, _) = listener.accept().await.unwrap();
+        let (stream, _) = tokio::select! {
+            r = listener.accept() => r.unwrap(),
+            _ = shutdown::stopped() => break,
+        };
 
         tokio::spawn(character_screen(stream, users.clone(), world.clone()));
     }
+
+    world_handle.await.unwrap();
 }
 
 pub const DESIRED_TIMESTEP: f32 = 1.0 / 10.0;
@@ -41,11 +50,18 @@ async fn run_world(clients_waiting_to_join: mpsc::Receiver<CharacterScreenClient
     loop {
         let before = Instant::now();
 
-        world.tick(&mut db).await;
+        if shutdown::deadline().is_some_and(|deadline| deadline <= before) {
+            world.shutdown(&mut db).await;
+            shutdown::stop();
+            return;
+        }
+
+        let profile = world.tick(&mut db, before).await;
 
         let after = Instant::now();
 
         let tick_duration = after.duration_since(before);
+        TICK_METRICS.lock().unwrap().record(tick_duration, &profile);
 
         if tick_duration.as_secs_f32() < DESIRED_TIMESTEP {
             sleep(Duration::from_secs_f32(
@@ -53,7 +69,10 @@ async fn run_world(clients_waiting_to_join: mpsc::Receiver<CharacterScreenClient
             ))
             .await;
         } else {
-            println!("Timestep took too long: '{}'", tick_duration.as_secs_f32());
+            warn!(
+                "Timestep took too long: '{}' ({profile})",
+                tick_duration.as_secs_f32()
+            );
         }
     }
 }
@@ -1,6 +1,9 @@
-use crate::world::world::client::Client;
+use crate::logging::Session;
+use crate::metrics::Counted;
+use crate::world::world::client::{Client, TeleportStatus};
 use crate::world::world_opcode_handler::character::Character;
-use tokio::io::AsyncReadExt;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
@@ -22,32 +25,48 @@ pub enum CharacterScreenProgress {
 pub struct CharacterScreenClient {
     pub status: CharacterScreenProgress,
     pub(super) received_messages: Receiver<ClientOpcodeMessage>,
-    pub(super) write: OwnedWriteHalf,
+    pub(super) write: Counted<OwnedWriteHalf>,
     pub(super) encrypter: EncrypterHalf,
     pub(super) account_name: String,
+    pub(super) session: Session,
     pub reader_handle: JoinHandle<()>,
 }
 
 impl CharacterScreenClient {
     pub fn into_client(self, character: Character) -> Client {
+        let session = Session {
+            character: Some(character.guid),
+            ..self.session
+        };
+
         Client {
             character,
-            in_process_of_teleport: false,
+            teleport_status: TeleportStatus::None,
+            teleport_counter: 0,
+            pending_objects: vec![],
+            reset_instances_requested: false,
+            last_accepted_movement: Instant::now(),
             received_messages: self.received_messages,
             write: self.write,
             encrypter: self.encrypter,
             account_name: self.account_name,
+            session,
             reader_handle: self.reader_handle,
         }
     }
 
     pub fn new(account_name: String, stream: TcpStream, encryption: HeaderCrypto) -> Self {
+        let session = Session::new(Some(account_name.clone()), stream.peer_addr().ok());
+
         let (read, write) = stream.into_split();
+        let (read, write) = (Counted::new(read), Counted::new(write));
         let (encrypter, decrypter) = encryption.split();
 
         let (client_send, client_recv) = mpsc::channel(32);
 
+        let reader_session = session.clone();
         let reader_handle = tokio::spawn(async move {
+            let session = reader_session;
             let mut read = read;
             let mut decrypter = decrypter;
             loop {
@@ -60,10 +79,13 @@ impl CharacterScreenClient {
                             ExpectedOpcodeError::Opcode { opcode, size, name } => {
                                 let mut v = vec![0_u8; size as usize];
                                 read.read_exact(&mut v).await.unwrap();
-                                dbg!(name, opcode, size, v);
+                                warn!(
+                                    session: &session,
+                                    "Unhandled opcode {name} ({opcode:#06x}) of size {size}: {v:?}"
+                                );
                             }
                             ExpectedOpcodeError::Parse(ref p) => {
-                                println!("{:#?}", p);
+                                warn!(session: &session, "Unable to parse message: {p:?}");
                             }
                             ExpectedOpcodeError::Io(_) => {
                                 break;
@@ -83,6 +105,7 @@ impl CharacterScreenClient {
             write,
             encrypter,
             account_name,
+            session,
             reader_handle,
         }
     }
@@ -91,6 +114,16 @@ impl CharacterScreenClient {
         &self.account_name
     }
 
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Closes the connection.
+    pub async fn disconnect(&mut self) {
+        let _ = self.write.shutdown().await;
+        self.reader_handle.abort();
+    }
+
     pub fn received_messages(&mut self) -> &mut Receiver<ClientOpcodeMessage> {
         &mut self.received_messages
     }
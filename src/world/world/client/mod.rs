@@ -1,8 +1,13 @@
 pub(crate) mod character_screen_client;
 
+use crate::logging::Session;
+use crate::metrics::Counted;
+use crate::world::world::object_update_message;
 use crate::world::world_opcode_handler::character::Character;
 use crate::world::world_opcode_handler::{write_server_test, write_test_case_inner};
 use character_screen_client::{CharacterScreenClient, CharacterScreenProgress};
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::OwnedWriteHalf;
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
@@ -11,19 +16,36 @@ use wow_world_base::geometry::distance_between;
 use wow_world_base::vanilla::position::Position;
 use wow_world_messages::vanilla::opcodes::{ClientOpcodeMessage, ServerOpcodeMessage};
 use wow_world_messages::vanilla::{
-    Language, MovementInfo, PlayerChatTag, SMSG_MESSAGECHAT_ChatType, ServerMessage, Vector3d,
-    SMSG_MESSAGECHAT,
+    Language, MovementInfo, Object, PlayerChatTag, SMSG_MESSAGECHAT_ChatType, ServerMessage,
+    Vector3d, SMSG_MESSAGECHAT,
 };
 use wow_world_messages::Guid;
 
+/// Progress of a teleport initiated by the server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TeleportStatus {
+    None,
+    /// Waiting for `MSG_MOVE_TELEPORT_ACK` with the same counter.
+    SameMap {
+        movement_counter: u32,
+    },
+    /// Waiting for `MSG_MOVE_WORLDPORT_ACK`.
+    OtherMap,
+}
+
 #[derive(Debug)]
 pub struct Client {
     character: Character,
-    pub in_process_of_teleport: bool,
+    pub teleport_status: TeleportStatus,
+    teleport_counter: u32,
+    pending_objects: Vec<Object>,
+    pub reset_instances_requested: bool,
+    pub last_accepted_movement: Instant,
     received_messages: Receiver<ClientOpcodeMessage>,
-    write: OwnedWriteHalf,
+    write: Counted<OwnedWriteHalf>,
     encrypter: EncrypterHalf,
     account_name: String,
+    session: Session,
     pub reader_handle: JoinHandle<()>,
 }
 
@@ -35,10 +57,18 @@ impl Client {
             write: self.write,
             encrypter: self.encrypter,
             account_name: self.account_name,
+            session: Session {
+                character: None,
+                ..self.session
+            },
             reader_handle: self.reader_handle,
         }
     }
 
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
     pub fn character(&self) -> &Character {
         &self.character
     }
@@ -47,10 +77,26 @@ impl Client {
         &mut self.character
     }
 
+    pub fn is_teleporting(&self) -> bool {
+        self.teleport_status != TeleportStatus::None
+    }
+
+    pub fn next_teleport_counter(&mut self) -> u32 {
+        self.teleport_counter = self.teleport_counter.wrapping_add(1);
+        self.teleport_counter
+    }
+
     pub fn set_movement_info(&mut self, info: MovementInfo) {
         self.character.info = info;
     }
 
+    /// Closes the connection after everything queued has been sent.
+    pub async fn disconnect(&mut self) {
+        self.flush_objects().await;
+        let _ = self.write.shutdown().await;
+        self.reader_handle.abort();
+    }
+
     pub fn received_messages(&mut self) -> &mut Receiver<ClientOpcodeMessage> {
         &mut self.received_messages
     }
@@ -65,6 +111,22 @@ impl Client {
             .unwrap();
     }
 
+    /// Queues an object update to be sent together with the rest of the tick's updates.
+    pub fn queue_object(&mut self, object: Object) {
+        self.pending_objects.push(object);
+    }
+
+    /// Sends every queued object update as a single, possibly compressed, message.
+    pub async fn flush_objects(&mut self) {
+        if self.pending_objects.is_empty() {
+            return;
+        }
+
+        let objects = std::mem::take(&mut self.pending_objects);
+        let m = object_update_message(objects);
+        self.send_opcode(&m).await;
+    }
+
     pub async fn send_opcode(&mut self, m: &ServerOpcodeMessage) {
         write_server_test(m);
 
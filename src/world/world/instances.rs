@@ -0,0 +1,230 @@
+use crate::world::world_opcode_handler::character::Character;
+use std::time::{Duration, SystemTime};
+use wow_world_base::vanilla::Map;
+use wow_world_messages::vanilla::{RaidInfo, SMSG_RAID_INSTANCE_INFO};
+use wow_world_messages::Guid;
+
+/// Instance id used by every map that is not instanced.
+pub const CONTINENT_INSTANCE_ID: u32 = 0;
+
+/// Time an instance without any players inside is kept in memory.
+pub const INSTANCE_UNLOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Point in time all raid reset schedules are aligned to, a Tuesday at 11:00 UTC.
+const RAID_RESET_EPOCH: u64 = 1704193200;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InstanceKind {
+    Dungeon,
+    Raid { reset_period: Duration },
+}
+
+impl InstanceKind {
+    pub fn for_map(map: Map) -> Option<Self> {
+        let raid = |days: u64| {
+            Some(Self::Raid {
+                reset_period: Duration::from_secs(days * DAY),
+            })
+        };
+
+        match map {
+            Map::ShadowfangKeep
+            | Map::StormwindStockade
+            | Map::Deadmines
+            | Map::WailingCaverns
+            | Map::RazorfenKraul
+            | Map::BlackfathomDeeps
+            | Map::Uldaman
+            | Map::Gnomeregan
+            | Map::SunkenTemple
+            | Map::RazorfenDowns
+            | Map::ScarletMonastery
+            | Map::ZulFarrak
+            | Map::BlackrockSpire
+            | Map::BlackrockDepths
+            | Map::Scholomance
+            | Map::Stratholme
+            | Map::Mauradon
+            | Map::RagefireChasm
+            | Map::DireMaul => Some(Self::Dungeon),
+            Map::ZulGurub | Map::RuinsOfAhnQiraj => raid(3),
+            Map::OnyxiasLair => raid(5),
+            Map::MoltenCore | Map::BlackwingLair | Map::AhnQirajTemple | Map::Naxxramas => raid(7),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies a single copy of a map.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct InstanceKey {
+    pub map: Map,
+    pub instance_id: u32,
+}
+
+impl InstanceKey {
+    pub const fn new(map: Map, instance_id: u32) -> Self {
+        Self { map, instance_id }
+    }
+}
+
+/// The guid that instances are created for, shared by everybody in a group.
+///
+/// Groups are not implemented yet, so every character leads a group of one.
+pub fn group_leader(character: &Character) -> Guid {
+    character.guid
+}
+
+/// Saved binding of a character to a specific instance.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct InstanceBinding {
+    pub map: Map,
+    pub instance_id: u32,
+    /// Only raids have a fixed reset, dungeons reset when unloaded or manually reset.
+    pub reset_time: Option<SystemTime>,
+    /// The [`group_leader`] the instance was created for.
+    pub leader: Guid,
+}
+
+impl InstanceBinding {
+    pub fn new(map: Map, instance_id: u32, leader: Guid, now: SystemTime) -> Self {
+        let reset_time = match InstanceKind::for_map(map) {
+            Some(InstanceKind::Raid { reset_period }) => Some(next_reset(now, reset_period)),
+            _ => None,
+        };
+
+        Self {
+            map,
+            instance_id,
+            reset_time,
+            leader,
+        }
+    }
+
+    pub fn key(&self) -> InstanceKey {
+        InstanceKey::new(self.map, self.instance_id)
+    }
+
+    pub fn is_raid(&self) -> bool {
+        self.reset_time.is_some()
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.reset_time.map(|t| t <= now).unwrap_or(false)
+    }
+
+    pub fn to_raid_info(&self, now: SystemTime) -> Option<RaidInfo> {
+        let reset_time = self.reset_time?;
+
+        Some(RaidInfo {
+            map: self.map,
+            reset_time: reset_time.duration_since(now).unwrap_or_default().as_secs() as u32,
+            instance_id: self.instance_id,
+        })
+    }
+}
+
+fn next_reset(now: SystemTime, reset_period: Duration) -> SystemTime {
+    let now = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let period = reset_period.as_secs();
+
+    let periods_passed = now.saturating_sub(RAID_RESET_EPOCH) / period;
+    let reset = RAID_RESET_EPOCH + (periods_passed + 1) * period;
+
+    SystemTime::UNIX_EPOCH + Duration::from_secs(reset)
+}
+
+pub fn raid_instance_info(bindings: &[InstanceBinding]) -> SMSG_RAID_INSTANCE_INFO {
+    let now = SystemTime::now();
+
+    SMSG_RAID_INSTANCE_INFO {
+        raid_infos: bindings
+            .iter()
+            .filter(|b| !b.is_expired(now))
+            .filter_map(|b| b.to_raid_info(now))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn continents_are_not_instanced() {
+        assert_eq!(InstanceKind::for_map(Map::EasternKingdoms), None);
+        assert_eq!(InstanceKind::for_map(Map::Kalimdor), None);
+    }
+
+    #[test]
+    fn instance_kinds() {
+        let raid = |days: u64| {
+            Some(InstanceKind::Raid {
+                reset_period: Duration::from_secs(days * DAY),
+            })
+        };
+
+        assert_eq!(
+            InstanceKind::for_map(Map::Deadmines),
+            Some(InstanceKind::Dungeon)
+        );
+        assert_eq!(InstanceKind::for_map(Map::ZulGurub), raid(3));
+        assert_eq!(InstanceKind::for_map(Map::OnyxiasLair), raid(5));
+        assert_eq!(InstanceKind::for_map(Map::MoltenCore), raid(7));
+    }
+
+    #[test]
+    fn reset_is_the_next_period_boundary() {
+        let week = Duration::from_secs(7 * DAY);
+        let first = RAID_RESET_EPOCH + 7 * DAY;
+
+        assert_eq!(next_reset(at(RAID_RESET_EPOCH), week), at(first));
+        assert_eq!(next_reset(at(RAID_RESET_EPOCH + 1), week), at(first));
+        assert_eq!(next_reset(at(first - 1), week), at(first));
+        assert_eq!(next_reset(at(first), week), at(first + 7 * DAY));
+    }
+
+    #[test]
+    fn reset_periods_share_the_epoch() {
+        let three_days = Duration::from_secs(3 * DAY);
+        let now = RAID_RESET_EPOCH + 10 * DAY;
+
+        assert_eq!(
+            next_reset(at(now), three_days),
+            at(RAID_RESET_EPOCH + 12 * DAY)
+        );
+    }
+
+    #[test]
+    fn reset_before_the_epoch() {
+        let week = Duration::from_secs(7 * DAY);
+
+        assert_eq!(next_reset(at(0), week), at(RAID_RESET_EPOCH + 7 * DAY));
+    }
+
+    #[test]
+    fn only_raids_have_a_reset_time() {
+        let now = at(RAID_RESET_EPOCH + DAY);
+
+        let leader = Guid::new(1);
+
+        assert_eq!(
+            InstanceBinding::new(Map::Deadmines, 1, leader, now).reset_time,
+            None
+        );
+
+        let binding = InstanceBinding::new(Map::MoltenCore, 2, leader, now);
+        assert_eq!(binding.reset_time, Some(at(RAID_RESET_EPOCH + 7 * DAY)));
+        assert!(!binding.is_expired(now));
+        assert!(binding.is_expired(at(RAID_RESET_EPOCH + 7 * DAY)));
+    }
+}
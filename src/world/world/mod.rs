@@ -1,47 +1,63 @@
+use crate::config::config;
+use crate::metrics::{MapCounts, METRICS};
+use crate::shutdown;
 use crate::world::character_screen_handler::handle_character_screen_opcodes;
 use crate::world::database::WorldDatabase;
-use crate::world::world::client::Client;
+use crate::world::world::client::{Client, TeleportStatus};
+use crate::world::world::instances::{
+    group_leader, raid_instance_info, InstanceBinding, InstanceKey, InstanceKind,
+    CONTINENT_INSTANCE_ID,
+};
 use crate::world::world::pathfinding_maps::PathfindingMaps;
-use crate::world::world_opcode_handler;
+use crate::world::world::tick_metrics::{TickPhase, TickProfile};
+use crate::world::world::world_map::{Departure, WorldMap};
 use crate::world::world_opcode_handler::character::Character;
-use crate::world::world_opcode_handler::creature::Creature;
-use crate::world::world_opcode_handler::entities::Entities;
+use crate::world::world_opcode_handler::update_fields::{
+    set_auras, set_max_power, set_power, set_skills, set_stats,
+};
 use client::character_screen_client::{CharacterScreenClient, CharacterScreenProgress};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
+use std::time::{Instant, SystemTime};
 use tokio::sync::mpsc::Receiver;
-use wow_world_base::combat::UNARMED_SPEED;
+use tokio::task::JoinSet;
 use wow_world_base::movement::{
     DEFAULT_RUNNING_BACKWARDS_SPEED, DEFAULT_TURN_SPEED, DEFAULT_WALKING_SPEED,
 };
 use wow_world_base::vanilla::position::Position;
-use wow_world_base::vanilla::HitInfo;
+use wow_world_base::vanilla::Map;
 use wow_world_messages::vanilla::opcodes::ServerOpcodeMessage;
 use wow_world_messages::vanilla::UpdateMask;
 use wow_world_messages::vanilla::{
-    DamageInfo, InitialSpell, Language, MSG_MOVE_TELEPORT_ACK_Server, MovementBlock,
+    InitialSpell, InstanceResetFailedReason, Language, MSG_MOVE_TELEPORT_ACK_Server, MovementBlock,
     MovementBlock_MovementFlags, MovementBlock_UpdateFlag, MovementBlock_UpdateFlag_Living,
     MovementInfo, MovementInfo_MovementFlags, Object, ObjectType, Object_UpdateType, PlayerChatTag,
-    SMSG_MESSAGECHAT_ChatType, SkillInfo, SkillInfoIndex, UpdatePlayerBuilder, Vector3d,
-    VisibleItem, VisibleItemIndex, SMSG_ACCOUNT_DATA_TIMES, SMSG_ATTACKERSTATEUPDATE,
-    SMSG_DESTROY_OBJECT, SMSG_INITIAL_SPELLS, SMSG_LOGIN_SETTIMESPEED, SMSG_LOGIN_VERIFY_WORLD,
-    SMSG_MESSAGECHAT, SMSG_NEW_WORLD, SMSG_TRANSFER_PENDING, SMSG_TUTORIAL_FLAGS,
+    SMSG_MESSAGECHAT_ChatType, ServerMessage, ServerMessageType, UpdatePlayerBuilder, Vector3d,
+    VisibleItem, VisibleItemIndex, SMSG_ACCOUNT_DATA_TIMES, SMSG_COMPRESSED_UPDATE_OBJECT,
+    SMSG_INITIAL_SPELLS, SMSG_INSTANCE_RESET, SMSG_INSTANCE_RESET_FAILED,
+    SMSG_INSTANCE_SAVE_CREATED, SMSG_LOGIN_SETTIMESPEED, SMSG_LOGIN_VERIFY_WORLD, SMSG_MESSAGECHAT,
+    SMSG_NEW_WORLD, SMSG_SERVER_MESSAGE, SMSG_TRANSFER_PENDING, SMSG_TUTORIAL_FLAGS,
     SMSG_UPDATE_OBJECT,
 };
 use wow_world_messages::{DateTime, Guid};
 
 pub mod client;
+pub mod instances;
 pub mod pathfinding_maps;
+pub mod tick_metrics;
+pub mod world_map;
 
 #[derive(Debug)]
 pub struct World {
-    clients: Vec<Client>,
+    maps: HashMap<InstanceKey, WorldMap>,
     clients_on_character_screen: Vec<CharacterScreenClient>,
     clients_waiting_to_join: Receiver<CharacterScreenClient>,
 
-    creatures: Vec<Creature>,
+    pathfinding: PathfindingMaps,
 
-    maps: PathfindingMaps,
+    /// Scheduled shutdown and the seconds left in the last warning sent for it.
+    shutdown_announcement: Option<(Instant, u64)>,
+    last_autosave: Instant,
 }
 
 impl World {
@@ -49,26 +65,121 @@ impl World {
         clients_waiting_to_join: Receiver<CharacterScreenClient>,
         db: &mut WorldDatabase,
     ) -> Self {
-        let maps = PathfindingMaps::new();
+        let mut pathfinding = PathfindingMaps::new();
+
+        // Instanced maps are populated when an instance is created
+        let mut continents: Vec<Map> = pathfinding.maps().collect();
+        for spawn in db.creature_spawns() {
+            let map = spawn.position.map;
+            if InstanceKind::for_map(map).is_none() && !continents.contains(&map) {
+                continents.push(map);
+            }
+        }
+
+        let mut maps = HashMap::new();
+        for map in continents {
+            let key = InstanceKey::new(map, CONTINENT_INSTANCE_ID);
+            maps.insert(key, WorldMap::new(key, pathfinding.take(&map), db));
+        }
 
         Self {
-            clients: vec![],
+            maps,
             clients_on_character_screen: vec![],
             clients_waiting_to_join,
-            creatures: vec![Creature::new("Thing", db.new_guid().into())],
-            maps,
+            pathfinding,
+            shutdown_announcement: None,
+            last_autosave: Instant::now(),
         }
     }
 
-    pub async fn tick(&mut self, db: &mut WorldDatabase) {
+    /// Returns the map, creating it if nobody has been on it yet.
+    ///
+    /// Pathfinding data is owned by a single instance of a map at a time,
+    /// since namigator maps are not safe to query from several map tasks at once.
+    fn map_mut(&mut self, key: InstanceKey, db: &mut WorldDatabase) -> &mut WorldMap {
+        if !self.maps.contains_key(&key) {
+            let pathfinding = self.pathfinding.take(&key.map);
+
+            let owner = self
+                .maps
+                .values()
+                .find(|m| m.key().map == key.map && m.has_pathfinding());
+            if let (None, Some(owner)) = (&pathfinding, owner) {
+                warn!(
+                    "Instance {} of {} has no pathfinding, instance {} is using it",
+                    key.instance_id,
+                    key.map,
+                    owner.key().instance_id
+                );
+            }
+
+            self.maps.insert(key, WorldMap::new(key, pathfinding, db));
+        }
+
+        self.maps.get_mut(&key).unwrap()
+    }
+
+    /// Finds the instance the group of the client should be placed in for its current map.
+    async fn enter_instance(&mut self, client: &mut Client, db: &mut WorldDatabase) -> InstanceKey {
+        let map = client.character().map;
+        let Some(kind) = InstanceKind::for_map(map) else {
+            return InstanceKey::new(map, CONTINENT_INSTANCE_ID);
+        };
+
+        let now = SystemTime::now();
+        let leader = group_leader(client.character());
+        client
+            .character_mut()
+            .instance_bindings
+            .retain(|b| !b.is_expired(now));
+
+        if let Some(binding) = client
+            .character()
+            .instance_bindings
+            .iter()
+            .find(|b| b.map == map && b.leader == leader)
+        {
+            let key = binding.key();
+
+            // Dungeons do not keep their progress after being unloaded
+            if matches!(kind, InstanceKind::Raid { .. }) || self.maps.contains_key(&key) {
+                return key;
+            }
+        }
+
+        client
+            .character_mut()
+            .instance_bindings
+            .retain(|b| b.map != map);
+
+        let binding = InstanceBinding::new(map, db.new_instance_id(), leader, now);
+        client.character_mut().instance_bindings.push(binding);
+
+        if binding.is_raid() {
+            client
+                .send_message(SMSG_INSTANCE_SAVE_CREATED { unknown: 0 })
+                .await;
+        }
+
+        binding.key()
+    }
+
+    pub async fn tick(&mut self, db: &mut WorldDatabase, now: Instant) -> TickProfile {
+        let mut profile = TickProfile::default();
+
         while let Ok(c) = self.clients_waiting_to_join.try_recv() {
             self.clients_on_character_screen.push(c);
         }
 
+        self.announce_shutdown().await;
+
+        let start = Instant::now();
         for client in &mut self.clients_on_character_screen {
-            handle_character_screen_opcodes(client, db).await;
+            handle_character_screen_opcodes(client, db, &mut profile).await;
         }
+        profile.add(TickPhase::CharacterScreenOpcodes, start.elapsed());
 
+        let start = Instant::now();
         while let Some(i) = self
             .clients_on_character_screen
             .iter()
@@ -81,99 +192,305 @@ impl World {
             };
             let mut c = c.into_client(character);
 
-            for client in &mut self.clients {
-                announce_character_login(client, c.character()).await;
+            let key = self.enter_instance(&mut c, db).await;
+            self.map_mut(key, db).join(c).await;
+        }
+        profile.add(TickPhase::Join, start.elapsed());
+
+        let departures = self.tick_maps(db, now, &mut profile).await;
+
+        for departure in departures {
+            match departure {
+                Departure::CharacterScreen(c) => {
+                    let start = Instant::now();
+                    let c = c.into_character_screen_client();
+                    self.clients_on_character_screen.push(c);
+                    profile.add(TickPhase::MoveToCharacterScreen, start.elapsed());
+                }
+                Departure::Teleport(mut c) => {
+                    let start = Instant::now();
+                    let key = self.enter_instance(&mut c, db).await;
+                    db.replace_character_data(c.character().clone());
+                    self.map_mut(key, db).receive_teleported_client(c);
+                    profile.add(TickPhase::Join, start.elapsed());
+                }
+                Departure::Disconnect(c) => {
+                    info!(session: c.session(), "{} disconnected", c.character().name);
+                    db.replace_character_data(c.character().clone());
+                }
             }
+        }
+
+        self.handle_instance_resets().await;
+        self.unload_empty_instances();
+        self.autosave(db);
 
-            for client in &mut self.clients {
-                announce_character_login(&mut c, client.character()).await;
+        while let Some((i, _)) = self
+            .clients_on_character_screen
+            .iter()
+            .enumerate()
+            .find(|(_, a)| a.reader_handle.is_finished())
+        {
+            self.clients_on_character_screen.remove(i);
+        }
+
+        self.record_metrics();
+
+        profile
+    }
+
+    /// Warns everybody in the world about a scheduled or cancelled shutdown.
+    async fn announce_shutdown(&mut self) {
+        let Some(deadline) = shutdown::deadline() else {
+            if self.shutdown_announcement.take().is_some() {
+                self.send_to_world(SMSG_SERVER_MESSAGE {
+                    message_type: ServerMessageType::ShutdownCancelled,
+                    message: String::new(),
+                })
+                .await;
             }
+            return;
+        };
 
-            for creature in &self.creatures {
-                c.send_message(creature.to_message()).await;
+        let remaining = deadline
+            .saturating_duration_since(Instant::now())
+            .as_secs_f32()
+            .ceil() as u64;
+
+        let should_send = match self.shutdown_announcement {
+            Some((d, seconds)) if d == deadline => {
+                seconds != remaining && shutdown::should_announce(remaining)
             }
+            // Newly scheduled, always warn once
+            _ => remaining != 0,
+        };
 
-            self.clients.push(c);
-        }
+        if should_send {
+            self.shutdown_announcement = Some((deadline, remaining));
 
-        let mut indices_to_move_to_character_screen = BTreeSet::new();
-        let mut move_to_character_screen = false;
-
-        for i in 0..self.clients.len() {
-            let mut client = self.clients.remove(i);
-            let mut entities = Entities::new(&mut self.clients, &mut self.creatures);
-            world_opcode_handler::handle_received_client_opcodes(
-                &mut client,
-                &mut entities,
-                db,
-                &mut move_to_character_screen,
-                &mut self.maps,
-            )
+            self.send_to_world(SMSG_SERVER_MESSAGE {
+                message_type: ServerMessageType::ShutdownTime,
+                message: shutdown::format_remaining(remaining),
+            })
             .await;
-            client.character_mut().update_auto_attack_timer();
-
-            if client.character().attacking && client.character().auto_attack_timer <= 0.0 {
-                client.character_mut().auto_attack_timer = UNARMED_SPEED;
-                let msg = SMSG_ATTACKERSTATEUPDATE {
-                    hit_info: HitInfo::CriticalHit,
-                    attacker: client.character().guid,
-                    target: client.character().target,
-                    total_damage: 1332,
-                    damages: vec![DamageInfo {
-                        spell_school_mask: 0,
-                        damage_float: 1332.0,
-                        damage_uint: 1332,
-                        absorb: 0,
-                        resist: 0,
-                    }],
-                    unknown1: 0,
-                    spell_id: 0,
-                    damage_state: 0,
-                    blocked_amount: 0,
-                };
+        }
+    }
 
-                client.send_message(msg.clone()).await;
+    async fn send_to_world(&mut self, message: SMSG_SERVER_MESSAGE) {
+        for map in self.maps.values_mut() {
+            for client in map.clients_mut() {
+                client.send_message(message.clone()).await;
+            }
+        }
+    }
 
-                for c in &mut self.clients {
-                    c.send_message(msg.clone()).await;
-                }
+    fn autosave(&mut self, db: &mut WorldDatabase) {
+        let Some(interval) = config().autosave_interval else {
+            return;
+        };
+
+        if self.last_autosave.elapsed() < interval {
+            return;
+        }
+        self.last_autosave = Instant::now();
+
+        let saved = self.save_all_characters(db);
+        debug!("Autosaved {saved} characters");
+    }
+
+    fn save_all_characters(&self, db: &mut WorldDatabase) -> usize {
+        let mut saved = 0;
+
+        for map in self.maps.values() {
+            for client in map.clients() {
+                db.replace_character_data(client.character().clone());
+                saved += 1;
             }
+        }
 
-            if move_to_character_screen {
-                indices_to_move_to_character_screen.insert(i);
+        saved
+    }
+
+    /// Saves every character in the world and closes every connection.
+    pub async fn shutdown(&mut self, db: &mut WorldDatabase) {
+        let saved = self.save_all_characters(db);
+        db.flush().await;
+
+        for map in self.maps.values_mut() {
+            for client in map.clients_mut() {
+                client.disconnect().await;
             }
+        }
 
-            self.clients.insert(i, client);
+        for client in &mut self.clients_on_character_screen {
+            client.disconnect().await;
         }
 
-        for i in indices_to_move_to_character_screen.iter().rev() {
-            let c = self.clients.remove(*i);
-            for a in &mut self.clients {
-                a.send_message(SMSG_DESTROY_OBJECT {
-                    guid: c.character().guid,
-                })
-                .await;
-            }
+        info!("Shut down after saving {saved} characters");
+    }
 
-            let c = c.into_character_screen_client();
-            self.clients_on_character_screen.push(c);
+    fn record_metrics(&self) {
+        let mut maps: BTreeMap<String, MapCounts> = BTreeMap::new();
+
+        for (key, map) in &self.maps {
+            let counts = maps.entry(key.map.to_string()).or_default();
+            counts.instances += 1;
+            counts.clients += map.client_count() as u64;
+            counts.creatures += map.creature_count() as u64;
         }
 
-        while let Some((i, _)) = self
-            .clients_on_character_screen
+        METRICS.set_world(self.clients_on_character_screen.len(), maps);
+    }
+
+    fn unload_empty_instances(&mut self) {
+        let now = Instant::now();
+
+        let keys: Vec<InstanceKey> = self
+            .maps
             .iter()
-            .enumerate()
-            .find(|(_, a)| a.reader_handle.is_finished())
-        {
-            self.clients_on_character_screen.remove(i);
+            .filter(|(key, map)| key.instance_id != CONTINENT_INSTANCE_ID && map.should_unload(now))
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in keys {
+            self.unload_instance(key);
+        }
+    }
+
+    fn unload_instance(&mut self, key: InstanceKey) {
+        if let Some(map) = self.maps.remove(&key) {
+            if let Some(pathfinding) = map.into_pathfinding() {
+                self.pathfinding.insert(key.map, pathfinding);
+            }
+        }
+    }
+
+    /// Resets every dungeon the requesting characters are bound to, unless somebody is inside.
+    async fn handle_instance_resets(&mut self) {
+        let mut requests = Vec::new();
+
+        for (key, map) in &mut self.maps {
+            for client in map.clients_mut() {
+                if client.reset_instances_requested {
+                    client.reset_instances_requested = false;
+                    requests.push((*key, client.character().guid));
+                }
+            }
+        }
+
+        for (key, guid) in requests {
+            let Some(bindings) = self
+                .maps
+                .get_mut(&key)
+                .and_then(|m| m.find_client_mut(guid))
+                .map(|c| c.character().instance_bindings.clone())
+            else {
+                continue;
+            };
+
+            let mut results = Vec::new();
+            for binding in bindings.iter().filter(|b| !b.is_raid()) {
+                let occupied = self
+                    .maps
+                    .get(&binding.key())
+                    .map(|m| m.has_players())
+                    .unwrap_or(false);
+
+                if occupied {
+                    results.push(Err(binding.map));
+                } else {
+                    self.unload_instance(binding.key());
+                    results.push(Ok(binding.map));
+                }
+            }
+
+            let Some(client) = self
+                .maps
+                .get_mut(&key)
+                .and_then(|m| m.find_client_mut(guid))
+            else {
+                continue;
+            };
+
+            for result in results {
+                match result {
+                    Ok(map) => {
+                        client
+                            .character_mut()
+                            .instance_bindings
+                            .retain(|b| b.map != map);
+
+                        client.send_message(SMSG_INSTANCE_RESET { map }).await;
+                    }
+                    Err(map) => {
+                        client
+                            .send_message(SMSG_INSTANCE_RESET_FAILED {
+                                reason: InstanceResetFailedReason::General,
+                                map,
+                            })
+                            .await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ticks every map in parallel on the runtime thread pool.
+    ///
+    /// Clients leaving their map are returned so that they can be handed over
+    /// once every map has finished ticking.
+    async fn tick_maps(
+        &mut self,
+        db: &WorldDatabase,
+        now: Instant,
+        profile: &mut TickProfile,
+    ) -> Vec<Departure> {
+        let mut tasks = JoinSet::new();
+        // A task that panics does not say which map it had
+        let mut ticking: Vec<InstanceKey> = self.maps.keys().copied().collect();
+
+        for (_, mut map) in self.maps.drain() {
+            let mut db = db.clone();
+
+            tasks.spawn(async move {
+                let (departures, profile) = map.tick(&mut db, now).await;
+                (map, departures, profile)
+            });
+        }
+
+        let mut departures = Vec::new();
+
+        while let Some(result) = tasks.join_next().await {
+            let (map, d, p) = match result {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Map tick failed: {e}");
+                    continue;
+                }
+            };
+
+            departures.extend(d);
+            profile.merge(p);
+            ticking.retain(|key| *key != map.key());
+            self.maps.insert(map.key(), map);
+        }
+
+        // The other maps keep running without the failed ones
+        for key in ticking {
+            error!(
+                "Dropped {} instance {} and its clients after its tick failed",
+                key.map, key.instance_id
+            );
         }
+
+        departures
     }
 }
 
-pub fn get_self_update_object_create_object2(character: &Character) -> SMSG_UPDATE_OBJECT {
-    let mut m = get_update_object_create_object2(character);
+pub fn get_self_create_object(character: &Character) -> Object {
+    let mut m = get_create_object(character);
 
-    match &mut m.objects[0].update_type {
+    match &mut m.update_type {
         Object_UpdateType::CreateObject2 { movement2, .. } => {
             movement2.update_flag = movement2.update_flag.clone().set_self();
         }
@@ -183,33 +500,30 @@ pub fn get_self_update_object_create_object2(character: &Character) -> SMSG_UPDA
     m
 }
 
-pub fn get_update_object_create_object2(character: &Character) -> SMSG_UPDATE_OBJECT {
-    SMSG_UPDATE_OBJECT {
-        has_transport: 0,
-        objects: vec![Object {
-            update_type: Object_UpdateType::CreateObject2 {
-                guid3: character.guid,
-                mask2: get_update_object_player(character),
-                movement2: MovementBlock {
-                    update_flag: MovementBlock_UpdateFlag::new_living(
-                        MovementBlock_UpdateFlag_Living::Living {
-                            backwards_running_speed: DEFAULT_RUNNING_BACKWARDS_SPEED,
-                            backwards_swimming_speed: 0.0,
-                            fall_time: 0.0,
-                            flags: MovementBlock_MovementFlags::empty(),
-                            living_orientation: character.info.orientation,
-                            living_position: character.info.position,
-                            running_speed: character.movement_speed,
-                            swimming_speed: 0.0,
-                            timestamp: 0,
-                            turn_rate: DEFAULT_TURN_SPEED,
-                            walking_speed: DEFAULT_WALKING_SPEED,
-                        },
-                    ),
-                },
-                object_type: ObjectType::Player,
+pub fn get_create_object(character: &Character) -> Object {
+    Object {
+        update_type: Object_UpdateType::CreateObject2 {
+            guid3: character.guid,
+            mask2: get_update_object_player(character),
+            movement2: MovementBlock {
+                update_flag: MovementBlock_UpdateFlag::new_living(
+                    MovementBlock_UpdateFlag_Living::Living {
+                        backwards_running_speed: DEFAULT_RUNNING_BACKWARDS_SPEED,
+                        backwards_swimming_speed: 0.0,
+                        fall_time: 0.0,
+                        flags: MovementBlock_MovementFlags::empty(),
+                        living_orientation: character.info.orientation,
+                        living_position: character.info.position,
+                        running_speed: character.run_speed(),
+                        swimming_speed: 0.0,
+                        timestamp: 0,
+                        turn_rate: DEFAULT_TURN_SPEED,
+                        walking_speed: DEFAULT_WALKING_SPEED,
+                    },
+                ),
             },
-        }],
+            object_type: ObjectType::Player,
+        },
     }
 }
 
@@ -236,20 +550,21 @@ fn get_update_object_player(character: &Character) -> UpdateMask {
             character.hairstyle,
             character.haircolor,
         )
-        .set_unit_base_health(character.base_health())
-        .set_unit_health(character.max_health())
+        .set_unit_health(character.health)
         .set_unit_maxhealth(character.max_health())
+        .set_player_flags(character.player_flags())
+        .set_player_field_coinage(character.money as i32)
         .set_unit_level(character.level.as_int() as i32)
-        .set_unit_agility(character.agility())
-        .set_unit_strength(character.strength())
-        .set_unit_stamina(character.stamina())
-        .set_unit_intellect(character.intellect())
-        .set_unit_spirit(character.spirit())
         .set_unit_factiontemplate(character.race_class.race().faction_id().as_int() as i32)
         .set_unit_displayid(character.race_class.race().display_id(character.gender))
         .set_unit_nativedisplayid(character.race_class.race().display_id(character.gender))
         .set_unit_target(character.target);
 
+    mask = set_stats(mask, character);
+    mask = set_power(mask, character.power_type(), character.power);
+    mask = set_max_power(mask, character.power_type(), character.max_power());
+    mask = set_auras(mask, &character.auras);
+
     for (i, (item, slot)) in character.inventory.all_slots().iter().enumerate() {
         if let Some(item) = item {
             if let Ok(index) = VisibleItemIndex::try_from(i) {
@@ -266,20 +581,34 @@ fn get_update_object_player(character: &Character) -> UpdateMask {
         }
     }
 
-    for (i, skill) in character.race_class.starter_skills().iter().enumerate() {
-        mask = mask.set_player_skill_info(
-            SkillInfo::new(*skill, 0, 295, 300, 0, 2),
-            SkillInfoIndex::try_from(i as u32).unwrap(),
-        );
-    }
+    mask = set_skills(mask, &character.skills);
 
     UpdateMask::Player(mask.finalize())
 }
 
-pub async fn announce_character_login(client: &mut Client, character: &Character) {
-    let m = get_update_object_create_object2(character);
+pub fn announce_character_login(client: &mut Client, character: &Character) {
+    client.queue_object(get_create_object(character));
+}
+
+/// Batches with a payload larger than this are sent as `SMSG_COMPRESSED_UPDATE_OBJECT`.
+const COMPRESSION_THRESHOLD: usize = 100;
+
+/// Creates an object update message, compressed if the payload is large enough to benefit.
+pub fn object_update_message(objects: Vec<Object>) -> ServerOpcodeMessage {
+    let m = SMSG_UPDATE_OBJECT {
+        has_transport: 0,
+        objects,
+    };
 
-    client.send_message(m).await;
+    if m.size_without_header() as usize > COMPRESSION_THRESHOLD {
+        SMSG_COMPRESSED_UPDATE_OBJECT {
+            has_transport: m.has_transport,
+            objects: m.objects,
+        }
+        .into()
+    } else {
+        m.into()
+    }
 }
 
 pub fn get_client_login_messages(character: &Character) -> Vec<ServerOpcodeMessage> {
@@ -347,75 +676,123 @@ pub fn get_client_login_messages(character: &Character) -> Vec<ServerOpcodeMessa
                     unknown1: 0,
                 })
                 .collect(),
-            cooldowns: vec![],
+            cooldowns: character
+                .cooldowns
+                .iter()
+                .map(|c| c.to_cooldown_spell())
+                .collect(),
         }
         .into(),
     );
 
-    let objects = character
-        .inventory
-        .all_slots()
-        .iter()
-        .filter_map(|(item, _)| item.map(|item| item.to_create_item_object(character.guid)))
-        .collect();
+    v.push(raid_instance_info(&character.instance_bindings).into());
 
-    v.push(
-        SMSG_UPDATE_OBJECT {
-            has_transport: 0,
-            objects,
-        }
-        .into(),
-    );
+    let mut objects = character.inventory.to_create_item_objects(character.guid);
+    objects.push(get_self_create_object(character));
 
-    v.push(get_self_update_object_create_object2(character).into());
+    v.push(object_update_message(objects));
 
     v
 }
 
 pub async fn prepare_teleport(p: Position, client: &mut Client) {
+    let info = MovementInfo {
+        flags: MovementInfo_MovementFlags::empty(),
+        timestamp: 0,
+        position: Vector3d {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+        },
+        orientation: p.orientation,
+        fall_time: 0.0,
+    };
+
     if p.map == client.character().map {
-        client
-            .send_message(MSG_MOVE_TELEPORT_ACK_Server {
-                guid: client.character().guid,
-                movement_counter: 0,
-                info: MovementInfo {
-                    flags: MovementInfo_MovementFlags::empty(),
-                    timestamp: 0,
-                    position: Vector3d {
-                        x: p.x,
-                        y: p.y,
-                        z: p.z,
-                    },
-                    orientation: p.orientation,
-                    fall_time: 0.0,
-                },
-            })
-            .await;
-    } else {
-        client
-            .send_message(SMSG_TRANSFER_PENDING {
-                map: p.map,
-                has_transport: None,
-            })
-            .await;
+        teleport_within_map(client, info).await;
+        return;
+    }
 
-        client
-            .send_message(SMSG_NEW_WORLD {
-                map: p.map,
-                position: Vector3d {
-                    x: p.x,
-                    y: p.y,
-                    z: p.z,
+    client
+        .send_message(SMSG_TRANSFER_PENDING {
+            map: p.map,
+            has_transport: None,
+        })
+        .await;
+
+    client
+        .send_message(SMSG_NEW_WORLD {
+            map: p.map,
+            position: info.position,
+            orientation: p.orientation,
+        })
+        .await;
+
+    client.set_movement_info(info);
+    client.character_mut().map = p.map;
+    client.teleport_status = TeleportStatus::OtherMap;
+}
+
+/// Moves the client to a new position on the same map.
+///
+/// Movement from the client is ignored until it acknowledges the teleport.
+pub async fn teleport_within_map(client: &mut Client, info: MovementInfo) {
+    let movement_counter = client.next_teleport_counter();
+    client.teleport_status = TeleportStatus::SameMap { movement_counter };
+
+    client
+        .send_message(MSG_MOVE_TELEPORT_ACK_Server {
+            guid: client.character().guid,
+            movement_counter,
+            info: info.clone(),
+        })
+        .await;
+
+    client.set_movement_info(info);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn out_of_range_objects(amount: u64) -> Vec<Object> {
+        (1..=amount)
+            .map(|guid| Object {
+                update_type: Object_UpdateType::OutOfRangeObjects {
+                    guids: vec![Guid::new(guid)],
                 },
-                orientation: p.orientation,
             })
-            .await;
+            .collect()
     }
 
-    client.character_mut().info.position.x = p.x;
-    client.character_mut().info.position.y = p.y;
-    client.character_mut().info.position.z = p.z;
-    client.character_mut().info.orientation = p.orientation;
-    client.character_mut().map = p.map;
-    client.in_process_of_teleport = true;
+    fn write_and_read(m: &ServerOpcodeMessage) -> ServerOpcodeMessage {
+        let mut buf = Vec::new();
+        m.write_unencrypted_server(&mut buf).unwrap();
+
+        ServerOpcodeMessage::read_unencrypted(&mut buf.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn large_batch_is_compressed() {
+        let objects = out_of_range_objects(50);
+
+        let m = write_and_read(&object_update_message(objects.clone()));
+
+        let ServerOpcodeMessage::SMSG_COMPRESSED_UPDATE_OBJECT(m) = m else {
+            panic!("expected SMSG_COMPRESSED_UPDATE_OBJECT, got {m:?}");
+        };
+        assert_eq!(m.objects, objects);
+    }
+
+    #[test]
+    fn small_batch_is_not_compressed() {
+        let objects = out_of_range_objects(1);
+
+        let m = write_and_read(&object_update_message(objects.clone()));
+
+        let ServerOpcodeMessage::SMSG_UPDATE_OBJECT(m) = m else {
+            panic!("expected SMSG_UPDATE_OBJECT, got {m:?}");
+        };
+        assert_eq!(m.objects, objects);
+    }
 }
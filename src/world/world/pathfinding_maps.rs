@@ -11,7 +11,7 @@ impl PathfindingMaps {
     pub fn new() -> Self {
         let maps = if let Some(data_path) = std::option_env!("WOW_VANILLA_USE_MAPS") {
             let output = std::env::temp_dir().join("wow_vanilla_server");
-            println!("Building and using maps for pathfind from data directory '{data_path}' and outputting to '{}'. This may take a while.", output.to_str().unwrap());
+            info!("Building and using maps for pathfind from data directory '{data_path}' and outputting to '{}'. This may take a while.", output.to_str().unwrap());
             let mut m = HashMap::new();
 
             let threads = {
@@ -25,39 +25,49 @@ impl PathfindingMaps {
             };
 
             if !bvh_files_exist(&output).unwrap() {
-                println!("Building gameobjects.");
+                info!("Building gameobjects.");
                 build_bvh(data_path, &output, threads).unwrap();
-                println!("Gameobjects built.");
+                info!("Gameobjects built.");
             } else {
-                println!("Gameobjects already built.");
+                info!("Gameobjects already built.");
             }
 
             const MAP: Map = Map::DevelopmentLand;
 
             if !map_files_exist(&output, MAP.directory_name()).unwrap() {
-                println!("Building map {MAP} ({})", MAP.directory_name());
+                info!("Building map {MAP} ({})", MAP.directory_name());
                 build_map(data_path, &output, MAP.directory_name(), "", threads).unwrap();
-                println!("Finished building {MAP} ({})", MAP.directory_name());
+                info!("Finished building {MAP} ({})", MAP.directory_name());
             } else {
-                println!("{MAP} ({}) already built.", MAP.directory_name());
+                info!("{MAP} ({}) already built.", MAP.directory_name());
             }
 
             let mut v =
                 VanillaMap::build_gameobjects_and_map(data_path, &output, MAP, threads).unwrap();
             v.load_all_adts().unwrap();
             m.insert(MAP, v);
-            println!("Finished setting up maps");
+            info!("Finished setting up maps");
 
             m
         } else {
-            println!("Not using maps for pathfind.");
+            info!("Not using maps for pathfind.");
             HashMap::new()
         };
 
         Self { maps }
     }
 
-    pub fn get(&self, map: &Map) -> Option<&VanillaMap> {
-        self.maps.get(&map)
+    /// Hands over the pathfinding data for `map` to the map that owns it.
+    pub fn take(&mut self, map: &Map) -> Option<VanillaMap> {
+        self.maps.remove(map)
+    }
+
+    /// Returns pathfinding data from a map that has been unloaded.
+    pub fn insert(&mut self, map: Map, pathfinding: VanillaMap) {
+        self.maps.insert(map, pathfinding);
+    }
+
+    pub fn maps(&self) -> impl Iterator<Item = Map> + '_ {
+        self.maps.keys().copied()
     }
 }
@@ -0,0 +1,246 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Timing of every tick since startup, shared with GM commands and the metrics endpoint.
+pub static TICK_METRICS: Mutex<TickMetrics> = Mutex::new(TickMetrics::new());
+
+/// Named part of [`World::tick`](crate::world::world::World::tick).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TickPhase {
+    Join,
+    CharacterScreenOpcodes,
+    /// Summed over all maps, which are ticked in parallel.
+    WorldOpcodes,
+    /// Summed over all maps, which are ticked in parallel.
+    Combat,
+    MoveToCharacterScreen,
+}
+
+impl TickPhase {
+    pub const ALL: [TickPhase; 5] = [
+        TickPhase::Join,
+        TickPhase::CharacterScreenOpcodes,
+        TickPhase::WorldOpcodes,
+        TickPhase::Combat,
+        TickPhase::MoveToCharacterScreen,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TickPhase::Join => "join",
+            TickPhase::CharacterScreenOpcodes => "character_screen_opcodes",
+            TickPhase::WorldOpcodes => "world_opcodes",
+            TickPhase::Combat => "combat",
+            TickPhase::MoveToCharacterScreen => "move_to_character_screen",
+        }
+    }
+}
+
+/// The single most expensive opcode handled during a tick.
+#[derive(Debug, Clone)]
+pub struct SlowestOpcode {
+    pub client: String,
+    pub opcode: &'static str,
+    pub duration: Duration,
+}
+
+/// Timings collected during a single tick.
+#[derive(Debug, Default, Clone)]
+pub struct TickProfile {
+    phases: [Duration; TickPhase::ALL.len()],
+    slowest_opcode: Option<SlowestOpcode>,
+}
+
+impl TickProfile {
+    pub fn add(&mut self, phase: TickPhase, duration: Duration) {
+        self.phases[phase as usize] += duration;
+    }
+
+    pub fn phase(&self, phase: TickPhase) -> Duration {
+        self.phases[phase as usize]
+    }
+
+    pub fn record_opcode(&mut self, client: &str, opcode: &'static str, duration: Duration) {
+        if self
+            .slowest_opcode
+            .as_ref()
+            .map(|s| s.duration < duration)
+            .unwrap_or(true)
+        {
+            self.slowest_opcode = Some(SlowestOpcode {
+                client: client.to_string(),
+                opcode,
+                duration,
+            });
+        }
+    }
+
+    pub fn merge(&mut self, other: TickProfile) {
+        for phase in TickPhase::ALL {
+            self.add(phase, other.phase(phase));
+        }
+
+        if let Some(s) = other.slowest_opcode {
+            self.record_opcode(&s.client, s.opcode, s.duration);
+        }
+    }
+}
+
+impl Display for TickProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, phase) in TickPhase::ALL.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {:?}", phase.name(), self.phase(*phase))?;
+        }
+
+        if let Some(s) = &self.slowest_opcode {
+            write!(
+                f,
+                ", slowest opcode {} from '{}' took {:?}",
+                s.opcode, s.client, s.duration
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Upper bounds of the histogram buckets in microseconds.
+const BUCKET_BOUNDS_MICROS: [u64; 12] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 1_000_000,
+];
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    /// The last bucket counts everything above the largest bound.
+    counts: [u64; BUCKET_BOUNDS_MICROS.len() + 1],
+    sum: Duration,
+    max: Duration,
+    count: u64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            counts: [0; BUCKET_BOUNDS_MICROS.len() + 1],
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+            count: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        let bucket = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|bound| micros <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MICROS.len());
+
+        self.counts[bucket] += 1;
+        self.sum += duration;
+        self.max = self.max.max(duration);
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            self.sum / self.count as u32
+        }
+    }
+
+    /// Cumulative counts for every bucket bound in seconds, excluding the overflow bucket.
+    pub fn cumulative_buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        BUCKET_BOUNDS_MICROS
+            .iter()
+            .zip(self.counts.iter())
+            .scan(0, |total, (bound, count)| {
+                *total += count;
+                Some((*bound as f64 / 1_000_000.0, *total))
+            })
+    }
+
+    /// Upper bound of the bucket containing the given quantile.
+    pub fn quantile_upper_bound(&self, quantile: f64) -> Option<Duration> {
+        let target = (self.count as f64 * quantile).ceil() as u64;
+
+        self.cumulative_buckets()
+            .find(|(_, total)| *total >= target)
+            .map(|(bound, _)| Duration::from_secs_f64(bound))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TickMetrics {
+    total: Histogram,
+    phases: [Histogram; TickPhase::ALL.len()],
+}
+
+impl TickMetrics {
+    pub const fn new() -> Self {
+        Self {
+            total: Histogram::new(),
+            phases: [
+                Histogram::new(),
+                Histogram::new(),
+                Histogram::new(),
+                Histogram::new(),
+                Histogram::new(),
+            ],
+        }
+    }
+
+    pub fn record(&mut self, total: Duration, profile: &TickProfile) {
+        self.total.record(total);
+
+        for phase in TickPhase::ALL {
+            self.phases[phase as usize].record(profile.phase(phase));
+        }
+    }
+
+    pub fn total(&self) -> &Histogram {
+        &self.total
+    }
+
+    pub fn phase(&self, phase: TickPhase) -> &Histogram {
+        &self.phases[phase as usize]
+    }
+
+    /// Human readable summary used by the `.tickstats` GM command.
+    pub fn summary(&self) -> String {
+        let line = |name: &str, h: &Histogram| {
+            let p99 = h
+                .quantile_upper_bound(0.99)
+                .map(|d| format!("<= {d:?}"))
+                .unwrap_or_else(|| "> 1s".to_string());
+
+            format!("{name}: mean {:?}, p99 {p99}, max {:?}", h.mean(), h.max())
+        };
+
+        let mut s = format!("{} ticks\n", self.total.count());
+        s.push_str(&line("total", &self.total));
+
+        for phase in TickPhase::ALL {
+            s.push('\n');
+            s.push_str(&line(phase.name(), self.phase(phase)));
+        }
+
+        s
+    }
+}
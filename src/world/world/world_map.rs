@@ -0,0 +1,360 @@
+use crate::world::database::WorldDatabase;
+use crate::world::world::announce_character_login;
+use crate::world::world::client::Client;
+use crate::world::world::instances::{InstanceKey, INSTANCE_UNLOAD_TIMEOUT};
+use crate::world::world::tick_metrics::{TickPhase, TickProfile};
+use crate::world::world_opcode_handler;
+use crate::world::world_opcode_handler::combat;
+use crate::world::world_opcode_handler::creature::{Creature, CreatureEvent, CreatureSpawn};
+use crate::world::world_opcode_handler::death::{self, Corpse};
+use crate::world::world_opcode_handler::entities::Entities;
+use crate::world::world_opcode_handler::inventory::ItemPosition;
+use crate::world::world_opcode_handler::regeneration;
+use crate::world::world_opcode_handler::spell;
+use crate::world::world_opcode_handler::spell::cooldown;
+use crate::world::world_opcode_handler::update_fields::{
+    container_values, item_values, player_values, unit_values, Field, Visibility,
+};
+use namigator::vanilla::VanillaMap;
+use std::time::Instant;
+use wow_world_messages::vanilla::{
+    Object, SMSG_DESTROY_OBJECT, SMSG_FORCE_RUN_SPEED_CHANGE, SMSG_SPLINE_SET_RUN_SPEED,
+};
+use wow_world_messages::Guid;
+
+/// A client that left a map during a tick.
+#[derive(Debug)]
+pub enum Departure {
+    /// Logged out and should be moved back to the character screen.
+    CharacterScreen(Client),
+    /// Teleported to another map. The character already has the new map set.
+    Teleport(Client),
+    /// Disconnected or kicked.
+    Disconnect(Client),
+}
+
+/// Container for everything that lives on a single map, or a single instance of a map.
+///
+/// Maps do not share any state with each other, so every map can be ticked independently.
+#[derive(Debug)]
+pub struct WorldMap {
+    key: InstanceKey,
+    clients: Vec<Client>,
+    creatures: Vec<Creature>,
+    corpses: Vec<Corpse>,
+    pathfinding: Option<VanillaMap>,
+    empty_since: Option<Instant>,
+}
+
+impl WorldMap {
+    /// Every instance gets its own copy of the creatures spawned on the map.
+    pub fn new(key: InstanceKey, pathfinding: Option<VanillaMap>, db: &mut WorldDatabase) -> Self {
+        let spawns: Vec<CreatureSpawn> = db
+            .creature_spawns()
+            .iter()
+            .filter(|s| s.position.map == key.map)
+            .copied()
+            .collect();
+
+        let creatures = spawns
+            .iter()
+            .filter_map(|spawn| {
+                let guid = db.new_guid().into();
+                let template = db.creature_template(spawn.entry)?;
+                Some(Creature::new(template, spawn, guid))
+            })
+            .collect();
+
+        Self {
+            key,
+            clients: vec![],
+            creatures,
+            corpses: vec![],
+            pathfinding,
+            empty_since: Some(Instant::now()),
+        }
+    }
+
+    pub fn key(&self) -> InstanceKey {
+        self.key
+    }
+
+    pub fn has_pathfinding(&self) -> bool {
+        self.pathfinding.is_some()
+    }
+
+    pub fn into_pathfinding(self) -> Option<VanillaMap> {
+        self.pathfinding
+    }
+
+    pub fn has_players(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn creature_count(&self) -> usize {
+        self.creatures.len()
+    }
+
+    /// Returns true when nobody has been inside for longer than [`INSTANCE_UNLOAD_TIMEOUT`].
+    pub fn should_unload(&self, now: Instant) -> bool {
+        self.empty_since
+            .map(|t| now.duration_since(t) > INSTANCE_UNLOAD_TIMEOUT)
+            .unwrap_or(false)
+    }
+
+    pub fn clients(&self) -> &[Client] {
+        &self.clients
+    }
+
+    pub fn clients_mut(&mut self) -> &mut [Client] {
+        &mut self.clients
+    }
+
+    pub fn find_client_mut(&mut self, guid: Guid) -> Option<&mut Client> {
+        self.clients.iter_mut().find(|c| c.character().guid == guid)
+    }
+
+    /// Adds a client that has just logged in and announces it to everybody on the map.
+    pub async fn join(&mut self, mut client: Client) {
+        for c in &mut self.clients {
+            announce_character_login(c, client.character());
+        }
+
+        for c in &mut self.clients {
+            announce_character_login(&mut client, c.character());
+        }
+
+        for creature in self.creatures.iter().filter(|c| c.is_spawned()) {
+            client.queue_object(creature.to_create_object());
+        }
+
+        let mut entities = Entities::new(&mut self.clients, &mut self.creatures, &mut self.corpses);
+        death::send_corpses(&mut client, &mut entities).await;
+
+        self.clients.push(client);
+        self.empty_since = None;
+    }
+
+    /// Adds a client arriving through a teleport.
+    ///
+    /// Announcing is postponed until the client sends `MSG_MOVE_WORLDPORT_ACK`.
+    pub fn receive_teleported_client(&mut self, client: Client) {
+        self.clients.push(client);
+        self.empty_since = None;
+    }
+
+    /// `now` is when the world tick started, every map of the tick shares it.
+    pub async fn tick(
+        &mut self,
+        db: &mut WorldDatabase,
+        now: Instant,
+    ) -> (Vec<Departure>, TickProfile) {
+        let mut departures = Vec::new();
+        let mut profile = TickProfile::default();
+
+        let mut i = 0;
+        while i < self.clients.len() {
+            let mut client = self.clients.remove(i);
+            let mut move_to_character_screen = false;
+
+            let start = Instant::now();
+            let mut entities =
+                Entities::new(&mut self.clients, &mut self.creatures, &mut self.corpses);
+            world_opcode_handler::handle_received_client_opcodes(
+                &mut client,
+                &mut entities,
+                db,
+                &mut move_to_character_screen,
+                self.pathfinding.as_ref(),
+                &mut profile,
+            )
+            .await;
+            profile.add(TickPhase::WorldOpcodes, start.elapsed());
+
+            if client.character().is_alive() && client.character().health == 0 {
+                death::die(&mut client, &mut self.clients).await;
+            }
+            client.character_mut().update_combat_timer();
+            regeneration::update_regeneration(client.character_mut());
+            cooldown::update_cooldowns(client.character_mut());
+            for aura in client.character_mut().update_auras() {
+                cooldown::aura_removed(&mut client, aura.spell).await;
+            }
+
+            let start = Instant::now();
+            client.character_mut().update_auto_attack_timer();
+
+            let mut entities =
+                Entities::new(&mut self.clients, &mut self.creatures, &mut self.corpses);
+            spell::update_spell_cast(&mut client, &mut entities, self.pathfinding.as_ref()).await;
+
+            if client.character().attacking && client.character().auto_attack_timer <= 0.0 {
+                let mut entities =
+                    Entities::new(&mut self.clients, &mut self.creatures, &mut self.corpses);
+                combat::melee_swing(&mut client, &mut entities).await;
+            }
+            profile.add(TickPhase::Combat, start.elapsed());
+
+            if client.reader_handle.is_finished() {
+                self.remove_from_observers(&client).await;
+                departures.push(Departure::Disconnect(client));
+            } else if move_to_character_screen {
+                let start = Instant::now();
+                self.remove_from_observers(&client).await;
+                departures.push(Departure::CharacterScreen(client));
+                profile.add(TickPhase::MoveToCharacterScreen, start.elapsed());
+            } else if client.character().map != self.key.map {
+                client.flush_objects().await;
+                self.remove_from_observers(&client).await;
+                departures.push(Departure::Teleport(client));
+            } else {
+                self.clients.insert(i, client);
+                i += 1;
+            }
+        }
+
+        self.update_creatures(now).await;
+
+        self.send_dirty_updates().await;
+
+        for client in &mut self.clients {
+            client.flush_objects().await;
+        }
+
+        if self.clients.is_empty() {
+            self.empty_since.get_or_insert(now);
+        } else {
+            self.empty_since = None;
+        }
+
+        (departures, profile)
+    }
+
+    /// Ticks the creatures and tells everybody about the ones that despawned or respawned.
+    async fn update_creatures(&mut self, now: Instant) {
+        let mut despawned = Vec::new();
+        let mut respawned = Vec::new();
+
+        for creature in &mut self.creatures {
+            if creature.is_alive() {
+                creature.update_auras();
+            }
+
+            match creature.update_state(now) {
+                Some(CreatureEvent::Despawned) => despawned.push(creature.guid),
+                Some(CreatureEvent::Respawned) => respawned.push(creature.guid),
+                Some(CreatureEvent::Died) | None => {}
+            }
+
+            if let Some(vendor) = &mut creature.vendor {
+                vendor.update();
+            }
+        }
+
+        for client in &mut self.clients {
+            for guid in despawned.iter().copied() {
+                client.send_message(SMSG_DESTROY_OBJECT { guid }).await;
+            }
+            for creature in self
+                .creatures
+                .iter()
+                .filter(|c| respawned.contains(&c.guid))
+            {
+                client.queue_object(creature.to_create_object());
+            }
+        }
+    }
+
+    /// Sends one values update per changed object to everybody who can see it.
+    ///
+    /// Owners receive all changed fields, while observers only receive the public ones.
+    async fn send_dirty_updates(&mut self) {
+        let mut public_objects = Vec::new();
+        let mut speed_changes = Vec::new();
+
+        for client in &mut self.clients {
+            let character = client.character_mut();
+            let guid = character.guid;
+
+            // Bag slots first, since clearing the items also clears the bags
+            let mut private_objects: Vec<Object> = character
+                .inventory
+                .equipped_bags()
+                .filter_map(|(bag, contents)| container_values(bag, contents))
+                .collect();
+            let positions: Vec<ItemPosition> =
+                character.inventory.items().map(|(p, _)| p).collect();
+            for position in positions {
+                let inventory = &mut character.inventory;
+                let contained = inventory.bag_guid(position).unwrap_or(guid);
+
+                if let Some(item) = inventory.get_at_mut(position) {
+                    private_objects.extend(item_values(item, contained));
+                    item.dirty.clear();
+                }
+            }
+
+            if let Some(object) = player_values(character, Visibility::Owner) {
+                private_objects.push(object);
+            }
+            if let Some(object) = player_values(character, Visibility::Observer) {
+                public_objects.push((guid, object));
+            }
+            if character.dirty.is_dirty(Field::RunSpeed) {
+                speed_changes.push((guid, character.run_speed()));
+            }
+            character.dirty.clear();
+
+            for object in private_objects {
+                client.queue_object(object);
+            }
+        }
+
+        for creature in &mut self.creatures {
+            if let Some(object) = unit_values(creature) {
+                public_objects.push((creature.guid, object));
+            }
+            creature.dirty.clear();
+        }
+
+        for client in &mut self.clients {
+            let own_guid = client.character().guid;
+
+            for (_, object) in public_objects.iter().filter(|(guid, _)| *guid != own_guid) {
+                client.queue_object(object.clone());
+            }
+
+            for (guid, speed) in speed_changes.iter().copied() {
+                if guid == own_guid {
+                    client
+                        .send_message(SMSG_FORCE_RUN_SPEED_CHANGE {
+                            guid,
+                            move_event: 0,
+                            speed,
+                        })
+                        .await;
+                } else {
+                    client
+                        .send_message(SMSG_SPLINE_SET_RUN_SPEED { guid, speed })
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// The corpse of the client stays on the map.
+    async fn remove_from_observers(&mut self, client: &Client) {
+        for c in &mut self.clients {
+            // A pending create for the client must not arrive after the destroy
+            c.flush_objects().await;
+            c.send_message(SMSG_DESTROY_OBJECT {
+                guid: client.character().guid,
+            })
+            .await;
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::world::world_opcode_handler::spell::data::{Spell, SpellEffect};
+use crate::world::DESIRED_TIMESTEP;
+use std::time::Duration;
+use wow_world_base::vanilla::ItemStatType;
+use wow_world_messages::Guid;
+
+/// Beneficial auras use the first slots, harmful auras the slots after them.
+pub const POSITIVE_AURA_SLOTS: u8 = 32;
+pub const AURA_SLOTS: u8 = 48;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stat {
+    Strength,
+    Agility,
+    Stamina,
+    Intellect,
+    Spirit,
+}
+
+impl Stat {
+    /// The same stat on items.
+    pub const fn item_stat_type(self) -> ItemStatType {
+        match self {
+            Stat::Strength => ItemStatType::Strength,
+            Stat::Agility => ItemStatType::Agility,
+            Stat::Stamina => ItemStatType::Stamina,
+            Stat::Intellect => ItemStatType::Intellect,
+            Stat::Spirit => ItemStatType::Spirit,
+        }
+    }
+}
+
+/// What an aura does while it is applied, amounts are per stack.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AuraEffect {
+    /// Nothing the server needs to keep track of, like stances.
+    Dummy,
+    PeriodicDamage {
+        amount: i32,
+        interval: Duration,
+    },
+    PeriodicHeal {
+        amount: i32,
+        interval: Duration,
+    },
+    PeriodicMana {
+        amount: i32,
+        interval: Duration,
+    },
+    ModStat {
+        stat: Stat,
+        amount: i32,
+    },
+    /// Percent added to every stat, negative lowers them.
+    ModAllStats {
+        percent: i32,
+    },
+    ModArmor {
+        amount: i32,
+    },
+    /// Percent added to the run speed, negative lowers it.
+    ModSpeed {
+        percent: i32,
+    },
+}
+
+impl AuraEffect {
+    const fn interval(&self) -> Option<Duration> {
+        match *self {
+            AuraEffect::PeriodicDamage { interval, .. }
+            | AuraEffect::PeriodicHeal { interval, .. }
+            | AuraEffect::PeriodicMana { interval, .. } => Some(interval),
+            _ => None,
+        }
+    }
+
+    /// Whether stats, and everything calculated from them, change with the aura.
+    pub const fn modifies_stats(&self) -> bool {
+        matches!(
+            self,
+            AuraEffect::ModStat { .. }
+                | AuraEffect::ModAllStats { .. }
+                | AuraEffect::ModArmor { .. }
+        )
+    }
+}
+
+/// A spell effect that stays on a unit for a while.
+#[derive(Debug, Copy, Clone)]
+pub struct Aura {
+    pub spell: u32,
+    pub caster: Guid,
+    pub slot: u8,
+    pub effect: AuraEffect,
+    pub harmful: bool,
+    pub stacks: u8,
+    /// Seconds left, `None` lasts until cancelled.
+    pub remaining: Option<f32>,
+    /// Seconds until the next periodic tick.
+    pub periodic_timer: f32,
+}
+
+impl Aura {
+    /// The aura applied by the spell, if it applies one.
+    pub fn from_spell(spell: &Spell, caster: Guid) -> Option<Self> {
+        spell.effects.iter().find_map(|e| match *e {
+            SpellEffect::ApplyAura { duration, effect } => Some(Self {
+                spell: spell.id,
+                caster,
+                slot: 0,
+                effect,
+                harmful: spell.harmful,
+                stacks: 1,
+                remaining: duration.map(|d| d.as_secs_f32()),
+                periodic_timer: effect.interval().map(|i| i.as_secs_f32()).unwrap_or(0.0),
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// The auras of a unit, in the slots sent in the unit fields.
+#[derive(Debug, Default, Clone)]
+pub struct Auras {
+    auras: Vec<Aura>,
+}
+
+impl Auras {
+    pub fn iter(&self) -> impl Iterator<Item = &Aura> {
+        self.auras.iter()
+    }
+
+    /// The spell in every aura slot, zero for empty slots.
+    pub fn slots(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        (0..AURA_SLOTS).map(|slot| {
+            let spell = self
+                .auras
+                .iter()
+                .find(|a| a.slot == slot)
+                .map(|a| a.spell)
+                .unwrap_or(0);
+            (slot, spell)
+        })
+    }
+
+    /// Applies an aura, or refreshes it and adds a stack if the spell is already applied.
+    ///
+    /// Returns the applied aura, or `None` if every slot is taken.
+    pub fn apply(&mut self, aura: Aura, max_stacks: u8) -> Option<Aura> {
+        if let Some(existing) = self.auras.iter_mut().find(|a| a.spell == aura.spell) {
+            existing.caster = aura.caster;
+            existing.remaining = aura.remaining;
+            existing.stacks = (existing.stacks + 1).min(max_stacks);
+            return Some(*existing);
+        }
+
+        let slots = if aura.harmful {
+            POSITIVE_AURA_SLOTS..AURA_SLOTS
+        } else {
+            0..POSITIVE_AURA_SLOTS
+        };
+        let slot = slots
+            .into_iter()
+            .find(|s| self.auras.iter().all(|a| a.slot != *s))?;
+
+        let aura = Aura { slot, ..aura };
+        self.auras.push(aura);
+
+        Some(aura)
+    }
+
+    pub fn remove(&mut self, spell: u32) -> Option<Aura> {
+        let index = self.auras.iter().position(|a| a.spell == spell)?;
+        Some(self.auras.remove(index))
+    }
+
+    pub fn remove_all(&mut self) -> Vec<Aura> {
+        std::mem::take(&mut self.auras)
+    }
+
+    /// Counts down every aura.
+    ///
+    /// Returns the auras that have expired, which are removed, and the auras with a periodic tick.
+    pub fn update(&mut self) -> (Vec<Aura>, Vec<Aura>) {
+        let mut ticks = Vec::new();
+
+        for aura in &mut self.auras {
+            if let Some(remaining) = &mut aura.remaining {
+                *remaining -= DESIRED_TIMESTEP;
+            }
+
+            if let Some(interval) = aura.effect.interval() {
+                aura.periodic_timer -= DESIRED_TIMESTEP;
+
+                if aura.periodic_timer <= 0.0 {
+                    aura.periodic_timer += interval.as_secs_f32();
+                    ticks.push(*aura);
+                }
+            }
+        }
+
+        let (expired, active) = std::mem::take(&mut self.auras)
+            .into_iter()
+            .partition(|a| a.remaining.is_some_and(|remaining| remaining <= 0.0));
+        self.auras = active;
+
+        (expired, ticks)
+    }
+
+    pub fn stat_bonus(&self, stat: Stat) -> i32 {
+        self.auras
+            .iter()
+            .map(|a| match a.effect {
+                AuraEffect::ModStat { stat: s, amount } if s == stat => {
+                    amount * i32::from(a.stacks)
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn stat_percent(&self) -> i32 {
+        self.auras
+            .iter()
+            .map(|a| match a.effect {
+                AuraEffect::ModAllStats { percent } => percent * i32::from(a.stacks),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn armor_bonus(&self) -> i32 {
+        self.auras
+            .iter()
+            .map(|a| match a.effect {
+                AuraEffect::ModArmor { amount } => amount * i32::from(a.stacks),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn speed_percent(&self) -> i32 {
+        self.auras
+            .iter()
+            .map(|a| match a.effect {
+                AuraEffect::ModSpeed { percent } => percent * i32::from(a.stacks),
+                _ => 0,
+            })
+            .sum()
+    }
+}
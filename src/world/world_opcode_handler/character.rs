@@ -1,13 +1,35 @@
 use crate::world::database::WorldDatabase;
-use crate::world::world_opcode_handler::inventory::Inventory;
+use crate::world::world::instances::InstanceBinding;
+use crate::world::world_opcode_handler::aura::{Aura, AuraEffect, Auras, Stat};
+use crate::world::world_opcode_handler::combat::{COMBAT_TIMEOUT, UNIT_FLAG_IN_COMBAT};
+use crate::world::world_opcode_handler::death::{Corpse, DeathState, PLAYER_FLAGS_GHOST};
+use crate::world::world_opcode_handler::inventory::{Inventory, ItemPosition, CHARACTER_BAG};
+use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::spell::cooldown::Cooldown;
+use crate::world::world_opcode_handler::spell::SpellCast;
+use crate::world::world_opcode_handler::update_fields::{DirtyFields, Field};
+use crate::world::world_opcode_handler::vendor::{BuybackItem, BUYBACK_SLOTS};
 use crate::world::DESIRED_TIMESTEP;
+use std::time::Instant;
 use wow_world_base::movement::DEFAULT_RUNNING_SPEED;
 use wow_world_base::stats::BaseStats;
 use wow_world_base::stats::{calculate_health, calculate_mana};
-use wow_world_base::vanilla::{Level, Map, PlayerGender, RaceClass, Vector3d};
-use wow_world_messages::vanilla::{Area, CreatureFamily, MovementInfo, Power};
+use wow_world_base::vanilla::{
+    ItemSlot, ItemStatType, Level, Map, PlayerGender, RaceClass, Skill, Vector3d,
+};
+use wow_world_messages::vanilla::{Area, Class, CreatureFamily, MovementInfo, Power};
 use wow_world_messages::Guid;
 
+/// Proficiencies that classes are trained in when reaching a level.
+const LEVEL_SKILLS: [(Class, u8, Skill); 6] = [
+    (Class::Warrior, 20, Skill::DualWield),
+    (Class::Warrior, 40, Skill::PlateMail),
+    (Class::Paladin, 40, Skill::PlateMail),
+    (Class::Hunter, 20, Skill::DualWield),
+    (Class::Hunter, 40, Skill::Mail),
+    (Class::Shaman, 40, Skill::Mail),
+];
+
 #[derive(Debug, Clone)]
 pub struct Character {
     pub guid: Guid,
@@ -24,10 +46,33 @@ pub struct Character {
     pub map: Map,
     pub info: MovementInfo,
     pub movement_speed: f32,
+    pub health: i32,
+    /// Mana, rage or energy depending on the class.
+    pub power: i32,
+    pub death_state: DeathState,
+    pub corpse: Option<Corpse>,
     pub target: Guid,
     pub attacking: bool,
     pub auto_attack_timer: f32,
+    /// In combat while above zero.
+    pub combat_timer: f32,
+    pub regeneration_timer: f32,
+    /// Mana regeneration from spirit is paused for a while after spending mana.
+    pub last_mana_use: Option<Instant>,
+    pub casting: Option<SpellCast>,
+    /// Seconds until spells that trigger the global cooldown can be cast again.
+    pub global_cooldown: f32,
+    pub cooldowns: Vec<Cooldown>,
+    pub auras: Auras,
     pub inventory: Inventory,
+    /// In copper.
+    pub money: u32,
+    /// Items sold to vendors this session, oldest first.
+    pub buyback: Vec<BuybackItem>,
+    pub instance_bindings: Vec<InstanceBinding>,
+    /// Weapon and armor proficiencies, which decide what can be equipped.
+    pub skills: Vec<Skill>,
+    pub dirty: DirtyFields,
 }
 
 impl Character {
@@ -37,6 +82,30 @@ impl Character {
             .unwrap_or(self.race_class.base_stats()[0])
     }
 
+    /// Equipped items that are not broken.
+    fn equipped_items(&self) -> impl Iterator<Item = &Item> {
+        self.inventory
+            .equipment()
+            .into_iter()
+            .filter_map(|(item, _)| item)
+            .filter(|item| !item.is_broken())
+    }
+
+    /// Sum of a stat on every equipped item.
+    fn item_stat(&self, stat: ItemStatType) -> i32 {
+        self.equipped_items()
+            .flat_map(|item| item.item.stats())
+            .filter(|s| s.stat_type == stat)
+            .map(|s| s.value)
+            .sum()
+    }
+
+    /// Adds the modifiers of equipment and auras to a base stat.
+    fn modified_stat(&self, stat: Stat, base: i32) -> i32 {
+        let flat = base + self.item_stat(stat.item_stat_type()) + self.auras.stat_bonus(stat);
+        (flat * (100 + self.auras.stat_percent()) / 100).max(0)
+    }
+
     pub fn test_character(
         db: &mut WorldDatabase,
         name: impl Into<String>,
@@ -44,7 +113,8 @@ impl Character {
         gender: PlayerGender,
     ) -> Self {
         let mut c = Self::new(db, name, race_class, gender, 0, 0, 0, 0, 0);
-        c.level = Level::new_vanilla_max_level_player();
+        c.set_level(Level::new_vanilla_max_level_player());
+        c.dirty.clear();
         c
     }
 
@@ -63,7 +133,7 @@ impl Character {
 
         let inventory = Inventory::new(race_class.starter_items(), db);
 
-        Self {
+        let mut c = Self {
             guid: db.new_guid().into(),
             name: name.into(),
             race_class,
@@ -88,10 +158,196 @@ impl Character {
                 fall_time: 0.0,
             },
             movement_speed: DEFAULT_RUNNING_SPEED,
+            health: 0,
+            power: 0,
+            death_state: DeathState::Alive,
+            corpse: None,
             target: Default::default(),
             attacking: false,
             auto_attack_timer: 0.0,
+            combat_timer: 0.0,
+            regeneration_timer: 0.0,
+            last_mana_use: None,
+            casting: None,
+            global_cooldown: 0.0,
+            cooldowns: vec![],
+            auras: Auras::default(),
             inventory,
+            money: 0,
+            buyback: vec![],
+            instance_bindings: vec![],
+            skills: race_class.starter_skills().to_vec(),
+            dirty: DirtyFields::default(),
+        };
+        c.health = c.max_health();
+        c.power = match c.power_type() {
+            Power::Rage => 0,
+            _ => c.max_power(),
+        };
+
+        c
+    }
+
+    pub fn set_level(&mut self, level: Level) {
+        self.level = level;
+        // Leveling up restores health
+        self.health = self.max_health();
+
+        self.dirty.mark(Field::Level);
+        self.dirty.mark(Field::Health);
+        self.dirty.mark(Field::MaxHealth);
+        self.dirty.mark(Field::Stats);
+
+        self.learn_level_skills();
+    }
+
+    /// Learns the proficiencies of the class up to the current level.
+    pub fn learn_level_skills(&mut self) {
+        let class = self.race_class.class();
+        let level = self.level.as_int();
+
+        for (skill_class, skill_level, skill) in LEVEL_SKILLS {
+            if skill_class == class && level >= skill_level && !self.skills.contains(&skill) {
+                self.skills.push(skill);
+                self.dirty.mark(Field::Skills);
+            }
+        }
+    }
+
+    pub fn set_health(&mut self, health: i32) {
+        self.health = health.clamp(0, self.max_health());
+        self.dirty.mark(Field::Health);
+    }
+
+    pub fn set_power(&mut self, power: i32) {
+        self.power = power.clamp(0, self.max_power());
+        self.dirty.mark(Field::Power);
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.death_state == DeathState::Alive
+    }
+
+    pub fn set_death_state(&mut self, death_state: DeathState) {
+        self.death_state = death_state;
+        self.dirty.mark(Field::PlayerFlags);
+    }
+
+    pub fn player_flags(&self) -> i32 {
+        match self.death_state {
+            DeathState::Ghost => PLAYER_FLAGS_GHOST,
+            DeathState::Alive | DeathState::Dead => 0,
+        }
+    }
+
+    /// Lowers the durability of every equipped item by a percentage of its maximum.
+    pub fn lose_durability(&mut self, percent: i32) {
+        let mut broke = false;
+        for item in self.inventory.equipment_mut() {
+            broke |= item.lose_durability(percent);
+        }
+
+        if broke {
+            self.stats_changed();
+        }
+    }
+
+    /// Lowers the durability of the item equipped in the slot by a number of points.
+    pub fn damage_equipment(&mut self, slot: ItemSlot, points: i32) {
+        let broke = self
+            .inventory
+            .get_at_mut(ItemPosition::slot(slot))
+            .is_some_and(|item| item.damage(points));
+
+        if broke {
+            self.stats_changed();
+        }
+    }
+
+    /// Copper needed to repair the item, or every item if `guid` is `None`.
+    pub fn repair_cost(&self, guid: Option<Guid>) -> u32 {
+        self.inventory
+            .items()
+            .filter(|(_, item)| guid.is_none_or(|guid| item.guid == guid))
+            .map(|(_, item)| item.repair_cost())
+            .sum()
+    }
+
+    /// Repairs the item, or every item if `guid` is `None`.
+    pub fn repair(&mut self, guid: Option<Guid>) {
+        let mut repaired_broken = false;
+        for item in self.inventory.items_mut() {
+            if guid.is_none_or(|guid| item.guid == guid) && item.repair_cost() > 0 {
+                repaired_broken |= item.repair();
+            }
+        }
+
+        if repaired_broken {
+            self.stats_changed();
+        }
+    }
+
+    pub fn set_money(&mut self, money: u32) {
+        self.money = money;
+        self.dirty.mark(Field::Coinage);
+    }
+
+    /// Keeps a sold item around so it can be bought back.
+    ///
+    /// Returns the oldest item if it no longer fits.
+    pub fn add_buyback(&mut self, item: BuybackItem) -> Option<Item> {
+        let oldest = if self.buyback.len() >= BUYBACK_SLOTS {
+            Some(self.buyback.remove(0).item)
+        } else {
+            None
+        };
+
+        self.buyback.push(item);
+        self.dirty.mark(Field::Buyback);
+
+        oldest
+    }
+
+    pub fn take_buyback(&mut self, index: usize) -> Option<BuybackItem> {
+        if index >= self.buyback.len() {
+            return None;
+        }
+
+        self.dirty.mark(Field::Buyback);
+        Some(self.buyback.remove(index))
+    }
+
+    pub fn set_target(&mut self, target: Guid) {
+        if self.target != target {
+            self.target = target;
+            self.dirty.mark(Field::Target);
+        }
+    }
+
+    pub fn set_movement_speed(&mut self, speed: f32) {
+        self.movement_speed = speed;
+        self.dirty.mark(Field::RunSpeed);
+    }
+
+    /// Marks the inventory field of the slot as changed, and the visible items if it is equipment.
+    pub fn mark_inventory_dirty(&mut self, slot: ItemSlot) {
+        self.dirty.mark_inventory(slot);
+
+        if slot.as_int() <= ItemSlot::Tabard.as_int() {
+            self.dirty.mark(Field::VisibleItems);
+            self.stats_changed();
+        }
+    }
+
+    /// Marks the slot of the character, or the slot of the bag holding the position.
+    pub fn mark_position_dirty(&mut self, position: ItemPosition) {
+        if let Some(slot) = position.item_slot() {
+            self.mark_inventory_dirty(slot);
+        } else if let Some(bag) = self
+            .inventory
+            .get_at_mut(ItemPosition::new(CHARACTER_BAG, position.bag))
+        {
+            bag.dirty.mark_container_slot(position.slot);
         }
     }
 
@@ -101,8 +357,125 @@ impl Character {
         }
     }
 
+    /// Enters combat, or stays in combat for longer if already in it.
+    pub fn enter_combat(&mut self) {
+        if !self.is_in_combat() {
+            self.dirty.mark(Field::UnitFlags);
+        }
+        self.combat_timer = COMBAT_TIMEOUT;
+    }
+
+    pub fn leave_combat(&mut self) {
+        if self.is_in_combat() {
+            self.dirty.mark(Field::UnitFlags);
+        }
+        self.combat_timer = 0.0;
+    }
+
+    pub fn is_in_combat(&self) -> bool {
+        self.combat_timer > 0.0
+    }
+
+    pub fn update_combat_timer(&mut self) {
+        if self.is_in_combat() {
+            self.combat_timer -= DESIRED_TIMESTEP;
+
+            if !self.is_in_combat() {
+                self.dirty.mark(Field::UnitFlags);
+            }
+        }
+    }
+
+    pub fn unit_flags(&self) -> i32 {
+        if self.is_in_combat() {
+            UNIT_FLAG_IN_COMBAT
+        } else {
+            0
+        }
+    }
+
+    /// Applies an aura, or adds a stack to it, and returns it with the slot it ended up in.
+    pub fn apply_aura(&mut self, aura: Aura, max_stacks: u8) -> Option<Aura> {
+        let aura = self.auras.apply(aura, max_stacks)?;
+        self.aura_changed(aura.effect);
+        Some(aura)
+    }
+
+    pub fn remove_aura(&mut self, spell: u32) -> Option<Aura> {
+        let aura = self.auras.remove(spell)?;
+        self.aura_changed(aura.effect);
+        Some(aura)
+    }
+
+    pub fn remove_all_auras(&mut self) -> Vec<Aura> {
+        let auras = self.auras.remove_all();
+        for aura in &auras {
+            self.aura_changed(aura.effect);
+        }
+        auras
+    }
+
+    /// Counts down the auras and applies their periodic effects, returns the expired auras.
+    pub fn update_auras(&mut self) -> Vec<Aura> {
+        let (expired, ticks) = self.auras.update();
+
+        for aura in ticks {
+            let stacks = i32::from(aura.stacks);
+
+            match aura.effect {
+                AuraEffect::PeriodicDamage { amount, .. } => {
+                    self.enter_combat();
+                    self.set_health(self.health - amount * stacks);
+                }
+                AuraEffect::PeriodicHeal { amount, .. } => {
+                    self.set_health(self.health + amount * stacks);
+                }
+                AuraEffect::PeriodicMana { amount, .. } => {
+                    if self.power_type() == Power::Mana {
+                        self.set_power(self.power + amount * stacks);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for aura in &expired {
+            self.aura_changed(aura.effect);
+        }
+
+        expired
+    }
+
+    fn aura_changed(&mut self, effect: AuraEffect) {
+        self.dirty.mark(Field::Auras);
+
+        if effect.modifies_stats() {
+            self.stats_changed();
+        }
+
+        if let AuraEffect::ModSpeed { .. } = effect {
+            self.dirty.mark(Field::RunSpeed);
+        }
+    }
+
+    /// Marks stats, and everything calculated from them, for sending.
+    fn stats_changed(&mut self) {
+        self.dirty.mark(Field::Stats);
+        self.dirty.mark(Field::MaxHealth);
+        self.dirty.mark(Field::MaxPower);
+        // Lowering the maximum also lowers the current value
+        self.set_health(self.health);
+        self.set_power(self.power);
+    }
+
+    /// The run speed including auras, `movement_speed` is the speed without them.
+    pub fn run_speed(&self) -> f32 {
+        let percent = (100 + self.auras.speed_percent()).max(0);
+        self.movement_speed * percent as f32 / 100.0
+    }
+
     pub fn strength(&self) -> i32 {
-        self.default_stats().strength.into()
+        self.modified_stat(Stat::Strength, self.default_stats().strength.into())
     }
 
     pub fn base_health(&self) -> i32 {
@@ -110,7 +483,13 @@ impl Character {
     }
 
     pub fn max_health(&self) -> i32 {
-        calculate_health(self.default_stats().health, self.default_stats().stamina).into()
+        let stats = self.default_stats();
+        let health: i32 = calculate_health(stats.health, stats.stamina).into();
+        let stamina: i32 = stats.stamina.into();
+
+        // Every point of stamina from items and auras is worth 10 health
+        let bonus = (self.stamina() - stamina) * 10 + self.item_stat(ItemStatType::Health);
+        (health + bonus).max(self.base_health())
     }
 
     pub fn base_mana(&self) -> i32 {
@@ -119,26 +498,95 @@ impl Character {
 
     pub fn max_mana(&self) -> i32 {
         if self.race_class.class().power_type() == Power::Mana {
-            calculate_mana(self.default_stats().mana, self.default_stats().intellect).into()
+            let stats = self.default_stats();
+            let mana: i32 = calculate_mana(stats.mana, stats.intellect).into();
+            let intellect: i32 = stats.intellect.into();
+
+            // Every point of intellect from items and auras is worth 15 mana
+            let bonus = (self.intellect() - intellect) * 15 + self.item_stat(ItemStatType::Mana);
+            (mana + bonus).max(self.base_mana())
         } else {
             0
         }
     }
 
+    pub fn power_type(&self) -> Power {
+        self.race_class.class().power_type()
+    }
+
+    /// Rage is sent multiplied by ten, so 1000 is shown as 100 rage by the client.
+    pub fn max_power(&self) -> i32 {
+        match self.power_type() {
+            Power::Mana => self.max_mana(),
+            Power::Rage => 1000,
+            _ => 100,
+        }
+    }
+
     pub fn agility(&self) -> i32 {
-        self.default_stats().agility.into()
+        self.modified_stat(Stat::Agility, self.default_stats().agility.into())
     }
 
     pub fn stamina(&self) -> i32 {
-        self.default_stats().stamina.into()
+        self.modified_stat(Stat::Stamina, self.default_stats().stamina.into())
     }
 
     pub fn intellect(&self) -> i32 {
-        self.default_stats().intellect.into()
+        self.modified_stat(Stat::Intellect, self.default_stats().intellect.into())
     }
 
     pub fn spirit(&self) -> i32 {
-        self.default_stats().spirit.into()
+        self.modified_stat(Stat::Spirit, self.default_stats().spirit.into())
+    }
+
+    /// Armor of the equipment and auras, every point of agility adds 2.
+    pub fn armor(&self) -> i32 {
+        let items: i32 = self
+            .equipped_items()
+            .map(|item| i32::from(item.item.armor()))
+            .sum();
+
+        (items + self.agility() * 2 + self.auras.armor_bonus()).max(0)
+    }
+
+    /// Armor followed by the holy, fire, nature, frost, shadow and arcane resistances,
+    /// in the order of the unit fields.
+    pub fn resistances(&self) -> [i32; 7] {
+        let mut resistances = [self.armor(), 0, 0, 0, 0, 0, 0];
+
+        for item in self.equipped_items().map(|i| i.item) {
+            let schools = [
+                item.holy_resistance(),
+                item.fire_resistance(),
+                item.nature_resistance(),
+                item.frost_resistance(),
+                item.shadow_resistance(),
+                item.arcane_resistance(),
+            ];
+
+            for (resistance, value) in resistances[1..].iter_mut().zip(schools) {
+                *resistance += i32::from(value);
+            }
+        }
+
+        resistances
+    }
+
+    /// Melee attack power, which adds 1 damage per second of weapon speed for every 14 points.
+    pub fn attack_power(&self) -> i32 {
+        let level = i32::from(self.level.as_int());
+        let strength = self.strength();
+        let agility = self.agility();
+
+        let attack_power = match self.race_class.class() {
+            Class::Warrior | Class::Paladin => level * 3 + strength * 2 - 20,
+            Class::Shaman => level * 2 + strength * 2 - 20,
+            Class::Rogue | Class::Hunter => level * 2 + strength + agility - 20,
+            Class::Druid => strength * 2 - 20,
+            Class::Priest | Class::Mage | Class::Warlock => strength - 10,
+        };
+
+        attack_power.max(0)
     }
 }
 
@@ -28,7 +28,7 @@ pub async fn handle_message(client: &mut Client, clients: &mut [Client], m: CMSG
             return;
         }
         _ => {
-            dbg!(m);
+            debug!(session: client.session(), "Unhandled chat message: {m:?}");
             return;
         }
     };
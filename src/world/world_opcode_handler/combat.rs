@@ -0,0 +1,537 @@
+use crate::world::world::client::Client;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::creature::Creature;
+use crate::world::world_opcode_handler::entities::{Entities, Entity};
+use crate::world::world_opcode_handler::send_to_all;
+use rand::Rng;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use wow_items::vanilla::InventoryType;
+use wow_world_base::combat::UNARMED_SPEED;
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::{Guid, HitInfo, ItemSlot};
+use wow_world_messages::vanilla::{
+    Class, DamageInfo, Power, SMSG_ATTACKERSTATEUPDATE, SMSG_ATTACKSTOP, SMSG_ATTACKSWING_BADFACING,
+};
+
+/// Maximum distance between the attacker and the target for a swing to land.
+pub(crate) const MELEE_RANGE: f32 = 5.0;
+
+/// Seconds without attacking or being attacked before leaving combat.
+pub(crate) const COMBAT_TIMEOUT: f32 = 5.0;
+
+/// Seconds before swinging again at a target that is not in front of the attacker.
+const BAD_FACING_DELAY: f32 = 0.5;
+
+pub(crate) const UNIT_FLAG_IN_COMBAT: i32 = 0x0008_0000;
+
+/// Victim states sent in `damage_state` of `SMSG_ATTACKERSTATEUPDATE`.
+const VICTIM_STATE_UNAFFECTED: u32 = 0;
+const VICTIM_STATE_NORMAL: u32 = 1;
+const VICTIM_STATE_DODGE: u32 = 2;
+const VICTIM_STATE_PARRY: u32 = 3;
+const VICTIM_STATE_BLOCKS: u32 = 5;
+
+const CRITICAL_MULTIPLIER: f32 = 2.0;
+
+const RAGE_DEALT_MULTIPLIER: f32 = 7.5;
+const RAGE_TAKEN_MULTIPLIER: f32 = 2.5;
+
+/// Chance of a hit costing a point of durability on the weapon or on one piece of armor.
+const DURABILITY_LOSS_CHANCE: f64 = 0.05;
+
+/// Equipment that wears down from being hit.
+const ARMOR_SLOTS: [ItemSlot; 10] = [
+    ItemSlot::Head,
+    ItemSlot::Shoulders,
+    ItemSlot::Chest,
+    ItemSlot::Waist,
+    ItemSlot::Legs,
+    ItemSlot::Boots,
+    ItemSlot::Wrist,
+    ItemSlot::Hands,
+    ItemSlot::Back,
+    ItemSlot::OffHand,
+];
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Weapon {
+    pub min_damage: f32,
+    pub max_damage: f32,
+    /// Seconds between swings.
+    pub speed: f32,
+}
+
+impl Weapon {
+    pub(crate) const UNARMED: Self = Self {
+        min_damage: 1.0,
+        max_damage: 2.0,
+        speed: UNARMED_SPEED,
+    };
+
+    /// The weapon in the main hand, or fists if there is none.
+    pub(crate) fn main_hand(character: &Character) -> Self {
+        let item = character.inventory.get(ItemSlot::MainHand);
+        let Some(item) = item.filter(|i| !i.is_broken()) else {
+            return Self::UNARMED;
+        };
+
+        let Some(damage) = item.item.damages().first() else {
+            return Self::UNARMED;
+        };
+
+        let speed = if item.item.delay() == 0 {
+            UNARMED_SPEED
+        } else {
+            f32::from(item.item.delay()) / 1000.0
+        };
+
+        Self {
+            min_damage: damage.damage_minimum,
+            max_damage: damage.damage_maximum.max(damage.damage_minimum),
+            speed,
+        }
+    }
+}
+
+/// The result of a single roll on the attack table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum MeleeOutcome {
+    Miss,
+    Dodge,
+    Parry,
+    Glancing,
+    Block,
+    Critical,
+    Hit,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Attacker {
+    level: u8,
+    attack_power: i32,
+    weapon: Weapon,
+    critical_chance: f32,
+}
+
+impl Attacker {
+    fn new(character: &Character) -> Self {
+        Self {
+            level: character.level.as_int(),
+            attack_power: character.attack_power(),
+            weapon: Weapon::main_hand(character),
+            critical_chance: 5.0 + character.agility() as f32 / 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Defender {
+    level: u8,
+    health: i32,
+    position: Position,
+    dodge_chance: f32,
+    parry_chance: f32,
+    block_chance: f32,
+    block_value: i32,
+    /// Only attacks from players against creatures can be glancing.
+    can_glance: bool,
+}
+
+impl Defender {
+    fn player(character: &Character, position: Position) -> Self {
+        let can_parry = matches!(
+            character.race_class.class(),
+            Class::Warrior | Class::Paladin | Class::Rogue | Class::Hunter
+        );
+
+        let shield = character
+            .inventory
+            .get(ItemSlot::OffHand)
+            .filter(|i| i.item.inventory_type() == InventoryType::Shield);
+
+        Self {
+            level: character.level.as_int(),
+            health: character.health,
+            position,
+            dodge_chance: 5.0 + character.agility() as f32 / 20.0,
+            parry_chance: if can_parry { 5.0 } else { 0.0 },
+            block_chance: if shield.is_some() { 5.0 } else { 0.0 },
+            block_value: shield.map(|s| i32::from(s.item.block())).unwrap_or(0)
+                + character.strength() / 20,
+            can_glance: false,
+        }
+    }
+
+    fn creature(creature: &Creature) -> Self {
+        Self {
+            level: creature.level,
+            health: creature.health,
+            position: creature.position(),
+            dodge_chance: 5.0,
+            parry_chance: 5.0,
+            block_chance: 0.0,
+            block_value: 0,
+            can_glance: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct MeleeSwing {
+    outcome: MeleeOutcome,
+    damage: i32,
+    blocked: i32,
+}
+
+/// Rolls once on the attack table, where every outcome pushes the ones after it down.
+///
+/// Every level the defender has over the attacker is 5 points of defense over weapon skill,
+/// which increases miss, dodge, parry, block and glancing chance and lowers critical chance.
+fn roll_outcome(attacker: &Attacker, defender: &Defender, roll: f32) -> MeleeOutcome {
+    let skill_difference = (i32::from(defender.level) - i32::from(attacker.level)) as f32 * 5.0;
+    let avoidance = skill_difference * 0.04;
+
+    let miss = if skill_difference > 10.0 {
+        7.0 + (skill_difference - 10.0) * 0.4
+    } else {
+        5.0 + skill_difference * 0.1
+    };
+    let glancing = if defender.can_glance {
+        (10.0 + skill_difference * 2.0).clamp(0.0, 40.0)
+    } else {
+        0.0
+    };
+
+    let table = [
+        (MeleeOutcome::Miss, miss),
+        (MeleeOutcome::Dodge, defender.dodge_chance + avoidance),
+        (MeleeOutcome::Parry, defender.parry_chance + avoidance),
+        (MeleeOutcome::Glancing, glancing),
+        (
+            MeleeOutcome::Block,
+            if defender.block_chance > 0.0 {
+                defender.block_chance + avoidance
+            } else {
+                0.0
+            },
+        ),
+        (MeleeOutcome::Critical, attacker.critical_chance - avoidance),
+    ];
+
+    let mut total = 0.0;
+    for (outcome, chance) in table {
+        total += chance.max(0.0);
+        if roll < total {
+            return outcome;
+        }
+    }
+
+    MeleeOutcome::Hit
+}
+
+/// Weapon damage including attack power, before the attack table is applied.
+fn roll_weapon_damage(weapon: &Weapon, attack_power: i32, rng: &mut impl Rng) -> f32 {
+    let damage = rng.gen_range(weapon.min_damage..=weapon.max_damage)
+        + attack_power as f32 / 14.0 * weapon.speed;
+    damage.max(1.0)
+}
+
+/// Damage of a hit with the main hand weapon, used by spells that deal weapon damage.
+pub(crate) fn weapon_damage(character: &Character, rng: &mut impl Rng) -> i32 {
+    roll_weapon_damage(&Weapon::main_hand(character), character.attack_power(), rng) as i32
+}
+
+fn roll_swing(attacker: &Attacker, defender: &Defender, rng: &mut impl Rng) -> MeleeSwing {
+    let outcome = roll_outcome(attacker, defender, rng.gen_range(0.0..100.0));
+
+    let base = roll_weapon_damage(&attacker.weapon, attacker.attack_power, rng);
+
+    let mut blocked = 0;
+    let damage = match outcome {
+        MeleeOutcome::Miss | MeleeOutcome::Dodge | MeleeOutcome::Parry => 0.0,
+        MeleeOutcome::Glancing => {
+            let skill_difference =
+                (i32::from(defender.level) - i32::from(attacker.level)).max(0) as f32 * 5.0;
+            let multiplier = (0.95 - skill_difference * 0.02).clamp(0.65, 0.95);
+            base * multiplier
+        }
+        MeleeOutcome::Block => {
+            blocked = defender.block_value.min(base as i32);
+            base - blocked as f32
+        }
+        MeleeOutcome::Critical => base * CRITICAL_MULTIPLIER,
+        MeleeOutcome::Hit => base,
+    };
+
+    MeleeSwing {
+        outcome,
+        damage: damage as i32,
+        blocked,
+    }
+}
+
+fn attacker_state_update(
+    attacker: Guid,
+    target: Guid,
+    swing: &MeleeSwing,
+) -> SMSG_ATTACKERSTATEUPDATE {
+    let (hit_info, damage_state) = match swing.outcome {
+        MeleeOutcome::Miss => (HitInfo::Miss, VICTIM_STATE_UNAFFECTED),
+        MeleeOutcome::Dodge => (HitInfo::NormalSwing, VICTIM_STATE_DODGE),
+        MeleeOutcome::Parry => (HitInfo::NormalSwing, VICTIM_STATE_PARRY),
+        MeleeOutcome::Glancing => (HitInfo::Glancing, VICTIM_STATE_NORMAL),
+        MeleeOutcome::Block => (HitInfo::NormalSwing, VICTIM_STATE_BLOCKS),
+        MeleeOutcome::Critical => (HitInfo::CriticalHit, VICTIM_STATE_NORMAL),
+        MeleeOutcome::Hit => (HitInfo::NormalSwing, VICTIM_STATE_NORMAL),
+    };
+
+    SMSG_ATTACKERSTATEUPDATE {
+        hit_info,
+        attacker,
+        target,
+        total_damage: swing.damage as u32,
+        damages: vec![DamageInfo {
+            spell_school_mask: 0,
+            damage_float: swing.damage as f32,
+            damage_uint: swing.damage as u32,
+            absorb: 0,
+            resist: 0,
+        }],
+        unknown1: 0,
+        spell_id: 0,
+        damage_state,
+        blocked_amount: swing.blocked as u32,
+    }
+}
+
+/// Rage from damage, scaled by how much damage is expected at the level.
+fn gain_rage(character: &mut Character, damage: i32, multiplier: f32) {
+    if character.power_type() != Power::Rage {
+        return;
+    }
+
+    let level = f32::from(character.level.as_int());
+    let conversion = 0.0091107836 * level * level + 3.225598133 * level + 4.2652911;
+    // Rage is stored multiplied by ten
+    let rage = (damage as f32 / conversion * multiplier * 10.0) as i32;
+
+    character.set_power(character.power + rage);
+}
+
+/// Damages a player or creature and puts both sides in combat.
+pub(crate) fn deal_damage(
+    attacker: &mut Character,
+    entities: &mut Entities,
+    target: Guid,
+    damage: i32,
+    rng: &mut impl Rng,
+) {
+    attacker.enter_combat();
+
+    if let Some(c) = entities.find_player_mut(target) {
+        let character = c.character_mut();
+        character.enter_combat();
+
+        if damage > 0 {
+            character.set_health(character.health - damage);
+            gain_rage(character, damage, RAGE_TAKEN_MULTIPLIER);
+
+            if rng.gen_bool(DURABILITY_LOSS_CHANCE) {
+                let slot = ARMOR_SLOTS[rng.gen_range(0..ARMOR_SLOTS.len())];
+                character.damage_equipment(slot, 1);
+            }
+        }
+    } else if let Some(c) = entities.find_creature_mut(target) {
+        if damage > 0 {
+            c.set_health(c.health - damage);
+        }
+    }
+
+    if damage > 0 {
+        gain_rage(attacker, damage, RAGE_DEALT_MULTIPLIER);
+    }
+}
+
+/// Whether `target` is within the half circle in front of `position`.
+fn is_in_front(position: &Position, target: &Position) -> bool {
+    let dx = target.x - position.x;
+    let dy = target.y - position.y;
+    if dx == 0.0 && dy == 0.0 {
+        return true;
+    }
+
+    // Difference between the facing and the direction to the target, in -PI..PI
+    let angle = (dy.atan2(dx) - position.orientation + PI).rem_euclid(TAU) - PI;
+    angle.abs() <= FRAC_PI_2
+}
+
+/// Stops auto attacking and tells everybody.
+pub(crate) async fn stop_attacking(client: &mut Client, clients: &mut [Client]) {
+    client.character_mut().attacking = false;
+
+    send_to_all(
+        SMSG_ATTACKSTOP {
+            player: client.character().guid,
+            enemy: client.character().target,
+            unknown1: 0,
+        },
+        client,
+        clients,
+    )
+    .await;
+}
+
+/// Swings at the current target and restarts the swing timer.
+///
+/// Targets that are out of range are swung at again on the next tick,
+/// targets behind the attacker shortly after telling the client,
+/// while attacking stops if either side is dead or the target is gone.
+pub(crate) async fn melee_swing(client: &mut Client, entities: &mut Entities<'_>) {
+    let target = client.character().target;
+
+    let defender = match entities.find_guid(target) {
+        Some(Entity::Player(c)) => Some(Defender::player(c.character(), c.position())),
+        Some(Entity::Creature(c)) => Some(Defender::creature(c)),
+        None => None,
+    };
+    let alive = client.character().is_alive();
+    let Some(defender) = defender.filter(|d| alive && d.health > 0) else {
+        stop_attacking(client, entities.clients()).await;
+        return;
+    };
+
+    match client.distance_to_position(&defender.position) {
+        Some(distance) if distance <= MELEE_RANGE => {}
+        _ => return,
+    }
+
+    if !is_in_front(&client.position(), &defender.position) {
+        client.character_mut().auto_attack_timer = BAD_FACING_DELAY;
+        client.send_message(SMSG_ATTACKSWING_BADFACING {}).await;
+        return;
+    }
+
+    let attacker = Attacker::new(client.character());
+    client.character_mut().auto_attack_timer = attacker.weapon.speed;
+
+    // The thread local rng can not be held across an await
+    let swing = {
+        let mut rng = rand::thread_rng();
+
+        let swing = roll_swing(&attacker, &defender, &mut rng);
+
+        deal_damage(
+            client.character_mut(),
+            entities,
+            target,
+            swing.damage,
+            &mut rng,
+        );
+        if swing.damage > 0 && rng.gen_bool(DURABILITY_LOSS_CHANCE) {
+            client
+                .character_mut()
+                .damage_equipment(ItemSlot::MainHand, 1);
+        }
+
+        swing
+    };
+
+    let guid = client.character().guid;
+    send_to_all(
+        attacker_state_update(guid, target, &swing),
+        client,
+        entities.clients(),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wow_world_base::vanilla::Map;
+
+    const ATTACKER: Attacker = Attacker {
+        level: 10,
+        attack_power: 0,
+        weapon: Weapon::UNARMED,
+        critical_chance: 5.0,
+    };
+
+    fn facing(orientation: f32) -> Position {
+        Position::new(Map::EasternKingdoms, 0.0, 0.0, 0.0, orientation)
+    }
+
+    fn defender(can_glance: bool, block_chance: f32) -> Defender {
+        Defender {
+            level: 10,
+            health: 100,
+            position: facing(0.0),
+            dodge_chance: 5.0,
+            parry_chance: 5.0,
+            block_chance,
+            block_value: 10,
+            can_glance,
+        }
+    }
+
+    #[test]
+    fn attack_table_boundaries() {
+        // Miss, dodge, parry, block and critical all take five points at the same level
+        let defender = defender(false, 5.0);
+        let outcome = |roll| roll_outcome(&ATTACKER, &defender, roll);
+
+        assert_eq!(outcome(0.0), MeleeOutcome::Miss);
+        assert_eq!(outcome(4.99), MeleeOutcome::Miss);
+        assert_eq!(outcome(5.0), MeleeOutcome::Dodge);
+        assert_eq!(outcome(9.99), MeleeOutcome::Dodge);
+        assert_eq!(outcome(10.0), MeleeOutcome::Parry);
+        assert_eq!(outcome(14.99), MeleeOutcome::Parry);
+        assert_eq!(outcome(15.0), MeleeOutcome::Block);
+        assert_eq!(outcome(19.99), MeleeOutcome::Block);
+        assert_eq!(outcome(20.0), MeleeOutcome::Critical);
+        assert_eq!(outcome(24.99), MeleeOutcome::Critical);
+        assert_eq!(outcome(25.0), MeleeOutcome::Hit);
+        assert_eq!(outcome(99.99), MeleeOutcome::Hit);
+    }
+
+    #[test]
+    fn glancing_comes_after_parry() {
+        let defender = defender(true, 0.0);
+        let outcome = |roll| roll_outcome(&ATTACKER, &defender, roll);
+
+        assert_eq!(outcome(14.99), MeleeOutcome::Parry);
+        assert_eq!(outcome(15.0), MeleeOutcome::Glancing);
+        assert_eq!(outcome(24.99), MeleeOutcome::Glancing);
+        assert_eq!(outcome(25.0), MeleeOutcome::Critical);
+        assert_eq!(outcome(30.0), MeleeOutcome::Hit);
+    }
+
+    #[test]
+    fn higher_level_defenders_avoid_more() {
+        let mut defender = defender(false, 0.0);
+        defender.level = ATTACKER.level + 3;
+
+        // 15 points of defense over weapon skill gives 9% miss and 5.6% dodge
+        assert_eq!(roll_outcome(&ATTACKER, &defender, 8.99), MeleeOutcome::Miss);
+        assert_eq!(roll_outcome(&ATTACKER, &defender, 9.0), MeleeOutcome::Dodge);
+        assert_eq!(
+            roll_outcome(&ATTACKER, &defender, 14.5),
+            MeleeOutcome::Parry
+        );
+    }
+
+    #[test]
+    fn only_targets_in_front() {
+        let target = |x, y| Position::new(Map::EasternKingdoms, x, y, 0.0, 0.0);
+
+        assert!(is_in_front(&facing(0.0), &target(1.0, 0.0)));
+        assert!(is_in_front(&facing(0.0), &target(1.0, 1.0)));
+        assert!(is_in_front(&facing(0.0), &target(0.0, 0.0)));
+        assert!(!is_in_front(&facing(0.0), &target(-1.0, 0.0)));
+        assert!(!is_in_front(&facing(0.0), &target(-1.0, -1.0)));
+        assert!(is_in_front(&facing(PI), &target(-1.0, 0.0)));
+        // Orientation wraps around at zero
+        assert!(is_in_front(&facing(TAU - 0.1), &target(1.0, 0.1)));
+        assert!(!is_in_front(&facing(3.0 * FRAC_PI_2), &target(0.0, 1.0)));
+    }
+}
@@ -1,13 +1,79 @@
+use crate::world::world_opcode_handler::aura::{Aura, AuraEffect, Auras};
+use crate::world::world_opcode_handler::update_fields::{DirtyFields, Field};
+use crate::world::world_opcode_handler::vendor::Vendor;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::{Duration, Instant};
 use wow_world_base::movement::{DEFAULT_RUNNING_SPEED, DEFAULT_TURN_SPEED, DEFAULT_WALKING_SPEED};
-use wow_world_base::vanilla::position::{position, Position, PositionIdentifier};
-use wow_world_base::vanilla::Map;
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::{CreatureFamily, Map};
 use wow_world_messages::vanilla::UpdateMask;
 use wow_world_messages::vanilla::{
     MovementBlock, MovementBlock_UpdateFlag, MovementBlock_UpdateFlag_Living, MovementInfo, Object,
-    ObjectType, Object_UpdateType, UpdateUnitBuilder, Vector3d, SMSG_UPDATE_OBJECT,
+    ObjectType, Object_UpdateType, UpdateUnitBuilder, Vector3d,
 };
 use wow_world_messages::Guid;
 
+/// What every creature with the same entry has in common.
+#[derive(Debug, Clone)]
+pub struct CreatureTemplate {
+    pub entry: u32,
+    pub name: String,
+    /// Shown below the name, like `General Supplies`.
+    pub sub_name: String,
+    pub min_level: u8,
+    pub max_level: u8,
+    /// One is picked at random for every spawn.
+    pub display_ids: Vec<u16>,
+    pub faction_template: u32,
+    /// Flags other than vendoring and repairing, which come from [`Vendor`].
+    pub npc_flags: i32,
+    pub creature_type: u32,
+    pub family: CreatureFamily,
+    /// Normal, elite, rare elite, boss or rare.
+    pub rank: u32,
+    pub health: i32,
+    pub min_damage: f32,
+    pub max_damage: f32,
+}
+
+/// A creature placed in the world when it starts.
+#[derive(Debug, Copy, Clone)]
+pub struct CreatureSpawn {
+    pub entry: u32,
+    pub position: Position,
+    /// Time from dying to coming back, at least [`CORPSE_DESPAWN_TIME`].
+    pub respawn_time: Duration,
+}
+
+/// How long corpses stay before they disappear.
+pub const CORPSE_DESPAWN_TIME: Duration = Duration::from_secs(60);
+
+const UNIT_DYNAMIC_FLAG_LOOTABLE: i32 = 0x0001;
+const UNIT_DYNAMIC_FLAG_DEAD: i32 = 0x0020;
+
+/// Where a creature is between dying and respawning.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CreatureState {
+    Alive,
+    /// The corpse is still in the world.
+    Dead {
+        died: Instant,
+    },
+    /// Removed from the world until it respawns.
+    Despawned {
+        died: Instant,
+    },
+}
+
+/// A change of [`CreatureState`] that observers have to be told about.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CreatureEvent {
+    Died,
+    Despawned,
+    Respawned,
+}
+
 #[derive(Debug)]
 pub struct Creature {
     pub name: String,
@@ -18,14 +84,29 @@ pub struct Creature {
     pub display_id: u16,
     pub entry: u32,
     pub faction_template: u32,
+    pub npc_flags: i32,
+    pub health: i32,
+    pub max_health: i32,
+    pub min_damage: f32,
+    pub max_damage: f32,
+    /// Creatures only use mana.
+    pub power: i32,
+    pub max_power: i32,
+    pub target: Guid,
+    pub auras: Auras,
+    pub vendor: Option<Vendor>,
+    pub state: CreatureState,
+    pub spawn: CreatureSpawn,
+    pub dirty: DirtyFields,
 }
 
 impl Creature {
-    pub fn new(name: impl Into<String>, guid: Guid) -> Self {
-        let p = position(PositionIdentifier::HumanStartZone);
+    pub fn new(template: &CreatureTemplate, spawn: &CreatureSpawn, guid: Guid) -> Self {
+        let p = spawn.position;
+        let mut rng = rand::thread_rng();
 
         Self {
-            name: name.into(),
+            name: template.name.clone(),
             guid,
             info: MovementInfo {
                 flags: Default::default(),
@@ -39,10 +120,152 @@ impl Creature {
                 fall_time: 0.0,
             },
             map: p.map,
-            level: 1,
-            display_id: 646,
-            entry: 69,
-            faction_template: 16,
+            level: rng.gen_range(template.min_level..=template.max_level),
+            display_id: template
+                .display_ids
+                .choose(&mut rng)
+                .copied()
+                .unwrap_or_default(),
+            entry: template.entry,
+            faction_template: template.faction_template,
+            npc_flags: template.npc_flags,
+            health: template.health,
+            max_health: template.health,
+            min_damage: template.min_damage,
+            max_damage: template.max_damage,
+            power: 0,
+            max_power: 0,
+            target: Guid::zero(),
+            auras: Auras::default(),
+            vendor: Vendor::new(template.entry),
+            state: CreatureState::Alive,
+            spawn: *spawn,
+            dirty: DirtyFields::default(),
+        }
+    }
+
+    /// Dead creatures stay at zero until they respawn.
+    pub fn set_health(&mut self, health: i32) {
+        if !self.is_alive() {
+            return;
+        }
+
+        self.health = health.clamp(0, self.max_health);
+        self.dirty.mark(Field::Health);
+    }
+
+    pub fn apply_aura(&mut self, aura: Aura, max_stacks: u8) -> Option<Aura> {
+        let aura = self.auras.apply(aura, max_stacks)?;
+        self.dirty.mark(Field::Auras);
+        Some(aura)
+    }
+
+    pub fn remove_all_auras(&mut self) {
+        if !self.auras.remove_all().is_empty() {
+            self.dirty.mark(Field::Auras);
+        }
+    }
+
+    /// Counts down the auras and applies periodic damage and healing.
+    ///
+    /// Creatures have no stats, so other modifiers have no effect.
+    pub fn update_auras(&mut self) {
+        let (expired, ticks) = self.auras.update();
+
+        for aura in ticks {
+            let stacks = i32::from(aura.stacks);
+
+            match aura.effect {
+                AuraEffect::PeriodicDamage { amount, .. } => {
+                    self.set_health(self.health - amount * stacks);
+                }
+                AuraEffect::PeriodicHeal { amount, .. } => {
+                    self.set_health(self.health + amount * stacks);
+                }
+                _ => {}
+            }
+        }
+
+        if !expired.is_empty() {
+            self.dirty.mark(Field::Auras);
+        }
+    }
+
+    pub fn set_target(&mut self, target: Guid) {
+        if self.target != target {
+            self.target = target;
+            self.dirty.mark(Field::Target);
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.state == CreatureState::Alive
+    }
+
+    /// Despawned creatures are not in the world and are not sent to anybody.
+    pub fn is_spawned(&self) -> bool {
+        !matches!(self.state, CreatureState::Despawned { .. })
+    }
+
+    pub fn dynamic_flags(&self) -> i32 {
+        match self.state {
+            CreatureState::Alive => 0,
+            CreatureState::Dead { .. } | CreatureState::Despawned { .. } => {
+                UNIT_DYNAMIC_FLAG_LOOTABLE | UNIT_DYNAMIC_FLAG_DEAD
+            }
+        }
+    }
+
+    /// Moves the creature from dying to despawning to respawning.
+    ///
+    /// The time is passed in so the whole cycle can be driven without waiting.
+    pub fn update_state(&mut self, now: Instant) -> Option<CreatureEvent> {
+        match self.state {
+            CreatureState::Alive if self.health == 0 => {
+                self.state = CreatureState::Dead { died: now };
+                self.remove_all_auras();
+                self.set_target(Guid::zero());
+                self.dirty.mark(Field::DynamicFlags);
+
+                Some(CreatureEvent::Died)
+            }
+            CreatureState::Dead { died } if now >= died + CORPSE_DESPAWN_TIME => {
+                self.state = CreatureState::Despawned { died };
+
+                Some(CreatureEvent::Despawned)
+            }
+            CreatureState::Despawned { died }
+                if now >= died + self.spawn.respawn_time.max(CORPSE_DESPAWN_TIME) =>
+            {
+                self.respawn();
+
+                Some(CreatureEvent::Respawned)
+            }
+            _ => None,
+        }
+    }
+
+    /// Back at the spawn point with full health, the creature is sent again as a new object.
+    fn respawn(&mut self) {
+        let p = self.spawn.position;
+        self.map = p.map;
+        self.info.position = Vector3d {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+        };
+        self.info.orientation = p.orientation;
+
+        self.health = self.max_health;
+        self.power = self.max_power;
+        self.state = CreatureState::Alive;
+        self.dirty.clear();
+    }
+
+    pub fn npc_flags(&self) -> i32 {
+        match &self.vendor {
+            Some(vendor) => self.npc_flags | vendor.npc_flags(),
+            None => self.npc_flags,
         }
     }
 
@@ -56,44 +279,152 @@ impl Creature {
         }
     }
 
-    pub fn to_message(&self) -> SMSG_UPDATE_OBJECT {
-        SMSG_UPDATE_OBJECT {
-            has_transport: 0,
-            objects: vec![Object {
-                update_type: Object_UpdateType::CreateObject2 {
-                    guid3: self.guid,
-                    mask2: UpdateMask::Unit(
-                        UpdateUnitBuilder::new()
-                            .set_unit_health(100)
-                            .set_unit_maxhealth(100)
-                            .set_object_guid(self.guid)
-                            .set_unit_displayid(self.display_id.into())
-                            .set_object_scale_x(1.0)
-                            .set_unit_level(self.level.into())
-                            .set_unit_factiontemplate(self.faction_template as i32)
-                            .set_object_entry(self.entry as i32)
-                            .finalize(),
+    pub fn to_create_object(&self) -> Object {
+        Object {
+            update_type: Object_UpdateType::CreateObject2 {
+                guid3: self.guid,
+                mask2: UpdateMask::Unit(
+                    UpdateUnitBuilder::new()
+                        .set_unit_health(self.health)
+                        .set_unit_maxhealth(self.max_health)
+                        .set_unit_mindamage(self.min_damage)
+                        .set_unit_maxdamage(self.max_damage)
+                        .set_unit_power1(self.power)
+                        .set_unit_maxpower1(self.max_power)
+                        .set_unit_target(self.target)
+                        .set_object_guid(self.guid)
+                        .set_unit_displayid(self.display_id.into())
+                        .set_object_scale_x(1.0)
+                        .set_unit_level(self.level.into())
+                        .set_unit_factiontemplate(self.faction_template as i32)
+                        .set_unit_npc_flags(self.npc_flags())
+                        .set_unit_dynamic_flags(self.dynamic_flags())
+                        .set_object_entry(self.entry as i32)
+                        .finalize(),
+                ),
+                movement2: MovementBlock {
+                    update_flag: MovementBlock_UpdateFlag::new_living(
+                        MovementBlock_UpdateFlag_Living::Living {
+                            backwards_running_speed: 0.0,
+                            backwards_swimming_speed: 0.0,
+                            fall_time: 0.0,
+                            flags: Default::default(),
+                            living_orientation: 0.0,
+                            living_position: self.info.position,
+                            running_speed: DEFAULT_RUNNING_SPEED,
+                            swimming_speed: 0.0,
+                            timestamp: 0,
+                            turn_rate: DEFAULT_TURN_SPEED,
+                            walking_speed: DEFAULT_WALKING_SPEED,
+                        },
                     ),
-                    movement2: MovementBlock {
-                        update_flag: MovementBlock_UpdateFlag::new_living(
-                            MovementBlock_UpdateFlag_Living::Living {
-                                backwards_running_speed: 0.0,
-                                backwards_swimming_speed: 0.0,
-                                fall_time: 0.0,
-                                flags: Default::default(),
-                                living_orientation: 0.0,
-                                living_position: self.info.position,
-                                running_speed: DEFAULT_RUNNING_SPEED,
-                                swimming_speed: 0.0,
-                                timestamp: 0,
-                                turn_rate: DEFAULT_TURN_SPEED,
-                                walking_speed: DEFAULT_WALKING_SPEED,
-                            },
-                        ),
-                    },
-                    object_type: ObjectType::Unit,
                 },
-            }],
+                object_type: ObjectType::Unit,
+            },
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wolf(respawn_time: Duration) -> Creature {
+        let template = CreatureTemplate {
+            entry: 69,
+            name: "Diseased Timber Wolf".to_string(),
+            sub_name: String::new(),
+            min_level: 1,
+            max_level: 2,
+            display_ids: vec![646],
+            faction_template: 16,
+            npc_flags: 0,
+            creature_type: 1,
+            family: CreatureFamily::try_from(1).unwrap(),
+            rank: 0,
+            health: 55,
+            min_damage: 1.0,
+            max_damage: 3.0,
+        };
+        let spawn = CreatureSpawn {
+            entry: 69,
+            position: Position {
+                map: Map::EasternKingdoms,
+                x: -8949.95,
+                y: -132.49,
+                z: 83.53,
+                orientation: 0.0,
+            },
+            respawn_time,
+        };
+
+        Creature::new(&template, &spawn, Guid::new(1))
+    }
+
+    #[test]
+    fn dies_despawns_and_respawns() {
+        let mut creature = wolf(Duration::from_secs(90));
+        let died = Instant::now();
+
+        assert_eq!(creature.update_state(died), None);
+        assert_eq!(creature.dynamic_flags(), 0);
+
+        creature.info.position.x += 10.0;
+        creature.set_health(0);
+        assert_eq!(creature.update_state(died), Some(CreatureEvent::Died));
+        assert!(!creature.is_alive());
+        assert!(creature.is_spawned());
+        assert_ne!(creature.dynamic_flags(), 0);
+
+        creature.set_health(10);
+        assert_eq!(creature.health, 0);
+
+        let despawn = died + CORPSE_DESPAWN_TIME;
+        assert_eq!(
+            creature.update_state(despawn - Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            creature.update_state(despawn),
+            Some(CreatureEvent::Despawned)
+        );
+        assert!(!creature.is_spawned());
+
+        let respawn = died + Duration::from_secs(90);
+        assert_eq!(
+            creature.update_state(respawn - Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            creature.update_state(respawn),
+            Some(CreatureEvent::Respawned)
+        );
+
+        assert!(creature.is_alive());
+        assert!(creature.is_spawned());
+        assert_eq!(creature.health, creature.max_health);
+        assert_eq!(creature.info.position.x, creature.spawn.position.x);
+        assert_eq!(creature.info.position.y, creature.spawn.position.y);
+        assert_eq!(creature.dynamic_flags(), 0);
+        assert_eq!(creature.update_state(respawn), None);
+    }
+
+    #[test]
+    fn corpse_despawns_before_a_short_respawn() {
+        let mut creature = wolf(Duration::from_secs(10));
+        let died = Instant::now();
+
+        creature.set_health(0);
+        creature.update_state(died);
+
+        assert_eq!(creature.update_state(died + Duration::from_secs(10)), None);
+        assert_eq!(
+            creature.update_state(died + CORPSE_DESPAWN_TIME),
+            Some(CreatureEvent::Despawned)
+        );
+        assert_eq!(
+            creature.update_state(died + CORPSE_DESPAWN_TIME),
+            Some(CreatureEvent::Respawned)
+        );
+    }
+}
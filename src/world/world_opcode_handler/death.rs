@@ -0,0 +1,539 @@
+use crate::world::database::WorldDatabase;
+use crate::world::world::client::Client;
+use crate::world::world::prepare_teleport;
+use crate::world::world_opcode_handler::aura::Aura;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::combat;
+use crate::world::world_opcode_handler::entities::Entities;
+use crate::world::world_opcode_handler::spell;
+use crate::world::world_opcode_handler::spell::cooldown;
+use crate::world::world_opcode_handler::spell::data::lookup_spell;
+use wow_world_base::geometry::distance_between;
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::{Map, PlayerGender, RaceClass, Vector3d};
+use wow_world_messages::vanilla::{
+    MovementBlock, MovementBlock_UpdateFlag, MovementBlock_UpdateFlag_Living, Object, ObjectType,
+    Object_UpdateType, Power, UpdateCorpseBuilder, UpdateMask, SMSG_DESTROY_OBJECT,
+    SMSG_FORCED_DEATH_UPDATE, SMSG_SPIRIT_HEALER_CONFIRM,
+};
+use wow_world_messages::Guid;
+
+/// Ghosts closer than this to their corpse can resurrect at it.
+const CORPSE_RECLAIM_RADIUS: f32 = 39.0;
+const SPIRIT_HEALER_RANGE: f32 = 10.0;
+const SPIRIT_HEALER_ENTRY: u32 = 6491;
+
+/// Percent of the maximum durability lost by every equipped item.
+const DEATH_DURABILITY_LOSS: i32 = 10;
+const SPIRIT_HEALER_DURABILITY_LOSS: i32 = 25;
+
+/// Percent of health and mana restored when resurrecting.
+const RESURRECTION_HEALTH: i32 = 50;
+
+pub(crate) const RESURRECTION_SICKNESS: u32 = 15007;
+/// Characters below this level are not affected by resurrection sickness.
+const RESURRECTION_SICKNESS_LEVEL: u8 = 11;
+
+pub(crate) const PLAYER_FLAGS_GHOST: i32 = 0x10;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum DeathState {
+    #[default]
+    Alive,
+    /// Dead at the place of death, waiting to release the spirit.
+    Dead,
+    /// Released, running back to the corpse.
+    Ghost,
+}
+
+impl DeathState {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            DeathState::Alive => "alive",
+            DeathState::Dead => "dead",
+            DeathState::Ghost => "ghost",
+        }
+    }
+
+    pub fn from_name(s: &str) -> Option<Self> {
+        Some(match s {
+            "alive" => Self::Alive,
+            "dead" => Self::Dead,
+            "ghost" => Self::Ghost,
+            _ => return None,
+        })
+    }
+}
+
+/// The body left behind when a dead character releases its spirit.
+///
+/// Corpses belong to the map and stay there while the owner is away or offline.
+#[derive(Debug, Copy, Clone)]
+pub struct Corpse {
+    pub guid: Guid,
+    pub owner: Guid,
+    pub race_class: RaceClass,
+    pub gender: PlayerGender,
+    pub position: Position,
+}
+
+impl Corpse {
+    pub fn new(guid: Guid, owner: &Character, position: Position) -> Self {
+        Self {
+            guid,
+            owner: owner.guid,
+            race_class: owner.race_class,
+            gender: owner.gender,
+            position,
+        }
+    }
+
+    pub fn to_create_object(&self) -> Object {
+        let display_id = self.race_class.race().display_id(self.gender);
+
+        Object {
+            update_type: Object_UpdateType::CreateObject {
+                guid3: self.guid,
+                mask2: UpdateMask::Corpse(
+                    UpdateCorpseBuilder::new()
+                        .set_object_guid(self.guid)
+                        .set_object_scale_x(1.0)
+                        .set_corpse_owner(self.owner)
+                        .set_corpse_facing(self.position.orientation)
+                        .set_corpse_pos_x(self.position.x)
+                        .set_corpse_pos_y(self.position.y)
+                        .set_corpse_pos_z(self.position.z)
+                        .set_corpse_display_id(display_id.into())
+                        .finalize(),
+                ),
+                movement2: MovementBlock {
+                    update_flag: MovementBlock_UpdateFlag::new_living(
+                        MovementBlock_UpdateFlag_Living::HasPosition {
+                            orientation: self.position.orientation,
+                            position: vector(&self.position),
+                        },
+                    ),
+                },
+                object_type: ObjectType::Corpse,
+            },
+        }
+    }
+}
+
+/// Graveyards ghosts are sent to, the closest one on the same map is used.
+const GRAVEYARDS: &[Position] = &[
+    // Northshire Valley
+    graveyard(Map::EasternKingdoms, -8944.0, -179.4, 79.4, 1.8),
+    // Goldshire
+    graveyard(Map::EasternKingdoms, -9339.5, 171.4, 61.6, 4.3),
+    // Stormwind City
+    graveyard(Map::EasternKingdoms, -9151.9, 410.9, 90.9, 0.6),
+    // Westfall, Sentinel Hill
+    graveyard(Map::EasternKingdoms, -10546.9, 1197.2, 31.7, 2.0),
+    // Coldridge Valley
+    graveyard(Map::EasternKingdoms, -6164.2, 336.3, 399.8, 0.8),
+    // Kharanos
+    graveyard(Map::EasternKingdoms, -5687.5, -515.9, 397.0, 2.5),
+    // Deathknell
+    graveyard(Map::EasternKingdoms, 1880.0, 1615.0, 94.5, 3.4),
+    // Brill
+    graveyard(Map::EasternKingdoms, 2348.7, 492.0, 33.4, 3.9),
+    // Valley of Trials
+    graveyard(Map::Kalimdor, -601.3, -4297.0, 37.8, 2.5),
+    // Razor Hill
+    graveyard(Map::Kalimdor, 340.4, -4686.3, 16.5, 1.7),
+    // Camp Narache
+    graveyard(Map::Kalimdor, -2944.6, -153.2, 65.8, 5.2),
+    // Bloodhoof Village
+    graveyard(Map::Kalimdor, -2517.8, -395.0, -1.8, 4.8),
+    // Shadowglen
+    graveyard(Map::Kalimdor, 10384.2, 811.5, 1317.5, 3.3),
+    // Dolanaar
+    graveyard(Map::Kalimdor, 9701.7, 945.1, 1291.4, 5.6),
+    // The Crossroads
+    graveyard(Map::Kalimdor, -591.0, -2526.0, 91.7, 3.8),
+];
+
+/// Instances have no graveyards, ghosts are sent to the entrance
+/// so that the corpse is one portal away.
+const INSTANCE_ENTRANCES: &[(Map, Position)] = &[
+    (
+        Map::ShadowfangKeep,
+        graveyard(Map::EasternKingdoms, -234.6, 1561.6, 76.9, 1.2),
+    ),
+    (
+        Map::StormwindStockade,
+        graveyard(Map::EasternKingdoms, -8764.8, 846.0, 87.5, 0.7),
+    ),
+    (
+        Map::Deadmines,
+        graveyard(Map::EasternKingdoms, -11208.3, 1672.5, 24.7, 1.5),
+    ),
+    (
+        Map::WailingCaverns,
+        graveyard(Map::Kalimdor, -740.1, -2214.2, 16.1, 5.6),
+    ),
+    (
+        Map::RazorfenKraul,
+        graveyard(Map::Kalimdor, -4459.4, -1660.2, 81.9, 0.8),
+    ),
+    (
+        Map::BlackfathomDeeps,
+        graveyard(Map::Kalimdor, 4249.1, 740.1, -25.0, 1.3),
+    ),
+    (
+        Map::Uldaman,
+        graveyard(Map::EasternKingdoms, -6066.7, -2955.6, 209.8, 3.2),
+    ),
+    (
+        Map::Gnomeregan,
+        graveyard(Map::EasternKingdoms, -5163.4, 927.2, 257.2, 1.4),
+    ),
+    (
+        Map::SunkenTemple,
+        graveyard(Map::EasternKingdoms, -10175.1, -3995.0, -112.9, 3.0),
+    ),
+    (
+        Map::RazorfenDowns,
+        graveyard(Map::Kalimdor, -4657.9, -2525.6, 81.4, 4.2),
+    ),
+    (
+        Map::ScarletMonastery,
+        graveyard(Map::EasternKingdoms, 2892.2, -811.3, 160.3, 5.1),
+    ),
+    (
+        Map::ZulFarrak,
+        graveyard(Map::Kalimdor, -6790.6, -2891.3, 8.9, 6.1),
+    ),
+    (
+        Map::BlackrockSpire,
+        graveyard(Map::EasternKingdoms, -7524.2, -1228.4, 285.7, 2.1),
+    ),
+    (
+        Map::BlackrockDepths,
+        graveyard(Map::EasternKingdoms, -7179.6, -922.0, 166.0, 1.8),
+    ),
+    (
+        Map::OnyxiasLair,
+        graveyard(Map::Kalimdor, -4751.6, -3752.5, 49.4, 3.2),
+    ),
+    (
+        Map::Scholomance,
+        graveyard(Map::EasternKingdoms, 1274.8, -2552.0, 90.6, 3.5),
+    ),
+    (
+        Map::ZulGurub,
+        graveyard(Map::EasternKingdoms, -11916.7, -1215.7, 92.3, 4.7),
+    ),
+    (
+        Map::Stratholme,
+        graveyard(Map::EasternKingdoms, 3392.3, -3378.5, 142.7, 0.2),
+    ),
+    (
+        Map::Mauradon,
+        graveyard(Map::Kalimdor, -1186.9, 2875.9, 85.8, 1.7),
+    ),
+    (
+        Map::RagefireChasm,
+        graveyard(Map::Kalimdor, 1811.8, -4410.5, -18.5, 5.2),
+    ),
+    (
+        Map::MoltenCore,
+        graveyard(Map::EasternKingdoms, -7510.6, -1036.7, 180.9, 0.6),
+    ),
+    (
+        Map::DireMaul,
+        graveyard(Map::Kalimdor, -3828.0, 1250.0, 160.2, 3.1),
+    ),
+    (
+        Map::BlackwingLair,
+        graveyard(Map::EasternKingdoms, -7663.4, -1218.7, 287.8, 5.3),
+    ),
+    (
+        Map::RuinsOfAhnQiraj,
+        graveyard(Map::Kalimdor, -8409.0, 1499.0, 27.7, 2.6),
+    ),
+    (
+        Map::AhnQirajTemple,
+        graveyard(Map::Kalimdor, -8234.0, 2011.0, 129.1, 0.9),
+    ),
+    (
+        Map::Naxxramas,
+        graveyard(Map::EasternKingdoms, 3125.2, -3748.0, 133.7, 5.5),
+    ),
+];
+
+const fn graveyard(map: Map, x: f32, y: f32, z: f32, orientation: f32) -> Position {
+    Position {
+        map,
+        x,
+        y,
+        z,
+        orientation,
+    }
+}
+
+fn vector(p: &Position) -> Vector3d {
+    Vector3d {
+        x: p.x,
+        y: p.y,
+        z: p.z,
+    }
+}
+
+/// The closest graveyard on the same map or the entrance of the instance.
+///
+/// Ghosts on any other map are released where they died, so the corpse can always be reached.
+fn nearest_graveyard(corpse: &Position) -> Position {
+    let position = vector(corpse);
+
+    let graveyard = GRAVEYARDS
+        .iter()
+        .filter(|g| g.map == corpse.map)
+        .min_by(|a, b| {
+            let a = distance_between(position, vector(a));
+            let b = distance_between(position, vector(b));
+            a.total_cmp(&b)
+        });
+    let entrance = || {
+        INSTANCE_ENTRANCES
+            .iter()
+            .find(|(map, _)| *map == corpse.map)
+            .map(|(_, entrance)| entrance)
+    };
+
+    graveyard.or_else(entrance).copied().unwrap_or(*corpse)
+}
+
+/// Called once the health of a living character has reached zero.
+pub(crate) async fn die(client: &mut Client, clients: &mut [Client]) {
+    if client.character().attacking {
+        combat::stop_attacking(client, clients).await;
+    }
+    spell::interrupt_cast(client, clients).await;
+    for aura in client.character_mut().remove_all_auras() {
+        cooldown::aura_removed(client, aura.spell).await;
+    }
+
+    let character = client.character_mut();
+    character.set_death_state(DeathState::Dead);
+    character.set_target(Guid::zero());
+    character.leave_combat();
+    character.set_power(0);
+    character.lose_durability(DEATH_DURABILITY_LOSS);
+
+    client.send_message(SMSG_FORCED_DEATH_UPDATE {}).await;
+}
+
+/// `CMSG_REPOP_REQUEST`, leaves a corpse and turns the character into a ghost at a graveyard.
+pub(crate) async fn release_spirit(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    db: &mut WorldDatabase,
+) {
+    if client.character().death_state != DeathState::Dead {
+        return;
+    }
+
+    let corpse = Corpse::new(db.new_guid().into(), client.character(), client.position());
+
+    let object = corpse.to_create_object();
+    for c in entities.clients() {
+        c.queue_object(object.clone());
+    }
+    client.queue_object(object);
+    entities.corpses().push(corpse);
+
+    let character = client.character_mut();
+    character.corpse = Some(corpse);
+    character.set_death_state(DeathState::Ghost);
+    // Ghosts are alive as far as the client is concerned
+    character.set_health(1);
+
+    let graveyard = nearest_graveyard(&corpse.position);
+    prepare_teleport(graveyard, client).await;
+}
+
+/// `CMSG_RECLAIM_CORPSE`, resurrects a ghost that has made it back to its corpse.
+pub(crate) async fn reclaim_corpse(client: &mut Client, entities: &mut Entities<'_>) {
+    if client.character().death_state != DeathState::Ghost {
+        return;
+    }
+
+    let Some(corpse) = client.character().corpse else {
+        return;
+    };
+
+    match client.distance_to_position(&corpse.position) {
+        Some(distance) if distance <= CORPSE_RECLAIM_RADIUS => {}
+        _ => {
+            client
+                .send_system_message("You are too far away from your corpse.")
+                .await;
+            return;
+        }
+    }
+
+    resurrect(client, entities).await;
+}
+
+/// `CMSG_GOSSIP_HELLO` on a spirit healer asks the ghost to confirm the resurrection.
+pub(crate) async fn talk_to_spirit_healer(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    guid: Guid,
+) {
+    if client.character().death_state == DeathState::Ghost
+        && spirit_healer_in_range(client, entities, guid)
+    {
+        client
+            .send_message(SMSG_SPIRIT_HEALER_CONFIRM { guid })
+            .await;
+    }
+}
+
+/// `CMSG_SPIRIT_HEALER_ACTIVATE`, resurrects at the graveyard with resurrection sickness.
+pub(crate) async fn activate_spirit_healer(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    guid: Guid,
+) {
+    if client.character().death_state != DeathState::Ghost
+        || !spirit_healer_in_range(client, entities, guid)
+    {
+        return;
+    }
+
+    client
+        .character_mut()
+        .lose_durability(SPIRIT_HEALER_DURABILITY_LOSS);
+
+    if client.character().level.as_int() >= RESURRECTION_SICKNESS_LEVEL {
+        let sickness = lookup_spell(RESURRECTION_SICKNESS)
+            .and_then(|s| Some((Aura::from_spell(s, guid)?, s.max_stacks)));
+
+        if let Some((aura, max_stacks)) = sickness {
+            spell::apply_player_aura(client, aura, max_stacks).await;
+        }
+    }
+
+    resurrect(client, entities).await;
+}
+
+fn spirit_healer_in_range(client: &Client, entities: &Entities, guid: Guid) -> bool {
+    let Some(healer) = entities.find_creature(guid) else {
+        return false;
+    };
+
+    healer.entry == SPIRIT_HEALER_ENTRY
+        && client
+            .distance_to_position(&healer.position())
+            .is_some_and(|d| d <= SPIRIT_HEALER_RANGE)
+}
+
+/// Sends the corpses on the map to a character arriving on it.
+///
+/// The corpse of the character is put back if the map was unloaded since the character died,
+/// and a corpse left behind by resurrecting on another map is removed.
+pub(crate) async fn send_corpses(client: &mut Client, entities: &mut Entities<'_>) {
+    let character = client.character();
+    let owner = character.guid;
+    let corpse = character.corpse.filter(|c| c.position.map == character.map);
+
+    let stale: Vec<Guid> = entities
+        .corpses()
+        .iter()
+        .filter(|c| c.owner == owner && corpse.is_none_or(|corpse| corpse.guid != c.guid))
+        .map(|c| c.guid)
+        .collect();
+    entities.corpses().retain(|c| !stale.contains(&c.guid));
+
+    for guid in stale {
+        for c in entities.clients() {
+            c.flush_objects().await;
+            c.send_message(SMSG_DESTROY_OBJECT { guid }).await;
+        }
+    }
+
+    let missing = corpse.filter(|corpse| entities.corpses().iter().all(|c| c.guid != corpse.guid));
+    if let Some(corpse) = missing {
+        let object = corpse.to_create_object();
+        for c in entities.clients() {
+            c.queue_object(object.clone());
+        }
+        entities.corpses().push(corpse);
+    }
+
+    for corpse in entities.corpses().iter() {
+        client.queue_object(corpse.to_create_object());
+    }
+}
+
+async fn resurrect(client: &mut Client, entities: &mut Entities<'_>) {
+    let character = client.character_mut();
+    character.set_death_state(DeathState::Alive);
+    character.set_health(character.max_health() * RESURRECTION_HEALTH / 100);
+    let power = match character.power_type() {
+        Power::Mana => character.max_power() * RESURRECTION_HEALTH / 100,
+        Power::Rage => 0,
+        _ => character.max_power(),
+    };
+    character.set_power(power);
+
+    let Some(corpse) = character.corpse.take() else {
+        return;
+    };
+
+    // Resurrecting at a spirit healer on another map leaves the corpse behind
+    let corpses = entities.corpses();
+    let Some(index) = corpses.iter().position(|c| c.guid == corpse.guid) else {
+        return;
+    };
+    corpses.remove(index);
+
+    let message = SMSG_DESTROY_OBJECT { guid: corpse.guid };
+    for c in entities.clients() {
+        c.flush_objects().await;
+        c.send_message(message.clone()).await;
+    }
+    client.flush_objects().await;
+    client.send_message(message).await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::world::world::instances::InstanceKind;
+
+    #[test]
+    fn every_instance_has_an_entrance() {
+        let instances = (0..1000)
+            .filter_map(|id| Map::try_from(id).ok())
+            .filter(|map| InstanceKind::for_map(*map).is_some());
+
+        for map in instances {
+            let corpse = graveyard(map, 0.0, 0.0, 0.0, 0.0);
+            assert_ne!(nearest_graveyard(&corpse).map, map, "{map}");
+        }
+    }
+
+    #[test]
+    fn nearest_graveyard_on_the_same_map() {
+        let goldshire = graveyard(Map::EasternKingdoms, -9460.0, 62.0, 56.0, 0.0);
+
+        let graveyard = nearest_graveyard(&goldshire);
+
+        assert_eq!(graveyard.map, Map::EasternKingdoms);
+        assert_eq!((graveyard.x, graveyard.y), (-9339.5, 171.4));
+    }
+
+    #[test]
+    fn released_at_the_corpse_without_graveyards() {
+        let corpse = graveyard(Map::DevelopmentLand, 10.0, 20.0, 30.0, 0.0);
+
+        let graveyard = nearest_graveyard(&corpse);
+
+        assert_eq!(graveyard.map, Map::DevelopmentLand);
+        assert_eq!((graveyard.x, graveyard.y), (10.0, 20.0));
+    }
+}
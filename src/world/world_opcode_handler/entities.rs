@@ -1,5 +1,6 @@
 use crate::world::world::client::Client;
 use crate::world::world_opcode_handler::creature::Creature;
+use crate::world::world_opcode_handler::death::Corpse;
 use wow_world_base::shared::Guid;
 use wow_world_base::vanilla::position::Position;
 
@@ -12,11 +13,20 @@ pub(crate) enum Entity<'a> {
 pub(crate) struct Entities<'a> {
     clients: &'a mut [Client],
     creatures: &'a mut [Creature],
+    corpses: &'a mut Vec<Corpse>,
 }
 
 impl<'a> Entities<'a> {
-    pub(crate) fn new(clients: &'a mut [Client], creatures: &'a mut [Creature]) -> Self {
-        Self { clients, creatures }
+    pub(crate) fn new(
+        clients: &'a mut [Client],
+        creatures: &'a mut [Creature],
+        corpses: &'a mut Vec<Corpse>,
+    ) -> Self {
+        Self {
+            clients,
+            creatures,
+            corpses,
+        }
     }
 
     pub(crate) fn clients(&mut self) -> &mut [Client] {
@@ -27,6 +37,10 @@ impl<'a> Entities<'a> {
         self.creatures
     }
 
+    pub(crate) fn corpses(&mut self) -> &mut Vec<Corpse> {
+        self.corpses
+    }
+
     pub(crate) fn find_guid(&self, guid: Guid) -> Option<Entity> {
         if let Some(c) = self.find_player(guid) {
             Some(Entity::Player(c))
@@ -45,6 +59,14 @@ impl<'a> Entities<'a> {
         self.creatures.iter().find(|c| c.guid == guid)
     }
 
+    pub(crate) fn find_player_mut(&mut self, guid: Guid) -> Option<&mut Client> {
+        self.clients.iter_mut().find(|c| c.character().guid == guid)
+    }
+
+    pub(crate) fn find_creature_mut(&mut self, guid: Guid) -> Option<&mut Creature> {
+        self.creatures.iter_mut().find(|c| c.guid == guid)
+    }
+
     pub(crate) fn find_position(&self, guid: Guid) -> Option<Position> {
         if let Some(c) = self.find_guid(guid) {
             Some(match c {
@@ -0,0 +1,489 @@
+use crate::world::world::client::Client;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::inventory::{is_bag_slot, ItemPosition};
+use crate::world::world_opcode_handler::item::Item;
+use wow_items::vanilla::InventoryType;
+use wow_world_base::vanilla::{ItemClassAndSubClass, ItemSlot, Skill};
+use wow_world_messages::vanilla::{
+    SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult, SMSG_INVENTORY_CHANGE_FAILURE,
+};
+use wow_world_messages::Guid;
+
+/// Why an item can not be equipped, moved, split or paid for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum EquipError {
+    Level {
+        required_level: u32,
+    },
+    /// Not usable by the class or race of the character.
+    NeverUsable,
+    Proficiency,
+    DualWield,
+    TwoHanded,
+    Unique,
+    WrongSlot,
+    /// Special bags only take items of their family, and bags can not be put inside of themselves.
+    WrongBag,
+    BagNotEmpty,
+    BagFull,
+    ItemNotFound,
+    /// Splits have to leave at least one item in the stack.
+    SplitAmount,
+    CantSplit,
+    NotEnoughMoney,
+}
+
+impl EquipError {
+    pub(crate) fn to_result(self, item: Guid) -> SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult {
+        type InventoryResult = SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult;
+
+        let bag_type_subclass = 0;
+        let item1 = item;
+        let item2 = Guid::zero();
+
+        match self {
+            EquipError::Level { required_level } => InventoryResult::CantEquipLevelI {
+                required_level,
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::NeverUsable => InventoryResult::YouCanNeverUseThatItem {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::Proficiency => InventoryResult::NoRequiredProficiency {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::DualWield => InventoryResult::CantDualWield {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::TwoHanded => InventoryResult::CantEquipWithTwohanded {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::Unique => InventoryResult::CantCarryMoreOfThis {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::WrongSlot => InventoryResult::ItemDoesntGoToSlot {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::WrongBag => InventoryResult::ItemDoesntGoIntoBag {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::BagNotEmpty => InventoryResult::CanOnlyDoWithEmptyBags {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::BagFull => InventoryResult::BagFull {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::ItemNotFound => InventoryResult::ItemNotFound {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::SplitAmount => InventoryResult::TriedToSplitMoreThanCount {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::CantSplit => InventoryResult::CouldntSplitItems {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+            EquipError::NotEnoughMoney => InventoryResult::NotEnoughMoney {
+                bag_type_subclass,
+                item1,
+                item2,
+            },
+        }
+    }
+}
+
+pub(crate) async fn send_inventory_failure(client: &mut Client, error: EquipError, item: Guid) {
+    client
+        .send_message(SMSG_INVENTORY_CHANGE_FAILURE {
+            result: error.to_result(item),
+        })
+        .await;
+}
+
+/// Equipment and bag slots, everything before the backpack.
+fn is_equipment_slot(slot: ItemSlot) -> bool {
+    slot.as_int() < ItemSlot::Inventory0.as_int()
+}
+
+/// The weapon or armor skill needed to equip items of the subclass, if any.
+const fn required_skill(class: ItemClassAndSubClass) -> Option<Skill> {
+    Some(match class {
+        ItemClassAndSubClass::OneHandedAxe => Skill::Axes,
+        ItemClassAndSubClass::TwoHandedAxe => Skill::TwoHandedAxes,
+        ItemClassAndSubClass::Bow => Skill::Bows,
+        ItemClassAndSubClass::Gun => Skill::Guns,
+        ItemClassAndSubClass::OneHandedMace => Skill::Maces,
+        ItemClassAndSubClass::TwoHandedMace => Skill::TwoHandedMaces,
+        ItemClassAndSubClass::Polearm => Skill::Polearms,
+        ItemClassAndSubClass::OneHandedSword => Skill::Swords,
+        ItemClassAndSubClass::TwoHandedSword => Skill::TwoHandedSwords,
+        ItemClassAndSubClass::Staff => Skill::Staves,
+        ItemClassAndSubClass::FistWeapon => Skill::FistWeapons,
+        ItemClassAndSubClass::Dagger => Skill::Daggers,
+        ItemClassAndSubClass::Thrown => Skill::Thrown,
+        ItemClassAndSubClass::Crossbow => Skill::Crossbows,
+        ItemClassAndSubClass::Wand => Skill::Wands,
+        ItemClassAndSubClass::ClothArmor => Skill::Cloth,
+        ItemClassAndSubClass::LeatherArmor => Skill::Leather,
+        ItemClassAndSubClass::MailArmor => Skill::Mail,
+        ItemClassAndSubClass::PlateArmor => Skill::PlateMail,
+        ItemClassAndSubClass::Shield => Skill::Shield,
+        _ => return None,
+    })
+}
+
+/// Masks with no bits set, or every bit set, allow everybody.
+fn mask_allows(mask: i32, id: u8) -> bool {
+    mask <= 0 || mask & (1 << (id - 1)) != 0
+}
+
+/// Checks whether the character may put the item in the slot.
+///
+/// Slots outside of the equipment and bag slots take any item.
+pub(crate) fn check_equip(
+    character: &Character,
+    item: &Item,
+    slot: ItemSlot,
+) -> Result<(), EquipError> {
+    if !is_equipment_slot(slot) {
+        return Ok(());
+    }
+
+    let inventory_type = item.item.inventory_type();
+    if !character
+        .inventory
+        .get_equipment_slots(inventory_type)
+        .contains(&slot)
+    {
+        return Err(EquipError::WrongSlot);
+    }
+
+    let required_level: i32 = item.item.required_level().into();
+    if i32::from(character.level.as_int()) < required_level {
+        return Err(EquipError::Level {
+            required_level: required_level as u32,
+        });
+    }
+
+    let class = character.race_class.class().as_int();
+    let race = character.race_class.race().as_int();
+    if !mask_allows(item.item.allowed_class().as_int() as i32, class)
+        || !mask_allows(item.item.allowed_race().as_int() as i32, race)
+    {
+        return Err(EquipError::NeverUsable);
+    }
+
+    let skills = &character.skills;
+    if let Some(skill) = required_skill(item.item.class_and_sub_class()) {
+        if !skills.contains(&skill) {
+            return Err(EquipError::Proficiency);
+        }
+    }
+
+    let off_hand_weapon = matches!(
+        inventory_type,
+        InventoryType::Weapon | InventoryType::WeaponOffHand
+    );
+    if slot == ItemSlot::OffHand && off_hand_weapon && !skills.contains(&Skill::DualWield) {
+        return Err(EquipError::DualWield);
+    }
+
+    let max_count: i32 = item.item.max_count().into();
+    if max_count > 0 {
+        let equipped = character
+            .inventory
+            .equipment()
+            .into_iter()
+            .filter(|(other, other_slot)| {
+                *other_slot != slot
+                    && other
+                        .is_some_and(|o| o.guid != item.guid && o.item.entry() == item.item.entry())
+            })
+            .count();
+
+        if equipped as i32 >= max_count {
+            return Err(EquipError::Unique);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the item may be moved to the position, both for equipment and bags.
+///
+/// Bags with items in them can only be moved between the bag slots.
+pub(crate) fn check_move(
+    character: &Character,
+    item: &Item,
+    from: ItemPosition,
+    to: ItemPosition,
+) -> Result<(), EquipError> {
+    let inventory = &character.inventory;
+
+    if let Some(from_slot) = from.item_slot() {
+        let not_empty = inventory
+            .bag_contents(from_slot)
+            .iter()
+            .any(Option::is_some);
+        if not_empty && !to.item_slot().is_some_and(is_bag_slot) {
+            return Err(EquipError::BagNotEmpty);
+        }
+
+        if to.bag == from_slot.as_int() {
+            return Err(EquipError::WrongBag);
+        }
+    }
+
+    if !inventory.can_hold(to, item) {
+        return Err(EquipError::WrongBag);
+    }
+
+    match to.item_slot() {
+        Some(slot) => check_equip(character, item, slot),
+        None => Ok(()),
+    }
+}
+
+/// Checks a swap requested by the client, both items have to be allowed in the position they end up in.
+///
+/// Unlike auto equipping, a swap never moves a third item,
+/// so two-handed weapons and off-hand items can not be equipped together.
+pub(crate) fn check_swap(
+    character: &Character,
+    source: ItemPosition,
+    destination: ItemPosition,
+) -> Result<(), SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult> {
+    let inventory = &character.inventory;
+
+    for (from, to) in [(source, destination), (destination, source)] {
+        let Some(item) = inventory.get_at(from) else {
+            continue;
+        };
+
+        let two_handed_conflict = match to.item_slot() {
+            Some(ItemSlot::MainHand) => {
+                item.item.inventory_type() == InventoryType::TwoHandedWeapon
+                    && inventory.is_occupied(ItemSlot::OffHand)
+            }
+            Some(ItemSlot::OffHand) => {
+                inventory.get_inventory_type(ItemSlot::MainHand)
+                    == Some(InventoryType::TwoHandedWeapon)
+            }
+            _ => false,
+        };
+
+        check_move(character, item, from, to)
+            .and_then(|()| {
+                if two_handed_conflict {
+                    Err(EquipError::TwoHanded)
+                } else {
+                    Ok(())
+                }
+            })
+            .map_err(|e| e.to_result(item.guid))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::world::database::WorldDatabase;
+    use crate::world::world_opcode_handler::inventory::Inventory;
+    use wow_items::vanilla::lookup_item;
+    use wow_world_base::vanilla::{Level, PlayerGender, RaceClass};
+
+    type InventoryResult = SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult;
+
+    fn character(race_class: RaceClass, level: u8) -> (Character, WorldDatabase) {
+        let mut db = WorldDatabase::in_memory();
+        let mut c = Character::new(
+            &mut db,
+            "Test",
+            race_class,
+            PlayerGender::Male,
+            0,
+            0,
+            0,
+            0,
+            0,
+        );
+        c.inventory = Inventory::empty();
+        c.set_level(Level::new(level));
+
+        (c, db)
+    }
+
+    /// The first item that fits the description.
+    fn item(
+        db: &mut WorldDatabase,
+        description: impl Fn(&wow_world_base::vanilla::Item) -> bool,
+    ) -> Item {
+        let item = (1..30_000)
+            .filter_map(lookup_item)
+            .find(|i| {
+                description(i) && i.max_count() == 0 && i.allowed_class().as_int() as i32 <= 0
+            })
+            .unwrap();
+
+        Item::new(item, Guid::zero(), 1, db)
+    }
+
+    fn slot_for(c: &Character, item: &Item) -> ItemSlot {
+        c.inventory.get_equipment_slots(item.item.inventory_type())[0]
+    }
+
+    fn plate(db: &mut WorldDatabase) -> Item {
+        item(db, |i| {
+            i.class_and_sub_class() == ItemClassAndSubClass::PlateArmor && i.required_level() <= 40
+        })
+    }
+
+    fn one_handed_sword(db: &mut WorldDatabase) -> Item {
+        item(db, |i| {
+            i.class_and_sub_class() == ItemClassAndSubClass::OneHandedSword
+                && i.inventory_type() == InventoryType::Weapon
+                && i.required_level() <= 1
+        })
+    }
+
+    #[test]
+    fn plate_is_learned_at_40() {
+        let (mut c, mut db) = character(RaceClass::HumanWarrior, 39);
+        let plate = plate(&mut db);
+        let slot = slot_for(&c, &plate);
+
+        assert!(!c.skills.contains(&Skill::PlateMail));
+
+        c.set_level(Level::new(40));
+        assert_eq!(check_equip(&c, &plate, slot), Ok(()));
+    }
+
+    #[test]
+    fn proficiency_comes_from_learned_skills() {
+        let (mut c, mut db) = character(RaceClass::HumanWarrior, 60);
+        let plate = plate(&mut db);
+        let slot = slot_for(&c, &plate);
+
+        c.skills.retain(|s| *s != Skill::PlateMail);
+        assert_eq!(check_equip(&c, &plate, slot), Err(EquipError::Proficiency));
+
+        let (mage, _) = character(RaceClass::HumanMage, 60);
+        assert_eq!(
+            check_equip(&mage, &plate, slot),
+            Err(EquipError::Proficiency)
+        );
+    }
+
+    #[test]
+    fn dual_wield_is_learned_at_20() {
+        let (mut c, mut db) = character(RaceClass::HumanWarrior, 19);
+        let sword = one_handed_sword(&mut db);
+
+        assert_eq!(
+            check_equip(&c, &sword, ItemSlot::OffHand),
+            Err(EquipError::DualWield)
+        );
+        assert_eq!(check_equip(&c, &sword, ItemSlot::MainHand), Ok(()));
+
+        c.set_level(Level::new(20));
+        assert_eq!(check_equip(&c, &sword, ItemSlot::OffHand), Ok(()));
+    }
+
+    #[test]
+    fn level_and_slot_requirements() {
+        let (c, mut db) = character(RaceClass::HumanWarrior, 1);
+        let sword = one_handed_sword(&mut db);
+        let high_level = item(&mut db, |i| {
+            i.class_and_sub_class() == ItemClassAndSubClass::OneHandedSword
+                && i.required_level() > 1
+        });
+
+        assert_eq!(
+            check_equip(&c, &sword, ItemSlot::Head),
+            Err(EquipError::WrongSlot)
+        );
+        assert_eq!(
+            check_equip(&c, &high_level, ItemSlot::MainHand),
+            Err(EquipError::Level {
+                required_level: high_level.item.required_level() as u32
+            })
+        );
+        // Anything goes in the backpack
+        assert_eq!(check_equip(&c, &high_level, ItemSlot::Inventory0), Ok(()));
+    }
+
+    #[test]
+    fn swap_two_handed_with_off_hand_equipped() {
+        let (mut c, mut db) = character(RaceClass::HumanWarrior, 60);
+        let two_handed = item(&mut db, |i| {
+            i.inventory_type() == InventoryType::TwoHandedWeapon
+                && required_skill(i.class_and_sub_class()).is_some_and(|s| c.skills.contains(&s))
+                && i.required_level() <= 60
+        });
+        let off_hand = one_handed_sword(&mut db);
+
+        let backpack = ItemPosition::slot(ItemSlot::Inventory0);
+        let main_hand = ItemPosition::slot(ItemSlot::MainHand);
+        c.inventory.set(backpack, two_handed);
+        c.inventory
+            .set(ItemPosition::slot(ItemSlot::OffHand), off_hand);
+
+        assert!(matches!(
+            check_swap(&c, backpack, main_hand),
+            Err(InventoryResult::CantEquipWithTwohanded { .. })
+        ));
+
+        c.inventory.remove(ItemPosition::slot(ItemSlot::OffHand));
+        assert!(check_swap(&c, backpack, main_hand).is_ok());
+    }
+
+    #[test]
+    fn swap_checks_both_items() {
+        let (mut c, mut db) = character(RaceClass::HumanMage, 60);
+        let plate = plate(&mut db);
+        let cloth = item(&mut db, |i| {
+            i.class_and_sub_class() == ItemClassAndSubClass::ClothArmor
+                && i.inventory_type() == plate.item.inventory_type()
+                && i.required_level() <= 60
+        });
+
+        let backpack = ItemPosition::slot(ItemSlot::Inventory0);
+        let equipped = ItemPosition::slot(slot_for(&c, &cloth));
+        c.inventory.set(backpack, plate);
+        c.inventory.set(equipped, cloth);
+
+        // The cloth may go to the backpack, but the plate can not be equipped
+        assert!(matches!(
+            check_swap(&c, equipped, backpack),
+            Err(InventoryResult::NoRequiredProficiency { .. })
+        ));
+        assert!(check_swap(&c, backpack, ItemPosition::slot(ItemSlot::Inventory1)).is_ok());
+    }
+}
@@ -1,17 +1,19 @@
 mod parser;
 
+use crate::shutdown;
 use crate::world::database::WorldDatabase;
 use crate::world::world;
 use crate::world::world::client::Client;
-use crate::world::world::pathfinding_maps::PathfindingMaps;
+use crate::world::world::tick_metrics::TICK_METRICS;
 use crate::world::world_opcode_handler::entities::{Entities, Entity};
 use crate::world::world_opcode_handler::gm_command::parser::GmCommand;
-use crate::world::world_opcode_handler::item::{award_item, Item};
+use crate::world::world_opcode_handler::item::{award_item, max_stack, Item};
+use namigator::vanilla::VanillaMap;
 use wow_world_base::vanilla::position::Position;
-use wow_world_base::vanilla::{SplineFlag, Vector3d};
+use wow_world_base::vanilla::{NewItemSource, SplineFlag, Vector3d};
 use wow_world_messages::vanilla::{
     CompressedMove, CompressedMove_CompressedMoveOpcode, MonsterMove, MonsterMove_MonsterMoveType,
-    SMSG_COMPRESSED_MOVES, SMSG_FORCE_RUN_SPEED_CHANGE, SMSG_SPLINE_SET_RUN_SPEED,
+    SMSG_COMPRESSED_MOVES,
 };
 
 pub(crate) async fn gm_command(
@@ -19,7 +21,7 @@ pub(crate) async fn gm_command(
     entities: &mut Entities<'_>,
     message: &str,
     mut db: &mut WorldDatabase,
-    maps: &mut PathfindingMaps,
+    pathfinding: Option<&VanillaMap>,
 ) {
     let command = match GmCommand::from_player_command(message, client, entities) {
         Ok(e) => e,
@@ -45,24 +47,48 @@ pub(crate) async fn gm_command(
         GmCommand::Teleport(p) => {
             world::prepare_teleport(p, client).await;
         }
-        GmCommand::SetRunSpeed(speed) => {
-            client.character_mut().movement_speed = speed;
+        GmCommand::Shutdown(after) => {
+            shutdown::request(after);
+
+            info!(
+                session: client.session(),
+                "{} scheduled a shutdown in {after:?}",
+                client.character().name
+            );
             client
-                .send_message(SMSG_FORCE_RUN_SPEED_CHANGE {
-                    guid: client.character().guid,
-                    move_event: 0,
-                    speed,
-                })
+                .send_system_message(format!(
+                    "Shutting down in {}",
+                    shutdown::format_remaining(after.as_secs())
+                ))
                 .await;
+        }
+        GmCommand::CancelShutdown => {
+            if shutdown::cancel() {
+                info!(
+                    session: client.session(),
+                    "{} cancelled the shutdown",
+                    client.character().name
+                );
+                client.send_system_message("Shutdown cancelled").await;
+            } else {
+                client.send_system_message("No shutdown is scheduled").await;
+            }
+        }
+        GmCommand::TickStats => {
+            let summary = TICK_METRICS.lock().unwrap().summary();
 
-            for c in entities.clients() {
-                c.send_message(SMSG_SPLINE_SET_RUN_SPEED {
-                    guid: client.character().guid,
-                    speed,
-                })
-                .await;
+            for line in summary.lines() {
+                client.send_system_message(line.to_string()).await;
             }
         }
+        GmCommand::Die => {
+            if client.character().is_alive() {
+                client.character_mut().set_health(0);
+            }
+        }
+        GmCommand::SetRunSpeed(speed) => {
+            client.character_mut().set_movement_speed(speed);
+        }
         GmCommand::Mark { names, p } => {
             use crate::file_utils::append_string_to_file;
             use std::fmt::Write;
@@ -94,7 +120,12 @@ pub(crate) async fn gm_command(
             )
             .unwrap();
 
-            println!("{} added {}", client.character().name, msg);
+            info!(
+                session: client.session(),
+                "{} added {}",
+                client.character().name,
+                msg.trim_end()
+            );
             append_string_to_file(&msg, Path::new("unadded_locations.txt"));
 
             let msg = format!("You added {}", msg);
@@ -106,14 +137,29 @@ pub(crate) async fn gm_command(
                 .send_system_message(format!("Range to target: '{}'", range))
                 .await;
         }
-        GmCommand::AddItem(item) => {
-            const AMOUNT: u8 = 1;
+        GmCommand::AddItem(item, mut amount) => {
+            let max_stack = u32::from(max_stack(item));
 
-            let item = Item::new(item, client.character().guid, AMOUNT, &mut db);
+            // One full stack at a time
+            while amount > 0 {
+                let stack = amount.min(max_stack);
+                let item = Item::new(item, client.character().guid, stack as u8, &mut db);
 
-            award_item(item, client, entities.clients()).await;
+                if !award_item(item, NewItemSource::Looted, client, entities.clients()).await {
+                    break;
+                }
+                amount -= stack;
+            }
         }
         GmCommand::MoveNpc => {
+            let Some(creature) = entities.creatures().first() else {
+                client
+                    .send_system_message("No creatures on this map to move")
+                    .await;
+                return;
+            };
+            let guid = creature.guid;
+
             client
                 .send_message(SMSG_COMPRESSED_MOVES {
                     moves: vec![CompressedMove {
@@ -136,7 +182,7 @@ pub(crate) async fn gm_command(
                                 },
                             },
                         },
-                        guid: entities.creatures()[0].guid,
+                        guid,
                     }],
                 })
                 .await;
@@ -187,7 +233,7 @@ pub(crate) async fn gm_command(
             };
             let other = o.position();
 
-            let f = if let Some(map) = maps.get(&pos.map) {
+            let f = if let Some(map) = pathfinding {
                 let los = map.line_of_sight(pos.into(), other.into()).unwrap();
                 if los {
                     client
@@ -1,5 +1,6 @@
 use crate::world::world::client::Client;
 use crate::world::world_opcode_handler::entities::{Entities, Entity};
+use std::time::Duration;
 use wow_items::vanilla::{lookup_item, lookup_item_by_name};
 use wow_world_base::geometry::trace_point_2d;
 use wow_world_base::shared::Guid;
@@ -10,13 +11,21 @@ pub(crate) enum GmCommand {
     WhereAmI,
     Teleport(Position),
     SetRunSpeed(f32),
-    Mark { names: Vec<String>, p: Position },
+    Mark {
+        names: Vec<String>,
+        p: Position,
+    },
     RangeToTarget(f32),
-    AddItem(&'static Item),
+    /// Item and amount.
+    AddItem(&'static Item, u32),
     MoveNpc,
     Information(Guid),
     ShouldHaveLineOfSight(Guid),
     ShouldNotHaveLineOfSight(Guid),
+    TickStats,
+    Die,
+    Shutdown(Duration),
+    CancelShutdown,
 }
 
 impl GmCommand {
@@ -47,6 +56,23 @@ impl GmCommand {
             Self::Teleport(p)
         } else if message == "whereami" {
             Self::WhereAmI
+        } else if message == "tickstats" {
+            Self::TickStats
+        } else if message == "die" {
+            Self::Die
+        } else if let Some(seconds) = message.strip_prefix("server shutdown") {
+            let seconds = seconds.trim();
+
+            if seconds == "cancel" {
+                Self::CancelShutdown
+            } else {
+                let seconds = parse_int(seconds, "seconds")?;
+                if seconds < 0 {
+                    return Err(format!("Unable to shut down in {seconds} seconds"));
+                }
+
+                Self::Shutdown(Duration::from_secs(seconds as u64))
+            }
         } else if let Some(target) = message.strip_prefix("info") {
             let target = target.trim();
 
@@ -210,18 +236,28 @@ impl GmCommand {
 
             Self::Teleport(p)
         } else if let Some(entry) = message.strip_prefix("additem") {
-            let entry = if let Ok(entry) = entry.trim().parse::<u32>() {
+            // An optional amount comes after the entry or name
+            let split = entry.trim().rsplit_once(' ');
+            let (entry, amount) = match split.map(|(e, a)| (e, a.parse::<u32>())) {
+                Some((entry, Ok(amount))) => (entry.trim(), amount),
+                _ => (entry.trim(), 1),
+            };
+            if amount == 0 {
+                return Err("Unable to additem: amount must be at least 1".to_string());
+            }
+
+            let entry = if let Ok(entry) = entry.parse::<u32>() {
                 let Some(item) = lookup_item(entry) else {
                     return Err(format!("Unable to additem: No item with id '{entry}'"));
                 };
                 item
-            } else if let Some(item) = lookup_item_by_name(entry.trim()) {
+            } else if let Some(item) = lookup_item_by_name(entry) {
                 item
             } else {
                 return Err(format!("Unable to additem: '{entry}' is not a valid entry"));
             };
 
-            Self::AddItem(entry)
+            Self::AddItem(entry, amount)
         } else if message == "move" {
             Self::MoveNpc
         } else if message == "los" {
@@ -1,20 +1,76 @@
 use crate::world::database::WorldDatabase;
 use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::update_fields::Field;
 use wow_items::vanilla::{lookup_item, InventoryType};
-use wow_world_base::vanilla::{Guid, ItemSlot, StarterItem};
-use wow_world_messages::vanilla::CharacterGear;
+use wow_world_base::vanilla::{BagFamily, Guid, ItemSlot, StarterItem};
+use wow_world_messages::vanilla::{CharacterGear, Object};
 
 const AMOUNT_OF_SLOTS: usize = 113;
+const AMOUNT_OF_BAGS: usize = 4;
+
+/// Bag index used by the client for the slots of the character itself.
+pub const CHARACTER_BAG: u8 = 0xff;
+
+/// Where an item is, as sent by the client.
+///
+/// `bag` is either [`CHARACTER_BAG`], where `slot` is an [`ItemSlot`],
+/// or the [`ItemSlot`] of an equipped bag, where `slot` is the index inside the bag.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ItemPosition {
+    pub bag: u8,
+    pub slot: u8,
+}
+
+impl ItemPosition {
+    pub const fn new(bag: u8, slot: u8) -> Self {
+        Self { bag, slot }
+    }
+
+    pub const fn slot(slot: ItemSlot) -> Self {
+        Self::new(CHARACTER_BAG, slot.as_int())
+    }
+
+    /// The slot of the character, `None` for positions inside a bag.
+    pub fn item_slot(self) -> Option<ItemSlot> {
+        if self.bag == CHARACTER_BAG {
+            ItemSlot::try_from(self.slot).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Index into [`Inventory::bags`] if this is one of the slots bags are equipped in.
+    fn equipped_bag(self) -> Option<usize> {
+        self.item_slot().and_then(bag_index)
+    }
+}
+
+fn bag_index(slot: ItemSlot) -> Option<usize> {
+    let index = slot.as_int().checked_sub(ItemSlot::Bag1.as_int())?;
+    (usize::from(index) < AMOUNT_OF_BAGS).then_some(usize::from(index))
+}
+
+pub fn is_bag_slot(slot: ItemSlot) -> bool {
+    bag_index(slot).is_some()
+}
 
 #[derive(Debug, Clone)]
 pub struct Inventory {
     pub slots: [Option<Item>; AMOUNT_OF_SLOTS],
+    /// Contents of the bags in [`ItemSlot::Bag1`] to [`ItemSlot::Bag4`], empty if no bag is equipped.
+    bags: [Vec<Option<Item>>; AMOUNT_OF_BAGS],
 }
 
 impl Inventory {
+    pub fn empty() -> Self {
+        Self {
+            slots: [(); AMOUNT_OF_SLOTS].map(|()| None),
+            bags: Default::default(),
+        }
+    }
+
     pub fn new(starter_items: &[StarterItem], db: &mut WorldDatabase) -> Self {
-        let slots = [(); AMOUNT_OF_SLOTS].map(|()| None);
-        let mut s = Self { slots };
+        let mut s = Self::empty();
 
         for item in starter_items {
             let i = Item::new(
@@ -23,53 +79,318 @@ impl Inventory {
                 item.amount,
                 db,
             );
-            s.set(item.ty, i);
+            s.set(ItemPosition::slot(item.ty), i);
         }
 
         s
     }
 
-    pub fn swap(&mut self, source: ItemSlot, destination: ItemSlot) {
-        let source_temp = self.take(source);
-        let dest_temp = self.take(destination);
+    pub fn swap(&mut self, source: ItemPosition, destination: ItemPosition) {
+        if !self.is_valid(source) || !self.is_valid(destination) {
+            return;
+        }
+
+        let source_temp = self.take_at(source);
+        let dest_temp = self.take_at(destination);
+
+        self.put_at(source, dest_temp);
+        self.put_at(destination, source_temp);
+
+        if source.bag != destination.bag {
+            for position in [source, destination] {
+                if let Some(item) = self.get_at_mut(position) {
+                    item.dirty.mark(Field::Contained);
+                }
+            }
+        }
+
+        // Bags keep their contents when moved between bag slots,
+        // bags moved anywhere else are empty
+        match (source.equipped_bag(), destination.equipped_bag()) {
+            (Some(a), Some(b)) => self.bags.swap(a, b),
+            (Some(i), None) | (None, Some(i)) => self.resize_bag(i),
+            (None, None) => {}
+        }
+    }
+
+    fn resize_bag(&mut self, index: usize) {
+        let bag = ItemSlot::try_from(ItemSlot::Bag1.as_int() + index as u8).unwrap();
+        let size = self.get(bag).map(|b| b.container_slots()).unwrap_or(0);
+
+        self.bags[index] = vec![None; size];
+    }
+
+    /// Contents of the bag equipped in the slot, empty if there is none.
+    pub fn bag_contents(&self, bag: ItemSlot) -> &[Option<Item>] {
+        bag_index(bag)
+            .map(|i| self.bags[i].as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether the position exists, slots past the size of a bag do not.
+    pub fn is_valid(&self, position: ItemPosition) -> bool {
+        self.inner_get_at(position).is_some()
+    }
+
+    fn inner_get_at(&self, position: ItemPosition) -> Option<&Option<Item>> {
+        match position.item_slot() {
+            Some(slot) => Some(self.inner_get(slot)),
+            None => {
+                let bag = ItemSlot::try_from(position.bag).ok().and_then(bag_index)?;
+                self.bags[bag].get(usize::from(position.slot))
+            }
+        }
+    }
+
+    fn slot_at_mut(&mut self, position: ItemPosition) -> Option<&mut Option<Item>> {
+        match position.item_slot() {
+            Some(slot) => Some(self.get_mut(slot)),
+            None => {
+                let bag = ItemSlot::try_from(position.bag).ok().and_then(bag_index)?;
+                self.bags[bag].get_mut(usize::from(position.slot))
+            }
+        }
+    }
 
-        *self.get_mut(source) = dest_temp;
-        *self.get_mut(destination) = source_temp;
+    pub fn get_at(&self, position: ItemPosition) -> Option<&Item> {
+        self.inner_get_at(position).and_then(Option::as_ref)
     }
 
-    fn get_inventory_slots(&self) -> &[Option<Item>] {
-        &self.slots
-            [ItemSlot::Inventory0.as_int() as usize..=ItemSlot::Inventory15.as_int() as usize]
+    pub fn get_at_mut(&mut self, position: ItemPosition) -> Option<&mut Item> {
+        self.slot_at_mut(position).and_then(Option::as_mut)
     }
 
-    fn get_inventory_slots_mut(&mut self) -> &mut [Option<Item>] {
-        &mut self.slots
-            [ItemSlot::Inventory0.as_int() as usize..=ItemSlot::Inventory15.as_int() as usize]
+    fn take_at(&mut self, position: ItemPosition) -> Option<Item> {
+        self.slot_at_mut(position).and_then(Option::take)
     }
 
-    pub fn get_first_free_slot(&self) -> Option<ItemSlot> {
-        self.get_inventory_slots()
+    fn put_at(&mut self, position: ItemPosition, item: Option<Item>) {
+        if let Some(slot) = self.slot_at_mut(position) {
+            *slot = item;
+        }
+    }
+
+    /// The bag holding the position, `None` for the slots of the character.
+    pub fn bag_guid(&self, position: ItemPosition) -> Option<Guid> {
+        let bag = ItemSlot::try_from(position.bag).ok()?;
+        self.get(bag).map(|b| b.guid)
+    }
+
+    /// Equipped bags together with their contents.
+    pub fn equipped_bags(&self) -> impl Iterator<Item = (&Item, &[Option<Item>])> {
+        let bag_start = usize::from(ItemSlot::Bag1.as_int());
+
+        self.slots[bag_start..bag_start + AMOUNT_OF_BAGS]
             .iter()
-            .position(Option::is_none)
-            .map(|i| {
-                ItemSlot::try_from((ItemSlot::Inventory0.as_int() as usize + i) as u8).unwrap()
+            .zip(self.bags.iter())
+            .filter_map(|(bag, contents)| bag.as_ref().map(|b| (b, contents.as_slice())))
+    }
+
+    /// Create objects for every item, bags are created with their contents.
+    pub fn to_create_item_objects(&self, owner: Guid) -> Vec<Object> {
+        self.items()
+            .map(|(position, item)| {
+                let contained = self.bag_guid(position).unwrap_or(owner);
+                let contents = position
+                    .item_slot()
+                    .map(|slot| self.bag_contents(slot))
+                    .unwrap_or(&[]);
+
+                item.to_create_item_object(owner, contained, contents)
             })
+            .collect()
     }
 
-    pub fn insert_into_first_slot(&mut self, item: Item) -> Option<ItemSlot> {
-        let slots = self.get_inventory_slots_mut();
+    /// Finds the position of an item anywhere in the inventory.
+    pub fn find(&self, guid: Guid) -> Option<ItemPosition> {
+        self.items()
+            .find(|(_, item)| item.guid == guid)
+            .map(|(position, _)| position)
+    }
+
+    /// Every item, including the items inside of bags.
+    pub fn items(&self) -> impl Iterator<Item = (ItemPosition, &Item)> {
+        let slots = self
+            .all_slots()
+            .into_iter()
+            .filter_map(|(item, slot)| item.map(|i| (ItemPosition::slot(slot), i)));
+        let bags = self.bags.iter().enumerate().flat_map(|(bag, contents)| {
+            contents.iter().enumerate().filter_map(move |(slot, item)| {
+                let position = ItemPosition::new(ItemSlot::Bag1.as_int() + bag as u8, slot as u8);
+                item.as_ref().map(|i| (position, i))
+            })
+        });
+
+        slots.chain(bags)
+    }
+
+    pub fn items_mut(&mut self) -> impl Iterator<Item = &mut Item> {
+        self.slots
+            .iter_mut()
+            .chain(self.bags.iter_mut().flatten())
+            .flatten()
+    }
+
+    /// The backpack followed by the equipped bags, the places looted and bought items go.
+    fn storage_positions(&self) -> impl Iterator<Item = ItemPosition> + '_ {
+        let backpack = (ItemSlot::Inventory0.as_int()..=ItemSlot::Inventory15.as_int())
+            .map(|slot| ItemPosition::new(CHARACTER_BAG, slot));
 
-        for (i, slot) in slots.iter_mut().enumerate() {
-            if slot.is_none() {
-                *slot = Some(item);
+        let bags = self.bags.iter().enumerate().flat_map(|(bag, contents)| {
+            (0..contents.len())
+                .map(move |slot| ItemPosition::new(ItemSlot::Bag1.as_int() + bag as u8, slot as u8))
+        });
 
-                let bag_start = ItemSlot::Inventory0.as_int() as usize;
-                let slot = bag_start + i;
-                return Some(ItemSlot::try_from(slot as u8).unwrap());
+        backpack.chain(bags)
+    }
+
+    /// Whether the item may be put in the position, special bags only take items of their family.
+    pub fn can_hold(&self, position: ItemPosition, item: &Item) -> bool {
+        let Some(bag) = ItemSlot::try_from(position.bag)
+            .ok()
+            .and_then(|b| self.get(b))
+        else {
+            return self.is_valid(position);
+        };
+
+        self.is_valid(position)
+            && (bag.item.bag_family() == BagFamily::None
+                || bag.item.bag_family() == item.item.bag_family())
+    }
+
+    /// The first free position the item can be stored in, optionally only inside of one bag.
+    pub fn first_free_position(&self, item: &Item, bag: Option<u8>) -> Option<ItemPosition> {
+        self.storage_positions()
+            .filter(|p| bag.is_none_or(|b| p.bag == b))
+            .find(|p| self.get_at(*p).is_none() && self.can_hold(*p, item))
+    }
+
+    /// Removes the item at the position, bags are only removed empty.
+    pub fn remove(&mut self, position: ItemPosition) -> Option<Item> {
+        let item = self.take_at(position);
+
+        if let Some(bag) = position.equipped_bag() {
+            self.resize_bag(bag);
+        }
+
+        item
+    }
+
+    /// Positions of stacks that the item can be added to.
+    fn stacks_for(&self, item: &Item) -> Vec<ItemPosition> {
+        self.storage_positions()
+            .filter(|p| self.get_at(*p).is_some_and(|i| i.stacks_with(item)))
+            .collect()
+    }
+
+    /// Whether [`Inventory::store`] would succeed.
+    pub fn has_room(&self, item: &Item) -> bool {
+        let room: u32 = self
+            .stacks_for(item)
+            .iter()
+            .filter_map(|p| self.get_at(*p))
+            .map(|i| u32::from(i.max_stack() - i.amount))
+            .sum();
+
+        u32::from(item.amount) <= room || self.first_free_position(item, None).is_some()
+    }
+
+    /// Adds the item to existing stacks of the same item first, and what is left to a free position.
+    ///
+    /// Returns the positions that changed, or `None` without changing anything if there is no room.
+    /// A position holding the item itself is a new stack, the other positions had their amount raised.
+    pub fn store(&mut self, mut item: Item) -> Option<Vec<ItemPosition>> {
+        if !self.has_room(&item) {
+            return None;
+        }
+
+        let stacks = self.stacks_for(&item);
+        let free = self.first_free_position(&item, None);
+
+        let mut changed = Vec::new();
+        for position in stacks {
+            if item.amount == 0 {
+                break;
             }
+
+            let Some(stack) = self.get_at_mut(position) else {
+                continue;
+            };
+            let added = item.amount.min(stack.max_stack() - stack.amount);
+            stack.set_amount(stack.amount + added);
+            item.amount -= added;
+            changed.push(position);
+        }
+
+        if let Some(position) = free.filter(|_| item.amount > 0) {
+            self.put_at(position, Some(item));
+            changed.push(position);
         }
 
-        None
+        Some(changed)
+    }
+
+    /// Moves as much of the source stack onto the destination stack as fits.
+    ///
+    /// Returns the source item if all of it was moved, in which case it is removed.
+    pub fn merge(&mut self, source: ItemPosition, destination: ItemPosition) -> Option<Item> {
+        let amount = self.get_at(source)?.amount;
+        let stack = self.get_at_mut(destination)?;
+        let moved = amount.min(stack.max_stack() - stack.amount);
+        stack.set_amount(stack.amount + moved);
+
+        if moved == amount {
+            self.remove(source)
+        } else {
+            let source = self.get_at_mut(source)?;
+            source.set_amount(amount - moved);
+            None
+        }
+    }
+
+    /// Amount of items with the entry in the backpack and bags, summed over all stacks.
+    pub fn count_items(&self, entry: u32) -> u32 {
+        self.storage_positions()
+            .filter_map(|p| self.get_at(p))
+            .filter(|i| i.item.entry() == entry)
+            .map(|i| u32::from(i.amount))
+            .sum()
+    }
+
+    /// Removes up to `amount` items with the entry from the backpack and bags.
+    ///
+    /// Returns the positions that changed, together with the item if the whole stack was removed.
+    pub fn remove_items(
+        &mut self,
+        entry: u32,
+        mut amount: u32,
+    ) -> Vec<(ItemPosition, Option<Item>)> {
+        let positions: Vec<_> = self.storage_positions().collect();
+        let mut changed = Vec::new();
+
+        for position in positions {
+            if amount == 0 {
+                break;
+            }
+
+            let Some(slot) = self.slot_at_mut(position) else {
+                continue;
+            };
+            let Some(item) = slot.as_mut().filter(|i| i.item.entry() == entry) else {
+                continue;
+            };
+
+            if u32::from(item.amount) > amount {
+                item.set_amount(item.amount - amount as u8);
+                amount = 0;
+                changed.push((position, None));
+            } else {
+                amount -= u32::from(item.amount);
+                changed.push((position, slot.take()));
+            }
+        }
+
+        changed
     }
 
     pub fn all_slots(&self) -> [(Option<&Item>, ItemSlot); AMOUNT_OF_SLOTS] {
@@ -98,6 +419,15 @@ impl Inventory {
         slots
     }
 
+    pub fn equipment_mut(&mut self) -> impl Iterator<Item = &mut Item> {
+        let inventory_start: usize = ItemSlot::Head.as_int().into();
+        let inventory_end: usize = ItemSlot::Tabard.as_int().into();
+
+        self.slots[inventory_start..=inventory_end]
+            .iter_mut()
+            .flatten()
+    }
+
     pub fn to_character_gear(&self) -> [CharacterGear; 19] {
         let mut gear = [CharacterGear::default(); 19];
 
@@ -119,16 +449,12 @@ impl Inventory {
         gear
     }
 
-    pub fn set(&mut self, item_slot: ItemSlot, item: Item) {
-        *self.get_mut(item_slot) = Some(item);
-    }
-
-    pub fn clear(&mut self, item_slot: ItemSlot) {
-        *self.get_mut(item_slot) = None;
-    }
+    pub fn set(&mut self, position: ItemPosition, item: Item) {
+        self.put_at(position, Some(item));
 
-    pub fn take(&mut self, item_slot: ItemSlot) -> Option<Item> {
-        self.get_mut(item_slot).take()
+        if let Some(bag) = position.equipped_bag() {
+            self.resize_bag(bag);
+        }
     }
 
     pub fn get(&self, item_slot: ItemSlot) -> Option<&Item> {
@@ -155,10 +481,6 @@ impl Inventory {
         !self.is_free(item_slot)
     }
 
-    pub fn is_inventory_full(&self) -> bool {
-        self.get_inventory_slots().iter().all(Option::is_some)
-    }
-
     /// Returns a list of possible equipment slots for a given `InventoryType`.
     ///
     /// This is used to determine which slots an item of a specific type can be equipped to.
@@ -203,3 +525,95 @@ impl Inventory {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::world::world_opcode_handler::update_fields::DirtyFields;
+
+    /// Refreshing Spring Water, which stacks.
+    fn water(guid: u64, amount: u8) -> Item {
+        Item {
+            item: lookup_item(159).unwrap(),
+            guid: Guid::new(guid),
+            amount,
+            creator: Guid::zero(),
+            durability: 0,
+            dirty: DirtyFields::default(),
+        }
+    }
+
+    fn backpack(index: u8) -> ItemPosition {
+        ItemPosition::new(CHARACTER_BAG, ItemSlot::Inventory0.as_int() + index)
+    }
+
+    fn amount(inventory: &Inventory, position: ItemPosition) -> Option<u8> {
+        inventory.get_at(position).map(|i| i.amount)
+    }
+
+    #[test]
+    fn store_fills_stacks_before_a_free_slot() {
+        let max = water(0, 1).max_stack();
+        let mut inventory = Inventory::empty();
+        inventory.set(backpack(0), water(1, max - 2));
+        inventory.set(backpack(1), water(2, max - 1));
+
+        let changed = inventory.store(water(3, 5)).unwrap();
+
+        assert_eq!(changed, vec![backpack(0), backpack(1), backpack(2)]);
+        assert_eq!(amount(&inventory, backpack(0)), Some(max));
+        assert_eq!(amount(&inventory, backpack(1)), Some(max));
+        assert_eq!(amount(&inventory, backpack(2)), Some(2));
+        assert_eq!(inventory.count_items(159), u32::from(max) * 2 + 2);
+    }
+
+    #[test]
+    fn store_in_stacks_when_full() {
+        let max = water(0, 1).max_stack();
+        let mut inventory = Inventory::empty();
+        inventory.set(backpack(0), water(1, max - 3));
+        for index in 1..16 {
+            inventory.set(backpack(index), water(u64::from(index) + 1, max));
+        }
+
+        assert_eq!(inventory.store(water(20, 4)), None);
+        assert_eq!(amount(&inventory, backpack(0)), Some(max - 3));
+
+        assert_eq!(inventory.store(water(21, 3)), Some(vec![backpack(0)]));
+        assert_eq!(amount(&inventory, backpack(0)), Some(max));
+    }
+
+    #[test]
+    fn partial_merge_keeps_the_rest() {
+        let max = water(0, 1).max_stack();
+        let mut inventory = Inventory::empty();
+        inventory.set(backpack(0), water(1, 5));
+        inventory.set(backpack(1), water(2, max - 2));
+
+        assert!(inventory.merge(backpack(0), backpack(1)).is_none());
+        assert_eq!(amount(&inventory, backpack(0)), Some(3));
+        assert_eq!(amount(&inventory, backpack(1)), Some(max));
+
+        inventory.set(backpack(2), water(3, 1));
+        let merged = inventory.merge(backpack(0), backpack(2)).unwrap();
+        assert_eq!(merged.guid, Guid::new(1));
+        assert_eq!(amount(&inventory, backpack(0)), None);
+        assert_eq!(amount(&inventory, backpack(2)), Some(4));
+    }
+
+    #[test]
+    fn remove_items_across_stacks() {
+        let mut inventory = Inventory::empty();
+        inventory.set(backpack(0), water(1, 2));
+        inventory.set(backpack(1), water(2, 5));
+
+        let changed = inventory.remove_items(159, 4);
+
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[0].0, backpack(0));
+        assert!(changed[0].1.is_some());
+        assert_eq!(changed[1].0, backpack(1));
+        assert!(changed[1].1.is_none());
+        assert_eq!(amount(&inventory, backpack(1)), Some(3));
+    }
+}
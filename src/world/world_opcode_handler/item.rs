@@ -1,20 +1,30 @@
 use crate::world::database::WorldDatabase;
 use crate::world::world::client::Client;
-use wow_world_base::vanilla::{
-    BagFamily, NewItemChatAlert, NewItemCreationType, NewItemSource, ObjectType,
-};
+use crate::world::world_opcode_handler::inventory::ItemPosition;
+use crate::world::world_opcode_handler::update_fields::{DirtyFields, Field};
+use wow_world_base::vanilla::{NewItemChatAlert, NewItemCreationType, NewItemSource, ObjectType};
 use wow_world_messages::vanilla::{
-    MovementBlock, MovementBlock_UpdateFlag, Object, Object_UpdateType, UpdateItemBuilder,
-    UpdatePlayerBuilder, SMSG_ITEM_PUSH_RESULT, SMSG_UPDATE_OBJECT,
+    MovementBlock, MovementBlock_UpdateFlag, Object, Object_UpdateType, UpdateContainerBuilder,
+    UpdateItemBuilder, SMSG_ITEM_PUSH_RESULT,
 };
 use wow_world_messages::Guid;
 
+/// Durability points times item level that cost one copper to repair.
+const REPAIR_COST_DIVISOR: u32 = 4;
+
+/// Most items of the kind that fit in one stack, at least one.
+pub fn max_stack(item: &wow_world_base::vanilla::Item) -> u8 {
+    u8::try_from(item.stackable()).unwrap_or(u8::MAX).max(1)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Item {
     pub item: &'static wow_world_base::vanilla::Item,
     pub guid: Guid,
     pub amount: u8,
     pub creator: Guid,
+    pub durability: i32,
+    pub dirty: DirtyFields,
 }
 
 impl Item {
@@ -23,37 +33,144 @@ impl Item {
         creator: Guid,
         amount: u8,
         db: &mut WorldDatabase,
+    ) -> Self {
+        Self::with_guid(item, db.new_guid().into(), creator, amount)
+    }
+
+    /// Does not take a guid, which allows checking where an item fits before creating it.
+    pub fn with_guid(
+        item: &'static wow_world_base::vanilla::Item,
+        guid: Guid,
+        creator: Guid,
+        amount: u8,
     ) -> Self {
         Self {
             item,
-            guid: db.new_guid().into(),
+            guid,
             amount,
             creator,
+            durability: item.max_durability(),
+            dirty: DirtyFields::default(),
         }
     }
 
-    pub fn to_create_item_object(&self, item_owner: Guid) -> Object {
-        let object_type = match self.item.bag_family() {
-            BagFamily::None => ObjectType::Item,
-            _ => ObjectType::Container,
+    /// Lowers durability by a percentage of the maximum.
+    ///
+    /// Returns `true` if the item broke.
+    pub fn lose_durability(&mut self, percent: i32) -> bool {
+        self.damage(self.item.max_durability() * percent / 100)
+    }
+
+    /// Lowers durability by a number of points.
+    ///
+    /// Returns `true` if the item broke.
+    pub fn damage(&mut self, points: i32) -> bool {
+        if self.item.max_durability() == 0 || self.durability == 0 || points <= 0 {
+            return false;
+        }
+
+        self.durability = (self.durability - points).max(0);
+        self.dirty.mark(Field::Durability);
+
+        self.is_broken()
+    }
+
+    /// Broken items stay equipped but do nothing until repaired.
+    pub fn is_broken(&self) -> bool {
+        self.item.max_durability() > 0 && self.durability == 0
+    }
+
+    /// Copper needed to restore the item to full durability.
+    pub fn repair_cost(&self) -> u32 {
+        let missing = (self.item.max_durability() - self.durability).max(0) as u32;
+        let item_level: i32 = self.item.item_level().into();
+
+        (missing * item_level.max(1) as u32).div_ceil(REPAIR_COST_DIVISOR)
+    }
+
+    /// Restores full durability.
+    ///
+    /// Returns `true` if the item was broken.
+    pub fn repair(&mut self) -> bool {
+        let was_broken = self.is_broken();
+
+        self.durability = self.item.max_durability();
+        self.dirty.mark(Field::Durability);
+
+        was_broken
+    }
+
+    pub fn max_stack(&self) -> u8 {
+        max_stack(self.item)
+    }
+
+    /// Whether the other item can be added to this stack.
+    pub fn stacks_with(&self, other: &Item) -> bool {
+        self.guid != other.guid
+            && self.item.entry() == other.item.entry()
+            && self.amount < self.max_stack()
+    }
+
+    /// Sets the amount and marks it for sending.
+    pub fn set_amount(&mut self, amount: u8) {
+        self.amount = amount;
+        self.dirty.mark(Field::StackCount);
+    }
+
+    /// Slots of a bag, zero for items that are not containers.
+    pub fn container_slots(&self) -> usize {
+        self.item.container_slots() as usize
+    }
+
+    /// Creates the item for its owner.
+    ///
+    /// `contained` is the bag the item is in, or the owner for items that are not in a bag.
+    /// `contents` are the items in the bag if the item is an equipped bag.
+    pub fn to_create_item_object(
+        &self,
+        item_owner: Guid,
+        contained: Guid,
+        contents: &[Option<Item>],
+    ) -> Object {
+        let (mask, object_type) = if self.container_slots() > 0 {
+            let mut mask = UpdateContainerBuilder::new()
+                .set_object_guid(self.guid)
+                .set_object_entry(self.item.entry() as i32)
+                .set_object_scale_x(1.0)
+                .set_item_owner(item_owner)
+                .set_item_contained(contained)
+                .set_item_stack_count(self.amount as i32)
+                .set_item_creator(self.creator)
+                .set_container_num_slots(self.container_slots() as i32);
+
+            for (slot, item) in contents.iter().enumerate() {
+                if let Some(item) = item {
+                    mask = mask.set_container_slot(slot, item.guid);
+                }
+            }
+
+            (mask.finalize().into(), ObjectType::Container)
+        } else {
+            let mask = UpdateItemBuilder::new()
+                .set_object_guid(self.guid)
+                .set_object_entry(self.item.entry() as i32)
+                .set_object_scale_x(1.0)
+                .set_item_owner(item_owner)
+                .set_item_contained(contained)
+                .set_item_stack_count(self.amount as i32)
+                .set_item_durability(self.durability)
+                .set_item_maxdurability(self.item.max_durability())
+                .set_item_creator(self.creator)
+                .finalize()
+                .into();
+
+            (mask, ObjectType::Item)
         };
 
         Object {
             update_type: Object_UpdateType::CreateObject {
                 guid3: self.guid,
-                mask2: UpdateItemBuilder::new()
-                    .set_object_guid(self.guid)
-                    .set_object_entry(self.item.entry() as i32)
-                    .set_object_scale_x(1.0)
-                    .set_item_owner(item_owner)
-                    .set_item_contained(item_owner)
-                    .set_item_stack_count(self.amount as i32)
-                    .set_item_durability(self.item.max_durability())
-                    .set_item_maxdurability(self.item.max_durability())
-                    .set_item_creator(self.creator)
-                    .set_item_stack_count(self.amount as i32)
-                    .finalize()
-                    .into(),
+                mask2: mask,
                 movement2: MovementBlock {
                     update_flag: MovementBlock_UpdateFlag::empty(),
                 },
@@ -63,46 +180,59 @@ impl Item {
     }
 }
 
-pub(crate) async fn award_item(item: Item, client: &mut Client, clients: &mut [Client]) {
-    let item_slot = client
-        .character_mut()
-        .inventory
-        .insert_into_first_slot(item);
-    let Some(item_slot) = item_slot else {
+/// Stores the item, adding it to existing stacks first, and tells everybody about it.
+///
+/// Returns `false` if there is no room for all of it.
+pub(crate) async fn award_item(
+    item: Item,
+    source: NewItemSource,
+    client: &mut Client,
+    clients: &mut [Client],
+) -> bool {
+    let changed = client.character_mut().inventory.store(item);
+    let Some(changed) = changed else {
         client
             .send_system_message("Unable to add item. No free slots available.")
             .await;
-        return;
+        return false;
     };
 
-    client
-        .send_opcode(
-            &SMSG_UPDATE_OBJECT {
-                has_transport: 0,
-                objects: vec![
-                    item.to_create_item_object(client.character().guid),
-                    Object {
-                        update_type: Object_UpdateType::Values {
-                            guid1: client.character().guid,
-                            mask1: UpdatePlayerBuilder::new()
-                                .set_player_field_inv(item_slot, item.guid)
-                                .finalize()
-                                .into(),
-                        },
-                    },
-                ],
-            }
-            .into(),
-        )
-        .await;
+    let owner = client.character().guid;
+    for position in changed.iter().copied() {
+        let inventory = &client.character().inventory;
+        // Whatever was not added to other stacks is a new item
+        let new = inventory.get_at(position).filter(|i| i.guid == item.guid);
+        if let Some(new) = new.copied() {
+            let contained = inventory.bag_guid(position).unwrap_or(owner);
+            client.queue_object(new.to_create_item_object(owner, contained, &[]));
+            client.character_mut().mark_position_dirty(position);
+        }
+    }
+    // The client ignores push results for items it has not been sent yet
+    client.flush_objects().await;
+
+    if let Some(position) = changed.last().copied() {
+        send_item_push_result(&item, position, source, client, clients).await;
+    }
+
+    true
+}
 
+/// Tells everybody that the character received the item in the position.
+pub(crate) async fn send_item_push_result(
+    item: &Item,
+    position: ItemPosition,
+    source: NewItemSource,
+    client: &mut Client,
+    clients: &mut [Client],
+) {
     let item_push_result = SMSG_ITEM_PUSH_RESULT {
         guid: client.character().guid,
-        source: NewItemSource::Looted,
+        source,
         creation_type: NewItemCreationType::Created,
         alert_chat: NewItemChatAlert::Show,
-        bag_slot: 0xff,
-        item_slot: item_slot.as_int() as u32,
+        bag_slot: position.bag,
+        item_slot: u32::from(position.slot),
         item: item.item.entry(),
         item_suffix_factor: 0,
         item_random_property_id: 0,
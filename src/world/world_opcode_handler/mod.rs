@@ -1,33 +1,61 @@
 use crate::file_utils::append_string_to_file;
+use crate::metrics::METRICS;
 use crate::world::database::WorldDatabase;
 use crate::world::world::client::Client;
-use crate::world::world::pathfinding_maps::PathfindingMaps;
+use crate::world::world::tick_metrics::TickProfile;
 use crate::world::world_opcode_handler::entities::Entities;
 use crate::world::world_opcode_handler::opcode_handler::handle_opcodes;
+use namigator::vanilla::VanillaMap;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use walkdir::WalkDir;
 use wow_world_messages::vanilla::opcodes::{ClientOpcodeMessage, ServerOpcodeMessage};
 use wow_world_messages::vanilla::ServerMessage;
 
+pub mod aura;
 pub mod character;
 pub mod chat;
+pub(crate) mod combat;
 pub mod creature;
+pub mod death;
 pub(crate) mod entities;
+pub(crate) mod equip;
 pub(crate) mod gm_command;
 pub mod inventory;
 pub(crate) mod item;
+mod movement;
 mod opcode_handler;
+pub(crate) mod regeneration;
+pub mod spell;
+pub mod update_fields;
+pub(crate) mod vendor;
 
 pub(crate) async fn handle_received_client_opcodes(
     client: &mut Client,
     entities: &mut Entities<'_>,
     db: &mut WorldDatabase,
     move_to_character_screen: &mut bool,
-    maps: &mut PathfindingMaps,
+    pathfinding: Option<&VanillaMap>,
+    profile: &mut TickProfile,
 ) {
     while let Ok(opcode) = client.received_messages().try_recv() {
-        handle_opcodes(client, entities, db, move_to_character_screen, opcode, maps).await;
+        let start = Instant::now();
+        let opcode_name = opcode.message_name();
+        METRICS.record_opcode(opcode_name);
+
+        handle_opcodes(
+            client,
+            entities,
+            db,
+            move_to_character_screen,
+            opcode,
+            pathfinding,
+        )
+        .await;
+
+        let name = &client.character().name;
+        profile.record_opcode(name, opcode_name, start.elapsed());
     }
 }
 
@@ -53,7 +81,7 @@ pub(crate) fn write_client_test(msg: &ClientOpcodeMessage) {
     if let Some(contents) = msg.to_test_case_string() {
         write_test_case_inner(contents.as_str(), msg.message_name());
     } else {
-        dbg!(&msg);
+        debug!("Unable to create test case for {msg:?}");
     }
 }
 
@@ -65,12 +93,12 @@ pub(crate) fn write_server_test(msg: &ServerOpcodeMessage) {
 
 pub(crate) fn write_test_case_inner(contents: &str, message_name: &str) {
     if let Some(path) = find_wowm_file(message_name) {
-        println!("Added {message_name} to {path}", path = path.display());
+        info!("Added {message_name} to {path}", path = path.display());
         append_string_to_file("\n", &path);
         append_string_to_file(&contents, &path);
     } else {
         let path = Path::new("./tests.wowm");
-        println!("Added {message_name} to {path}", path = path.display());
+        info!("Added {message_name} to {path}", path = path.display());
         append_string_to_file("\n", path);
         append_string_to_file(&contents, path);
     }
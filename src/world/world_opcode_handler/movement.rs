@@ -0,0 +1,255 @@
+use crate::config::{config, MovementViolationAction};
+use crate::world::world::client::Client;
+use crate::world::world::teleport_within_map;
+use namigator::vanilla::VanillaMap;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use wow_world_base::vanilla::position::Position;
+use wow_world_base::vanilla::Map;
+use wow_world_messages::vanilla::MovementInfo;
+
+/// Multiplier on the expected distance to account for latency and packet bunching.
+const SPEED_TOLERANCE: f32 = 1.25;
+/// Flat distance always allowed on top of the expected distance.
+const DISTANCE_SLACK: f32 = 1.0;
+/// How far the client clock is allowed to run ahead of the server clock, in seconds.
+const MAX_CLIENT_TIME_AHEAD: f32 = 1.0;
+/// How far below the lowest terrain height a client is allowed to be.
+const TERRAIN_TOLERANCE: f32 = 3.0;
+/// Height above the feet used for line of sight checks, so that slopes do not block the ray.
+const EYE_HEIGHT: f32 = 1.5;
+/// Movement shorter than this is never checked for line of sight.
+const MIN_LINE_OF_SIGHT_DISTANCE: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum MovementViolation {
+    TooFast { distance: f32, allowed: f32 },
+    BelowTerrain { z: f32, terrain: f32 },
+    ThroughWall,
+}
+
+impl Display for MovementViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovementViolation::TooFast { distance, allowed } => {
+                write!(f, "moved {distance} yards while only {allowed} was allowed")
+            }
+            MovementViolation::BelowTerrain { z, terrain } => {
+                write!(f, "z {z} is below terrain height {terrain}")
+            }
+            MovementViolation::ThroughWall => f.write_str("moved through a wall"),
+        }
+    }
+}
+
+/// Raises a position from the feet to the eyes for line of sight checks.
+pub(crate) fn eye_position(p: Position) -> Position {
+    Position {
+        z: p.z + EYE_HEIGHT,
+        ..p
+    }
+}
+
+/// Compares the new movement against the last accepted movement on the map.
+///
+/// `since_accepted` is the time the server has seen pass since the last accepted movement.
+pub(crate) fn validate_movement(
+    old: &MovementInfo,
+    info: &MovementInfo,
+    run_speed: f32,
+    since_accepted: Duration,
+    map_id: Map,
+    pathfinding: Option<&VanillaMap>,
+) -> Result<(), MovementViolation> {
+    let dx = info.position.x - old.position.x;
+    let dy = info.position.y - old.position.y;
+    // Height is left out since falling is not limited by the run speed
+    let distance = (dx * dx + dy * dy).sqrt();
+
+    let server_elapsed = since_accepted.as_secs_f32();
+    let client_elapsed = info.timestamp.wrapping_sub(old.timestamp) as f32 / 1000.0;
+    let elapsed = server_elapsed.max(client_elapsed.min(server_elapsed + MAX_CLIENT_TIME_AHEAD));
+
+    let allowed = run_speed * elapsed * SPEED_TOLERANCE + DISTANCE_SLACK;
+    if distance > allowed {
+        return Err(MovementViolation::TooFast { distance, allowed });
+    }
+
+    let Some(map) = pathfinding else {
+        return Ok(());
+    };
+
+    if let Ok(heights) = map.find_heights(info.position.x, info.position.y) {
+        let lowest = heights.iter().copied().reduce(f32::min);
+
+        if let Some(terrain) = lowest {
+            if info.position.z < terrain - TERRAIN_TOLERANCE {
+                return Err(MovementViolation::BelowTerrain {
+                    z: info.position.z,
+                    terrain,
+                });
+            }
+        }
+    }
+
+    if distance > MIN_LINE_OF_SIGHT_DISTANCE {
+        let from = eye_position(Position::new(
+            map_id,
+            old.position.x,
+            old.position.y,
+            old.position.z,
+            old.orientation,
+        ));
+        let to = eye_position(Position::new(
+            map_id,
+            info.position.x,
+            info.position.y,
+            info.position.z,
+            info.orientation,
+        ));
+
+        if let Ok(false) = map.line_of_sight(from.into(), to.into()) {
+            return Err(MovementViolation::ThroughWall);
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates movement and applies the configured [`MovementViolationAction`].
+///
+/// Returns `false` if the movement must not be applied or forwarded to other clients.
+pub(crate) async fn accept_movement(
+    client: &mut Client,
+    info: &MovementInfo,
+    pathfinding: Option<&VanillaMap>,
+) -> bool {
+    let now = Instant::now();
+
+    let character = client.character();
+    let result = validate_movement(
+        &character.info,
+        info,
+        character.run_speed(),
+        now.duration_since(client.last_accepted_movement),
+        character.map,
+        pathfinding,
+    );
+
+    if let Err(violation) = result {
+        warn!(
+            session: client.session(),
+            "Movement violation by '{}': {violation}",
+            client.character().name
+        );
+
+        match config().movement_violation {
+            MovementViolationAction::Log => {}
+            MovementViolationAction::RubberBand => {
+                rubber_band(client).await;
+                return false;
+            }
+            MovementViolationAction::Kick => {
+                // The map removes the client on the next tick
+                client.disconnect().await;
+                return false;
+            }
+        }
+    }
+
+    client.last_accepted_movement = now;
+
+    true
+}
+
+/// Moves the client back to the last accepted position.
+async fn rubber_band(client: &mut Client) {
+    let info = client.character().info.clone();
+    teleport_within_map(client, info).await;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wow_world_base::movement::DEFAULT_RUNNING_SPEED;
+    use wow_world_base::vanilla::Vector3d;
+
+    fn at(x: f32, y: f32, z: f32, timestamp: u32) -> MovementInfo {
+        MovementInfo {
+            flags: Default::default(),
+            timestamp,
+            position: Vector3d { x, y, z },
+            orientation: 0.0,
+            fall_time: 0.0,
+        }
+    }
+
+    fn validate(
+        old: &MovementInfo,
+        new: &MovementInfo,
+        seconds: f32,
+    ) -> Result<(), MovementViolation> {
+        validate_movement(
+            old,
+            new,
+            DEFAULT_RUNNING_SPEED,
+            Duration::from_secs_f32(seconds),
+            Map::EasternKingdoms,
+            None,
+        )
+    }
+
+    #[test]
+    fn running_is_allowed() {
+        let old = at(0.0, 0.0, 0.0, 1000);
+        let new = at(DEFAULT_RUNNING_SPEED, 0.0, 0.0, 2000);
+
+        assert!(validate(&old, &new, 1.0).is_ok());
+    }
+
+    #[test]
+    fn too_fast() {
+        let old = at(0.0, 0.0, 0.0, 1000);
+        let new = at(0.0, 3.0 * DEFAULT_RUNNING_SPEED, 0.0, 2000);
+
+        assert!(matches!(
+            validate(&old, &new, 1.0),
+            Err(MovementViolation::TooFast { .. })
+        ));
+    }
+
+    #[test]
+    fn falling_is_not_limited() {
+        let old = at(0.0, 0.0, 100.0, 1000);
+        let new = at(0.0, 0.0, 0.0, 1100);
+
+        assert!(validate(&old, &new, 0.1).is_ok());
+    }
+
+    #[test]
+    fn slack_allows_small_moves_without_time() {
+        let old = at(0.0, 0.0, 0.0, 1000);
+        let new = at(DISTANCE_SLACK, 0.0, 0.0, 1000);
+
+        assert!(validate(&old, &new, 0.0).is_ok());
+    }
+
+    #[test]
+    fn client_time_is_trusted_up_to_a_limit() {
+        let old = at(0.0, 0.0, 0.0, 1000);
+        let bunched = at(DEFAULT_RUNNING_SPEED, 0.0, 0.0, 2000);
+        // Claims ten seconds passed while the server only saw a tenth of a second
+        let ahead = at(10.0 * DEFAULT_RUNNING_SPEED, 0.0, 0.0, 11000);
+
+        assert!(validate(&old, &bunched, 0.1).is_ok());
+        assert!(validate(&old, &ahead, 0.1).is_err());
+    }
+
+    #[test]
+    fn client_timestamps_wrap_around() {
+        let old = at(0.0, 0.0, 0.0, u32::MAX - 499);
+        let new = at(DEFAULT_RUNNING_SPEED, 0.0, 0.0, 500);
+
+        assert!(validate(&old, &new, 0.0).is_ok());
+    }
+}
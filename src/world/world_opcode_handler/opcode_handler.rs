@@ -1,21 +1,31 @@
 use crate::world::database::WorldDatabase;
-use crate::world::world::client::Client;
-use crate::world::world::pathfinding_maps::PathfindingMaps;
+use crate::world::world::client::{Client, TeleportStatus};
+use crate::world::world::instances::raid_instance_info;
 use crate::world::world::{announce_character_login, get_client_login_messages, prepare_teleport};
 use crate::world::world_opcode_handler::chat::handle_message;
+use crate::world::world_opcode_handler::combat;
+use crate::world::world_opcode_handler::death;
 use crate::world::world_opcode_handler::entities::Entities;
+use crate::world::world_opcode_handler::equip::{self, send_inventory_failure, EquipError};
+use crate::world::world_opcode_handler::inventory::{ItemPosition, CHARACTER_BAG};
+use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::movement::accept_movement;
+use crate::world::world_opcode_handler::spell;
+use crate::world::world_opcode_handler::spell::cooldown;
+use crate::world::world_opcode_handler::spell::data::lookup_item_spell;
+use crate::world::world_opcode_handler::vendor;
 use crate::world::world_opcode_handler::{
     gm_command, send_movement_to_clients, send_to_all, write_client_test,
 };
-use std::time::SystemTime;
+use namigator::vanilla::VanillaMap;
+use std::time::{Instant, SystemTime};
 use wow_items::vanilla::InventoryType;
-use wow_world_base::combat::UNARMED_SPEED;
 use wow_world_base::vanilla::position::{position_from_str, Position};
 use wow_world_base::vanilla::trigger::Trigger;
-use wow_world_base::vanilla::{CreatureFamily, Guid, HitInfo, ItemSlot};
+use wow_world_base::vanilla::{Guid, ItemSlot};
 use wow_world_messages::vanilla::opcodes::ClientOpcodeMessage;
 use wow_world_messages::vanilla::{
-    item_to_name_query_response, item_to_query_response, DamageInfo, LogoutResult, LogoutSpeed,
+    item_to_name_query_response, item_to_query_response, LogoutResult, LogoutSpeed,
     MSG_MOVE_FALL_LAND_Server, MSG_MOVE_HEARTBEAT_Server, MSG_MOVE_JUMP_Server,
     MSG_MOVE_SET_FACING_Server, MSG_MOVE_SET_PITCH_Server, MSG_MOVE_SET_RUN_MODE_Server,
     MSG_MOVE_SET_WALK_MODE_Server, MSG_MOVE_START_BACKWARD_Server, MSG_MOVE_START_FORWARD_Server,
@@ -23,13 +33,11 @@ use wow_world_messages::vanilla::{
     MSG_MOVE_START_STRAFE_LEFT_Server, MSG_MOVE_START_STRAFE_RIGHT_Server,
     MSG_MOVE_START_SWIM_Server, MSG_MOVE_START_TURN_LEFT_Server, MSG_MOVE_START_TURN_RIGHT_Server,
     MSG_MOVE_STOP_PITCH_Server, MSG_MOVE_STOP_STRAFE_Server, MSG_MOVE_STOP_SWIM_Server,
-    MSG_MOVE_STOP_Server, MSG_MOVE_STOP_TURN_Server, Object, Object_UpdateType,
-    SMSG_CREATURE_QUERY_RESPONSE_found, SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult, UpdateMask,
-    UpdatePlayerBuilder, VisibleItem, VisibleItemIndex, SMSG_ATTACKERSTATEUPDATE, SMSG_ATTACKSTART,
-    SMSG_ATTACKSTOP, SMSG_CREATURE_QUERY_RESPONSE, SMSG_EMOTE, SMSG_INVENTORY_CHANGE_FAILURE,
+    MSG_MOVE_STOP_Server, MSG_MOVE_STOP_TURN_Server, SMSG_CREATURE_QUERY_RESPONSE_found,
+    SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult, SMSG_ATTACKSTART, SMSG_CREATURE_QUERY_RESPONSE,
+    SMSG_DESTROY_OBJECT, SMSG_EMOTE, SMSG_INVENTORY_CHANGE_FAILURE,
     SMSG_ITEM_QUERY_SINGLE_RESPONSE, SMSG_LOGOUT_COMPLETE, SMSG_LOGOUT_RESPONSE,
     SMSG_NAME_QUERY_RESPONSE, SMSG_PONG, SMSG_QUERY_TIME_RESPONSE, SMSG_TEXT_EMOTE,
-    SMSG_UPDATE_OBJECT,
 };
 
 pub(super) async fn handle_opcodes(
@@ -38,12 +46,26 @@ pub(super) async fn handle_opcodes(
     db: &mut WorldDatabase,
     move_to_character_screen: &mut bool,
     opcode: ClientOpcodeMessage,
-    maps: &mut PathfindingMaps,
+    pathfinding: Option<&VanillaMap>,
 ) {
     let guid = client.character().guid;
 
     if let Some(info) = opcode.movement_info() {
+        if client.is_teleporting() {
+            // Sent before the client knew about the teleport
+            return;
+        }
+
+        if !accept_movement(client, info, pathfinding).await {
+            return;
+        }
+
+        let moved = info.position != client.character().info.position;
         client.character_mut().info = info.clone();
+
+        if moved {
+            spell::interrupt_cast(client, entities.clients()).await;
+        }
     }
 
     match opcode {
@@ -98,7 +120,11 @@ pub(super) async fn handle_opcodes(
                         .await;
                 }
                 Some(item) => {
-                    println!("Sending response for {}", item.name());
+                    debug!(
+                        session: client.session(),
+                        "Sending response for {}",
+                        item.name()
+                    );
                     client.send_message(item_to_query_response(item)).await;
                 }
             }
@@ -126,29 +152,36 @@ pub(super) async fn handle_opcodes(
                 .await;
         }
         ClientOpcodeMessage::CMSG_CREATURE_QUERY(c) => {
-            if let Some(creature) = entities.find_creature(c.guid) {
-                client
-                    .send_message(SMSG_CREATURE_QUERY_RESPONSE {
-                        creature_entry: c.creature,
-                        found: Some(SMSG_CREATURE_QUERY_RESPONSE_found {
-                            name1: creature.name.clone(),
-                            name2: "".to_string(),
-                            name3: "".to_string(),
-                            name4: "".to_string(),
-                            sub_name: "".to_string(),
-                            type_flags: 0,
-                            creature_type: 0,
-                            creature_family: CreatureFamily::None,
-                            creature_rank: 0,
-                            unknown0: 0,
-                            spell_data_id: 0,
-                            display_id: 0,
-                            civilian: 0,
-                            racial_leader: 0,
-                        }),
-                    })
-                    .await;
-            }
+            let found = db.creature_template(c.creature).map(|template| {
+                SMSG_CREATURE_QUERY_RESPONSE_found {
+                    name1: template.name.clone(),
+                    name2: "".to_string(),
+                    name3: "".to_string(),
+                    name4: "".to_string(),
+                    sub_name: template.sub_name.clone(),
+                    type_flags: 0,
+                    creature_type: template.creature_type,
+                    creature_family: template.family,
+                    creature_rank: template.rank,
+                    unknown0: 0,
+                    spell_data_id: 0,
+                    display_id: template
+                        .display_ids
+                        .first()
+                        .copied()
+                        .unwrap_or_default()
+                        .into(),
+                    civilian: 0,
+                    racial_leader: 0,
+                }
+            });
+
+            client
+                .send_message(SMSG_CREATURE_QUERY_RESPONSE {
+                    creature_entry: c.creature,
+                    found,
+                })
+                .await;
         }
         ClientOpcodeMessage::CMSG_WORLD_TELEPORT(c) => {
             let p = Position::new(
@@ -170,27 +203,51 @@ pub(super) async fn handle_opcodes(
                     .await;
             }
         }
+        ClientOpcodeMessage::MSG_MOVE_TELEPORT_ACK(c) => {
+            let TeleportStatus::SameMap { movement_counter } = client.teleport_status else {
+                return;
+            };
+            if movement_counter != c.movement_counter {
+                // Acknowledgement of an older teleport
+                return;
+            }
+            client.teleport_status = TeleportStatus::None;
+            client.last_accepted_movement = Instant::now();
+
+            send_movement_to_clients(
+                MSG_MOVE_HEARTBEAT_Server {
+                    guid,
+                    info: client.character().info.clone(),
+                }
+                .into(),
+                entities.clients(),
+            )
+            .await
+        }
         ClientOpcodeMessage::MSG_MOVE_WORLDPORT_ACK => {
-            if !client.in_process_of_teleport {
+            if client.teleport_status != TeleportStatus::OtherMap {
                 return;
             }
-            client.in_process_of_teleport = false;
+            client.teleport_status = TeleportStatus::None;
+            client.last_accepted_movement = Instant::now();
 
             for m in get_client_login_messages(client.character()) {
                 client.send_opcode(&m).await;
             }
 
             for c in entities.clients() {
-                announce_character_login(client, c.character()).await;
+                announce_character_login(client, c.character());
             }
 
             for c in entities.clients() {
-                announce_character_login(c, client.character()).await;
+                announce_character_login(c, client.character());
             }
 
-            for creature in entities.creatures() {
-                client.send_message(creature.to_message()).await;
+            for creature in entities.creatures().iter().filter(|c| c.is_spawned()) {
+                client.queue_object(creature.to_create_object());
             }
+
+            death::send_corpses(client, entities).await;
         }
         ClientOpcodeMessage::CMSG_MESSAGECHAT(c) => {
             if c.message.starts_with('.') {
@@ -199,7 +256,7 @@ pub(super) async fn handle_opcodes(
                     entities,
                     c.message.trim_start_matches('.'),
                     db,
-                    maps,
+                    pathfinding,
                 )
                 .await;
 
@@ -223,7 +280,7 @@ pub(super) async fn handle_opcodes(
             client.send_message(SMSG_LOGOUT_COMPLETE {}).await;
         }
         ClientOpcodeMessage::CMSG_SET_SELECTION(c) => {
-            client.character_mut().target = c.target;
+            client.character_mut().set_target(c.target);
         }
         ClientOpcodeMessage::CMSG_QUERY_TIME => {
             client
@@ -404,9 +461,14 @@ pub(super) async fn handle_opcodes(
             .await
         }
         ClientOpcodeMessage::CMSG_AUTOEQUIP_ITEM(c) => {
-            // TODO: source_bag?
-            let source_slot = ItemSlot::try_from(c.source_slot as u8).unwrap();
-            handle_autoequip_item(guid, client, entities, source_slot).await;
+            handle_autoequip_item(client, ItemPosition::new(c.source_bag, c.source_slot)).await;
+        }
+        ClientOpcodeMessage::CMSG_AUTOEQUIP_ITEM_SLOT(c) => {
+            handle_autoequip_item_slot(client, c.item, c.destination_slot).await;
+        }
+        ClientOpcodeMessage::CMSG_AUTOSTORE_BAG_ITEM(c) => {
+            let source = ItemPosition::new(c.source_bag, c.source_slot);
+            handle_autostore_bag_item(client, source, c.destination_bag).await;
         }
         ClientOpcodeMessage::CMSG_MOVE_FALL_RESET(_) => {}
         ClientOpcodeMessage::CMSG_PING(c) => {
@@ -420,12 +482,12 @@ pub(super) async fn handle_opcodes(
             // Do not spam console, mangos also ignores
         }
         ClientOpcodeMessage::CMSG_ATTACKSWING(c) => {
-            client.character_mut().target = c.guid;
-            client.character_mut().attacking = true;
-            if client.character().auto_attack_timer > UNARMED_SPEED {
+            if !client.character().is_alive() {
                 return;
             }
-            client.character_mut().auto_attack_timer = UNARMED_SPEED;
+
+            client.character_mut().set_target(c.guid);
+            client.character_mut().attacking = true;
 
             send_to_all(
                 SMSG_ATTACKSTART {
@@ -437,46 +499,125 @@ pub(super) async fn handle_opcodes(
             )
             .await;
 
-            send_to_all(
-                SMSG_ATTACKERSTATEUPDATE {
-                    hit_info: HitInfo::CriticalHit,
-                    attacker: guid,
-                    target: client.character().target,
-                    total_damage: 1337,
-                    damages: vec![DamageInfo {
-                        spell_school_mask: 0,
-                        damage_float: 1332.0,
-                        damage_uint: 1332,
-                        absorb: 0,
-                        resist: 0,
-                    }],
-                    unknown1: 0,
-                    spell_id: 0,
-                    damage_state: 0,
-                    blocked_amount: 0,
-                },
-                client,
-                entities.clients(),
-            )
-            .await;
+            // Switching targets does not reset the swing timer
+            if client.character().auto_attack_timer <= 0.0 {
+                combat::melee_swing(client, entities).await;
+            }
         }
         ClientOpcodeMessage::CMSG_ATTACKSTOP => {
-            client.character_mut().attacking = false;
-
-            send_to_all(
-                SMSG_ATTACKSTOP {
-                    player: guid,
-                    enemy: client.character().target,
-                    unknown1: 0,
-                },
+            combat::stop_attacking(client, entities.clients()).await;
+        }
+        ClientOpcodeMessage::CMSG_REPOP_REQUEST => {
+            death::release_spirit(client, entities, db).await;
+        }
+        ClientOpcodeMessage::CMSG_RECLAIM_CORPSE(_) => {
+            death::reclaim_corpse(client, entities).await;
+        }
+        ClientOpcodeMessage::CMSG_GOSSIP_HELLO(c) => {
+            death::talk_to_spirit_healer(client, entities, c.guid).await;
+        }
+        ClientOpcodeMessage::CMSG_SPIRIT_HEALER_ACTIVATE(c) => {
+            death::activate_spirit_healer(client, entities, c.guid).await;
+        }
+        ClientOpcodeMessage::CMSG_LIST_INVENTORY(c) => {
+            vendor::list_inventory(client, entities, c.vendor).await;
+        }
+        ClientOpcodeMessage::CMSG_BUY_ITEM(c) => {
+            vendor::buy_item(client, entities, db, c.vendor, c.item, c.amount, None).await;
+        }
+        ClientOpcodeMessage::CMSG_BUY_ITEM_IN_SLOT(c) => {
+            let Some(position) = vendor::bag_position(client.character(), c.bag, c.bag_slot) else {
+                send_inventory_failure(client, EquipError::ItemNotFound, c.bag).await;
+                return;
+            };
+            vendor::buy_item(
                 client,
-                entities.clients(),
+                entities,
+                db,
+                c.vendor,
+                c.item,
+                c.amount,
+                Some(position),
             )
             .await;
         }
+        ClientOpcodeMessage::CMSG_SELL_ITEM(c) => {
+            vendor::sell_item(client, entities, db, c.vendor, c.item, c.amount).await;
+        }
+        ClientOpcodeMessage::CMSG_BUYBACK_ITEM(c) => {
+            vendor::buyback_item(client, entities, c.vendor, c.slot.as_int()).await;
+        }
+        ClientOpcodeMessage::CMSG_REPAIR_ITEM(c) => {
+            vendor::repair_item(client, entities, c.npc, c.item).await;
+        }
+        ClientOpcodeMessage::CMSG_CAST_SPELL(c) => {
+            spell::cast_spell(client, entities, pathfinding, c.spell, c.targets, None).await;
+        }
+        ClientOpcodeMessage::CMSG_USE_ITEM(c) => {
+            let position = ItemPosition::new(c.bag_index, c.bag_slot);
+            let item = client
+                .character()
+                .inventory
+                .get_at(position)
+                .and_then(|item| Some((*item, lookup_item_spell(item.item.entry())?)));
+
+            if let Some((item, spell)) = item {
+                spell::cast_spell(
+                    client,
+                    entities,
+                    pathfinding,
+                    spell.spell,
+                    c.targets,
+                    Some(item),
+                )
+                .await;
+            }
+        }
+        ClientOpcodeMessage::CMSG_CANCEL_CAST(_) => {
+            spell::interrupt_cast(client, entities.clients()).await;
+        }
+        ClientOpcodeMessage::CMSG_CANCEL_AURA(c) => {
+            // Only beneficial auras can be cancelled
+            let harmful = client
+                .character()
+                .auras
+                .iter()
+                .any(|a| a.spell == c.id && a.harmful);
+
+            if !harmful && client.character_mut().remove_aura(c.id).is_some() {
+                cooldown::aura_removed(client, c.id).await;
+            }
+        }
         ClientOpcodeMessage::CMSG_SWAP_INV_ITEM(c) => {
-            handle_swap_inventory_item(guid, client, entities, c.source_slot, c.destination_slot)
-                .await
+            let source = ItemPosition::slot(c.source_slot);
+            let destination = ItemPosition::slot(c.destination_slot);
+            handle_swap_inventory_item(client, source, destination).await;
+        }
+        ClientOpcodeMessage::CMSG_SPLIT_ITEM(c) => {
+            let source = ItemPosition::new(c.source_bag, c.source_slot);
+            let destination = ItemPosition::new(c.destination_bag, c.destination_slot);
+            handle_split_item(client, db, source, destination, c.amount).await;
+        }
+        ClientOpcodeMessage::CMSG_DESTROYITEM(c) => {
+            handle_destroy_item(client, ItemPosition::new(c.bag, c.slot), c.amount).await;
+        }
+        ClientOpcodeMessage::CMSG_AUTOSTORE_LOOT_ITEM(_) => {
+            // Nothing can be looted yet, so there is never an item in the loot slot
+            send_inventory_failure(client, EquipError::ItemNotFound, Guid::zero()).await;
+        }
+        ClientOpcodeMessage::CMSG_SWAP_ITEM(c) => {
+            let source = ItemPosition::new(c.source_bag, c.source_slot);
+            let destination = ItemPosition::new(c.destination_bag, c.destination_slot);
+            handle_swap_inventory_item(client, source, destination).await;
+        }
+        ClientOpcodeMessage::CMSG_REQUEST_RAID_INFO => {
+            client
+                .send_message(raid_instance_info(&client.character().instance_bindings))
+                .await;
+        }
+        ClientOpcodeMessage::CMSG_RESET_INSTANCES => {
+            // Resetting touches other maps, so it is handled by the world after the tick
+            client.reset_instances_requested = true;
         }
         ClientOpcodeMessage::CMSG_TEXT_EMOTE(v) => {
             client
@@ -511,14 +652,8 @@ pub(super) async fn handle_opcodes(
     }
 }
 
-async fn handle_autoequip_item(
-    guid: Guid,
-    client: &mut Client,
-    entities: &mut Entities<'_>,
-    source_slot: ItemSlot,
-) {
-    let Some(source_inventory_type) = client.character().inventory.get_inventory_type(source_slot)
-    else {
+async fn handle_autoequip_item(client: &mut Client, source: ItemPosition) {
+    let Some(source_item) = client.character().inventory.get_at(source).copied() else {
         // No item found in the source slot
         client
             .send_message(SMSG_INVENTORY_CHANGE_FAILURE {
@@ -531,29 +666,7 @@ async fn handle_autoequip_item(
             .await;
         return;
     };
-
-    // Handle the special case, where two items might need to be unequipped
-    if source_inventory_type == InventoryType::TwoHandedWeapon {
-        // Make sure to unequip the off-hand
-        if client.character().inventory.is_occupied(ItemSlot::OffHand) {
-            let Some(free_slot) = client.character().inventory.get_first_free_slot() else {
-                // No free slot available for the off hand
-                let source_item = client.character().inventory.get(source_slot).unwrap();
-
-                client
-                    .send_message(SMSG_INVENTORY_CHANGE_FAILURE {
-                        result: SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult::InventoryFull {
-                            bag_type_subclass: 0,
-                            item1: source_item.guid,
-                            item2: Guid::zero(),
-                        },
-                    })
-                    .await;
-                return;
-            };
-            handle_swap_inventory_item(guid, client, entities, ItemSlot::OffHand, free_slot).await;
-        }
-    }
+    let source_inventory_type = source_item.item.inventory_type();
 
     let mut equipment_slot = None;
 
@@ -576,7 +689,6 @@ async fn handle_autoequip_item(
 
     let Some(destination_slot) = equipment_slot else {
         // No destination slot available
-        let source_item = client.character().inventory.get(source_slot).unwrap();
         client
             .send_message(SMSG_INVENTORY_CHANGE_FAILURE {
                 result: SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult::NoEquipmentSlotAvailable {
@@ -588,8 +700,47 @@ async fn handle_autoequip_item(
             .await;
         return;
     };
+    let destination = ItemPosition::slot(destination_slot);
+
+    // The equipped item, if any, takes the place of the new item
+    let character = client.character();
+    let result = equip::check_move(character, &source_item, source, destination).and_then(|()| {
+        match character.inventory.get_at(destination) {
+            Some(equipped) => equip::check_move(character, equipped, destination, source),
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = result {
+        send_inventory_failure(client, e, source_item.guid).await;
+        return;
+    }
+
+    // Handle the special case, where two items might need to be unequipped
+    if source_inventory_type == InventoryType::TwoHandedWeapon {
+        // Make sure to unequip the off-hand
+        if let Some(off_hand) = client.character().inventory.get(ItemSlot::OffHand) {
+            let Some(free) = client
+                .character()
+                .inventory
+                .first_free_position(off_hand, None)
+            else {
+                // No free slot available for the off hand
+                client
+                    .send_message(SMSG_INVENTORY_CHANGE_FAILURE {
+                        result: SMSG_INVENTORY_CHANGE_FAILURE_InventoryResult::InventoryFull {
+                            bag_type_subclass: 0,
+                            item1: source_item.guid,
+                            item2: Guid::zero(),
+                        },
+                    })
+                    .await;
+                return;
+            };
+            swap_inventory_item(client, ItemPosition::slot(ItemSlot::OffHand), free);
+        }
+    }
 
-    handle_swap_inventory_item(guid, client, entities, source_slot, destination_slot).await;
+    swap_inventory_item(client, source, destination);
 
     // Special case for off-hand weapons, where a two-handed weapon might need to be unequipped
     if destination_slot == ItemSlot::OffHand {
@@ -601,72 +752,177 @@ async fn handle_autoequip_item(
         {
             // This must be free, since it would be impossible for a two-handed weapon to be equipped
             // together with an off-hand.
-            assert!(client.character().inventory.is_free(source_slot));
-            handle_swap_inventory_item(guid, client, entities, ItemSlot::MainHand, source_slot)
-                .await;
+            assert!(client.character().inventory.get_at(source).is_none());
+            swap_inventory_item(client, ItemPosition::slot(ItemSlot::MainHand), source);
         }
     }
 }
 
+/// `CMSG_AUTOSTORE_BAG_ITEM`, moves an item into the first free slot of a bag.
+async fn handle_autostore_bag_item(client: &mut Client, source: ItemPosition, bag: u8) {
+    let character = client.character();
+    let Some(item) = character.inventory.get_at(source).copied() else {
+        return;
+    };
+
+    let result = match character.inventory.first_free_position(&item, Some(bag)) {
+        Some(destination) => {
+            equip::check_move(character, &item, source, destination).map(|()| destination)
+        }
+        None if bag == CHARACTER_BAG
+            || character
+                .inventory
+                .bag_guid(ItemPosition::new(bag, 0))
+                .is_some() =>
+        {
+            Err(EquipError::BagFull)
+        }
+        None => Err(EquipError::WrongBag),
+    };
+
+    match result {
+        Ok(destination) => swap_inventory_item(client, source, destination),
+        Err(e) => send_inventory_failure(client, e, item.guid).await,
+    }
+}
+
+/// `CMSG_AUTOEQUIP_ITEM_SLOT`, equips an item in a specific slot, like dragging it to the slot.
+async fn handle_autoequip_item_slot(client: &mut Client, item: Guid, destination_slot: u8) {
+    let Some(source) = client.character().inventory.find(item) else {
+        send_inventory_failure(client, EquipError::ItemNotFound, item).await;
+        return;
+    };
+
+    let Ok(destination) = ItemSlot::try_from(destination_slot) else {
+        send_inventory_failure(client, EquipError::WrongSlot, item).await;
+        return;
+    };
+
+    handle_swap_inventory_item(client, source, ItemPosition::slot(destination)).await;
+}
+
 async fn handle_swap_inventory_item(
-    guid: Guid,
     client: &mut Client,
-    entities: &mut Entities<'_>,
-    source_slot: ItemSlot,
-    destination_slot: ItemSlot,
+    source: ItemPosition,
+    destination: ItemPosition,
 ) {
-    client
-        .character_mut()
-        .inventory
-        .swap(source_slot, destination_slot);
-    let mut player = UpdatePlayerBuilder::new()
-        .set_player_field_inv(
-            source_slot,
-            client
-                .character()
-                .inventory
-                .get(source_slot)
-                .map(|a| a.guid)
-                .unwrap_or(Guid::zero()),
-        )
-        .set_player_field_inv(
-            destination_slot,
-            client
-                .character()
-                .inventory
-                .get(destination_slot)
-                .map(|a| a.guid)
-                .unwrap_or(Guid::zero()),
-        );
-
-    for (i, (item, _)) in client.character().inventory.equipment().iter().enumerate() {
-        let (item, random_property, creator) = if let Some(item) = item {
-            (
-                item.item.entry(),
-                item.item.random_property() as u32,
-                item.creator,
-            )
-        } else {
-            (0, 0, Guid::zero())
-        };
-        if let Ok(index) = VisibleItemIndex::try_from(i) {
-            let visible_item = VisibleItem::new(creator, item, [0, 0], random_property, 0);
-            player = player.set_player_visible_item(visible_item, index);
+    let inventory = &client.character().inventory;
+    if let (Some(item), Some(stack)) = (inventory.get_at(source), inventory.get_at(destination)) {
+        if stack.stacks_with(item) {
+            merge_stacks(client, source, destination).await;
+            return;
         }
     }
 
-    send_to_all(
-        SMSG_UPDATE_OBJECT {
-            has_transport: 0,
-            objects: vec![Object {
-                update_type: Object_UpdateType::Values {
-                    guid1: guid,
-                    mask1: UpdateMask::Player(player.finalize()),
-                },
-            }],
-        },
-        client,
-        entities.clients(),
-    )
-    .await;
+    if let Err(result) = equip::check_swap(client.character(), source, destination) {
+        client
+            .send_message(SMSG_INVENTORY_CHANGE_FAILURE { result })
+            .await;
+        return;
+    }
+
+    swap_inventory_item(client, source, destination);
+}
+
+async fn merge_stacks(client: &mut Client, source: ItemPosition, destination: ItemPosition) {
+    let character = client.character_mut();
+
+    if let Some(removed) = character.inventory.merge(source, destination) {
+        character.mark_position_dirty(source);
+        client
+            .send_message(SMSG_DESTROY_OBJECT { guid: removed.guid })
+            .await;
+    }
+}
+
+/// `CMSG_SPLIT_ITEM`, moves part of a stack to an empty position or onto a stack of the same item.
+async fn handle_split_item(
+    client: &mut Client,
+    db: &mut WorldDatabase,
+    source: ItemPosition,
+    destination: ItemPosition,
+    amount: u8,
+) {
+    let character = client.character();
+    let Some(item) = character.inventory.get_at(source).copied() else {
+        send_inventory_failure(client, EquipError::ItemNotFound, Guid::zero()).await;
+        return;
+    };
+
+    if amount == 0 || amount >= item.amount {
+        send_inventory_failure(client, EquipError::SplitAmount, item.guid).await;
+        return;
+    }
+
+    match character.inventory.get_at(destination) {
+        Some(stack) if stack.stacks_with(&item) && stack.max_stack() - stack.amount >= amount => {
+            let inventory = &mut client.character_mut().inventory;
+            if let Some(stack) = inventory.get_at_mut(destination) {
+                stack.set_amount(stack.amount + amount);
+            }
+            if let Some(item) = inventory.get_at_mut(source) {
+                item.set_amount(item.amount - amount);
+            }
+        }
+        Some(_) => send_inventory_failure(client, EquipError::CantSplit, item.guid).await,
+        None => {
+            // Rejected splits must not take a guid
+            let split = Item { amount, ..item };
+            if let Err(e) = equip::check_move(character, &split, source, destination) {
+                send_inventory_failure(client, e, item.guid).await;
+                return;
+            }
+
+            let new = Item::new(item.item, item.creator, amount, db);
+
+            let owner = character.guid;
+            let contained = character.inventory.bag_guid(destination).unwrap_or(owner);
+            client.queue_object(new.to_create_item_object(owner, contained, &[]));
+
+            let character = client.character_mut();
+            if let Some(item) = character.inventory.get_at_mut(source) {
+                item.set_amount(item.amount - amount);
+            }
+            character.inventory.set(destination, new);
+            character.mark_position_dirty(destination);
+        }
+    }
+}
+
+/// `CMSG_DESTROYITEM`, an amount of zero destroys the whole stack.
+async fn handle_destroy_item(client: &mut Client, position: ItemPosition, amount: u8) {
+    let character = client.character_mut();
+    let Some(item) = character.inventory.get_at_mut(position) else {
+        send_inventory_failure(client, EquipError::ItemNotFound, Guid::zero()).await;
+        return;
+    };
+
+    if amount != 0 && amount < item.amount {
+        item.set_amount(item.amount - amount);
+        return;
+    }
+
+    let guid = item.guid;
+    let bag_not_empty = position.item_slot().is_some_and(|slot| {
+        character
+            .inventory
+            .bag_contents(slot)
+            .iter()
+            .any(Option::is_some)
+    });
+    if bag_not_empty {
+        send_inventory_failure(client, EquipError::BagNotEmpty, guid).await;
+        return;
+    }
+
+    character.inventory.remove(position);
+    character.mark_position_dirty(position);
+    client.send_message(SMSG_DESTROY_OBJECT { guid }).await;
+}
+
+fn swap_inventory_item(client: &mut Client, source: ItemPosition, destination: ItemPosition) {
+    let character = client.character_mut();
+    character.inventory.swap(source, destination);
+    character.mark_position_dirty(source);
+    character.mark_position_dirty(destination);
 }
@@ -0,0 +1,85 @@
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::DESIRED_TIMESTEP;
+use std::time::Duration;
+use wow_world_messages::vanilla::{Class, Power};
+
+/// Health and power are regenerated in ticks of this many seconds.
+const REGENERATION_INTERVAL: f32 = 2.0;
+
+/// Spirit does not regenerate mana for this long after mana has been spent.
+const FIVE_SECOND_RULE: Duration = Duration::from_secs(5);
+
+/// Rage lost every tick outside of combat, rage is stored multiplied by ten.
+const RAGE_DECAY: i32 = 20;
+const ENERGY_REGENERATION: i32 = 20;
+
+/// Regenerates health and power once every [`REGENERATION_INTERVAL`].
+pub(crate) fn update_regeneration(character: &mut Character) {
+    character.regeneration_timer -= DESIRED_TIMESTEP;
+    if character.regeneration_timer > 0.0 {
+        return;
+    }
+    character.regeneration_timer += REGENERATION_INTERVAL;
+
+    if !character.is_alive() {
+        return;
+    }
+
+    if !character.is_in_combat() && character.health < character.max_health() {
+        let health = character.health + health_regeneration(character);
+        character.set_health(health);
+    }
+
+    let power = character.power;
+    match character.power_type() {
+        Power::Mana => {
+            let five_second_rule = character
+                .last_mana_use
+                .is_some_and(|t| t.elapsed() < FIVE_SECOND_RULE);
+
+            if !five_second_rule && power < character.max_power() {
+                character.set_power(power + mana_regeneration(character));
+            }
+        }
+        Power::Rage => {
+            if !character.is_in_combat() && power > 0 {
+                character.set_power(power - RAGE_DECAY);
+            }
+        }
+        Power::Energy => {
+            if power < character.max_power() {
+                character.set_power(power + ENERGY_REGENERATION);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Health per tick outside of combat.
+fn health_regeneration(character: &Character) -> i32 {
+    let spirit = character.spirit() as f32;
+
+    let health = match character.race_class.class() {
+        Class::Warrior => spirit * 0.8 + 6.0,
+        Class::Paladin => spirit * 0.25,
+        Class::Hunter => spirit * 0.43 - 5.5,
+        Class::Rogue => spirit * 0.5 - 2.0,
+        Class::Shaman => spirit * 0.11 + 2.0,
+        Class::Warlock => spirit * 0.07 + 6.0,
+        Class::Priest | Class::Mage | Class::Druid => spirit * 0.11 + 1.0,
+    };
+
+    (health as i32).max(1)
+}
+
+/// Mana per tick while the five second rule does not apply.
+fn mana_regeneration(character: &Character) -> i32 {
+    let spirit = character.spirit() as f32;
+
+    let mana = match character.race_class.class() {
+        Class::Priest | Class::Mage => spirit / 4.0 + 12.5,
+        _ => spirit / 5.0 + 15.0,
+    };
+
+    (mana as i32).max(1)
+}
@@ -0,0 +1,141 @@
+use crate::world::world::client::Client;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::spell::data::{lookup_spell, Spell};
+use crate::world::DESIRED_TIMESTEP;
+use std::time::{Duration, SystemTime};
+use wow_world_messages::vanilla::{
+    CooldownSpell, SpellCooldownTime, SMSG_COOLDOWN_EVENT, SMSG_SPELL_COOLDOWN,
+};
+
+/// Seconds of the cooldown shared by every spell that triggers it.
+pub const GLOBAL_COOLDOWN: f32 = 1.5;
+
+/// Cooldowns with at least this much left are saved with the character.
+pub const PERSISTENT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A spell, and the category of the spell, that can not be cast until a point in time.
+///
+/// Times are wall clock times so that they keep running while the character is logged out.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cooldown {
+    pub spell: u32,
+    /// The item the spell was cast from, zero if none.
+    pub item: u32,
+    pub until: SystemTime,
+    pub category: u32,
+    pub category_until: SystemTime,
+}
+
+impl Cooldown {
+    fn remaining(&self, now: SystemTime) -> Duration {
+        self.until.duration_since(now).unwrap_or(Duration::ZERO)
+    }
+
+    fn category_remaining(&self, now: SystemTime) -> Duration {
+        self.category_until
+            .duration_since(now)
+            .unwrap_or(Duration::ZERO)
+    }
+
+    /// Whether the cooldown is worth saving across a logout.
+    pub fn is_persistent(&self) -> bool {
+        let now = SystemTime::now();
+
+        self.remaining(now) >= PERSISTENT_COOLDOWN
+            || self.category_remaining(now) >= PERSISTENT_COOLDOWN
+    }
+
+    /// Entry in `SMSG_INITIAL_SPELLS`, sent on login so the client shows the remaining time.
+    pub fn to_cooldown_spell(&self) -> CooldownSpell {
+        let now = SystemTime::now();
+
+        CooldownSpell {
+            spell_id: self.spell as u16,
+            item_id: self.item as u16,
+            spell_category: self.category as u16,
+            cooldown_time: self.remaining(now).as_millis() as u32,
+            category_cooldown_time: self.category_remaining(now).as_millis() as u32,
+        }
+    }
+}
+
+/// Whether the spell, its category, or the global cooldown is still cooling down.
+pub(crate) fn is_on_cooldown(character: &Character, spell: &Spell) -> bool {
+    if spell.global_cooldown && character.global_cooldown > 0.0 {
+        return true;
+    }
+
+    let now = SystemTime::now();
+    character.cooldowns.iter().any(|c| {
+        (c.spell == spell.id && c.until > now)
+            || (spell.category != 0 && c.category == spell.category && c.category_until > now)
+    })
+}
+
+/// Counts down the global cooldown and forgets the cooldowns that have run out.
+pub(crate) fn update_cooldowns(character: &mut Character) {
+    if character.global_cooldown > 0.0 {
+        character.global_cooldown -= DESIRED_TIMESTEP;
+    }
+
+    let now = SystemTime::now();
+    character
+        .cooldowns
+        .retain(|c| c.until > now || c.category_until > now);
+}
+
+fn add_cooldown(character: &mut Character, spell: &Spell, item: u32) -> Duration {
+    let now = SystemTime::now();
+
+    character.cooldowns.retain(|c| c.spell != spell.id);
+    character.cooldowns.push(Cooldown {
+        spell: spell.id,
+        item,
+        until: now + spell.cooldown,
+        category: spell.category,
+        category_until: now + spell.category_cooldown,
+    });
+
+    spell.cooldown.max(spell.category_cooldown)
+}
+
+/// Starts the cooldowns of a finished cast and tells the client how long they last.
+pub(crate) async fn start_cooldowns(client: &mut Client, spell: &Spell, item: u32) {
+    let character = client.character_mut();
+    if spell.global_cooldown {
+        character.global_cooldown = GLOBAL_COOLDOWN;
+    }
+
+    if spell.cooldown_after_aura || (spell.cooldown.is_zero() && spell.category_cooldown.is_zero())
+    {
+        return;
+    }
+
+    let duration = add_cooldown(character, spell, item);
+
+    client
+        .send_message(SMSG_SPELL_COOLDOWN {
+            guid: client.character().guid,
+            cooldowns: vec![SpellCooldownTime {
+                id: spell.id,
+                cooldown_time: duration.as_millis() as u32,
+            }],
+        })
+        .await;
+}
+
+/// Starts the cooldown of spells like Stealth once their aura is gone.
+pub(crate) async fn aura_removed(client: &mut Client, spell: u32) {
+    let Some(spell) = lookup_spell(spell).filter(|s| s.cooldown_after_aura) else {
+        return;
+    };
+
+    add_cooldown(client.character_mut(), spell, 0);
+
+    client
+        .send_message(SMSG_COOLDOWN_EVENT {
+            id: spell.id,
+            guid: client.character().guid,
+        })
+        .await;
+}
@@ -0,0 +1,466 @@
+use crate::world::world_opcode_handler::aura::{AuraEffect, Stat};
+use std::time::Duration;
+
+/// Who a spell can be cast on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpellTarget {
+    /// Always the caster, the target sent by the client is ignored.
+    Caster,
+    /// Any other unit.
+    Enemy,
+    /// A player, or the caster if nothing is targeted.
+    Friendly,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpellEffect {
+    SchoolDamage {
+        min: i32,
+        max: i32,
+    },
+    /// Weapon damage plus a flat bonus.
+    WeaponDamage {
+        bonus: i32,
+    },
+    Heal {
+        min: i32,
+        max: i32,
+    },
+    /// Teleports the caster to its home location.
+    TeleportHome,
+    /// `None` lasts until cancelled.
+    ApplyAura {
+        duration: Option<Duration>,
+        effect: AuraEffect,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Reagent {
+    pub item: u32,
+    pub amount: u8,
+}
+
+#[derive(Debug)]
+pub struct Spell {
+    pub id: u32,
+    pub name: &'static str,
+    /// Seconds, instant spells are zero.
+    pub cast_time: f32,
+    /// Yards, ignored for [`SpellTarget::Caster`].
+    pub range: f32,
+    /// In the power of the caster, rage is multiplied by ten.
+    pub power_cost: i32,
+    pub reagents: &'static [Reagent],
+    pub target: SpellTarget,
+    pub effects: &'static [SpellEffect],
+    /// Time before the spell can be cast again.
+    pub cooldown: Duration,
+    /// Spells in the same category share [`Spell::category_cooldown`], zero is no category.
+    pub category: u32,
+    pub category_cooldown: Duration,
+    /// Whether the spell triggers, and is blocked by, the global cooldown.
+    pub global_cooldown: bool,
+    /// The cooldown starts when the aura applied by the spell is removed instead of on cast.
+    pub cooldown_after_aura: bool,
+    /// Harmful auras take the debuff slots and can not be cancelled.
+    pub harmful: bool,
+    pub max_stacks: u8,
+}
+
+const MELEE_RANGE: f32 = 5.0;
+const THIRTY_MINUTES: Option<Duration> = Some(Duration::from_secs(30 * 60));
+
+const fn spell(
+    id: u32,
+    name: &'static str,
+    cast_time: f32,
+    range: f32,
+    power_cost: i32,
+    target: SpellTarget,
+    effects: &'static [SpellEffect],
+) -> Spell {
+    Spell {
+        id,
+        name,
+        cast_time,
+        range,
+        power_cost,
+        reagents: &[],
+        target,
+        effects,
+        cooldown: Duration::ZERO,
+        category: 0,
+        category_cooldown: Duration::ZERO,
+        global_cooldown: true,
+        cooldown_after_aura: false,
+        harmful: matches!(target, SpellTarget::Enemy),
+        max_stacks: 1,
+    }
+}
+
+/// Rank 1 of the spells every class starts with or trains early,
+/// spells cast by items and auras applied by the server.
+const SPELLS: &[Spell] = &[
+    // Warrior
+    spell(
+        78,
+        "Heroic Strike",
+        0.0,
+        MELEE_RANGE,
+        150,
+        SpellTarget::Enemy,
+        &[SpellEffect::WeaponDamage { bonus: 11 }],
+    ),
+    Spell {
+        cooldown: Duration::from_secs(1),
+        global_cooldown: false,
+        ..spell(
+            2457,
+            "Battle Stance",
+            0.0,
+            0.0,
+            0,
+            SpellTarget::Caster,
+            &[SpellEffect::ApplyAura {
+                duration: None,
+                effect: AuraEffect::Dummy,
+            }],
+        )
+    },
+    // Paladin
+    spell(
+        635,
+        "Holy Light",
+        2.5,
+        40.0,
+        35,
+        SpellTarget::Friendly,
+        &[SpellEffect::Heal { min: 42, max: 51 }],
+    ),
+    spell(
+        21084,
+        "Seal of Righteousness",
+        0.0,
+        0.0,
+        20,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(30)),
+            effect: AuraEffect::Dummy,
+        }],
+    ),
+    // Hunter
+    spell(
+        2973,
+        "Raptor Strike",
+        0.0,
+        MELEE_RANGE,
+        15,
+        SpellTarget::Enemy,
+        &[SpellEffect::WeaponDamage { bonus: 5 }],
+    ),
+    // Rogue
+    spell(
+        1752,
+        "Sinister Strike",
+        0.0,
+        MELEE_RANGE,
+        45,
+        SpellTarget::Enemy,
+        &[SpellEffect::WeaponDamage { bonus: 3 }],
+    ),
+    Spell {
+        cooldown: Duration::from_secs(10),
+        global_cooldown: false,
+        cooldown_after_aura: true,
+        ..spell(
+            1784,
+            "Stealth",
+            0.0,
+            0.0,
+            0,
+            SpellTarget::Caster,
+            &[SpellEffect::ApplyAura {
+                duration: None,
+                effect: AuraEffect::ModSpeed { percent: -50 },
+            }],
+        )
+    },
+    spell(
+        2098,
+        "Eviscerate",
+        0.0,
+        MELEE_RANGE,
+        35,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 6, max: 10 }],
+    ),
+    // Priest
+    spell(
+        585,
+        "Smite",
+        1.5,
+        30.0,
+        20,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 13, max: 17 }],
+    ),
+    spell(
+        2050,
+        "Lesser Heal",
+        1.5,
+        40.0,
+        30,
+        SpellTarget::Friendly,
+        &[SpellEffect::Heal { min: 46, max: 56 }],
+    ),
+    // Shaman
+    spell(
+        403,
+        "Lightning Bolt",
+        1.5,
+        30.0,
+        15,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 13, max: 15 }],
+    ),
+    spell(
+        331,
+        "Healing Wave",
+        1.5,
+        40.0,
+        25,
+        SpellTarget::Friendly,
+        &[SpellEffect::Heal { min: 34, max: 44 }],
+    ),
+    // Mage
+    spell(
+        133,
+        "Fireball",
+        1.5,
+        35.0,
+        30,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 14, max: 22 }],
+    ),
+    spell(
+        168,
+        "Frost Armor",
+        0.0,
+        0.0,
+        60,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: THIRTY_MINUTES,
+            effect: AuraEffect::ModArmor { amount: 30 },
+        }],
+    ),
+    // Warlock
+    spell(
+        686,
+        "Shadow Bolt",
+        1.7,
+        30.0,
+        25,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 13, max: 18 }],
+    ),
+    spell(
+        687,
+        "Demon Skin",
+        0.0,
+        0.0,
+        50,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: THIRTY_MINUTES,
+            effect: AuraEffect::ModArmor { amount: 90 },
+        }],
+    ),
+    // Druid
+    spell(
+        5176,
+        "Wrath",
+        1.5,
+        30.0,
+        20,
+        SpellTarget::Enemy,
+        &[SpellEffect::SchoolDamage { min: 13, max: 16 }],
+    ),
+    spell(
+        5185,
+        "Healing Touch",
+        1.5,
+        40.0,
+        25,
+        SpellTarget::Friendly,
+        &[SpellEffect::Heal { min: 40, max: 55 }],
+    ),
+    // Trained, not known until trainers exist
+    spell(
+        772,
+        "Rend",
+        0.0,
+        MELEE_RANGE,
+        100,
+        SpellTarget::Enemy,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(9)),
+            effect: AuraEffect::PeriodicDamage {
+                amount: 5,
+                interval: Duration::from_secs(3),
+            },
+        }],
+    ),
+    spell(
+        589,
+        "Shadow Word: Pain",
+        0.0,
+        30.0,
+        25,
+        SpellTarget::Enemy,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(18)),
+            effect: AuraEffect::PeriodicDamage {
+                amount: 5,
+                interval: Duration::from_secs(3),
+            },
+        }],
+    ),
+    spell(
+        348,
+        "Immolate",
+        2.0,
+        30.0,
+        25,
+        SpellTarget::Enemy,
+        &[
+            SpellEffect::SchoolDamage { min: 11, max: 13 },
+            SpellEffect::ApplyAura {
+                duration: Some(Duration::from_secs(15)),
+                effect: AuraEffect::PeriodicDamage {
+                    amount: 4,
+                    interval: Duration::from_secs(3),
+                },
+            },
+        ],
+    ),
+    // Items
+    Spell {
+        cooldown: Duration::from_secs(60 * 60),
+        ..spell(
+            8690,
+            "Hearthstone",
+            10.0,
+            0.0,
+            0,
+            SpellTarget::Caster,
+            &[SpellEffect::TeleportHome],
+        )
+    },
+    spell(
+        2367,
+        "Lion's Strength",
+        0.0,
+        0.0,
+        0,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(60 * 60)),
+            effect: AuraEffect::ModStat {
+                stat: Stat::Strength,
+                amount: 4,
+            },
+        }],
+    ),
+    spell(
+        433,
+        "Food",
+        0.0,
+        0.0,
+        0,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(18)),
+            effect: AuraEffect::PeriodicHeal {
+                amount: 10,
+                interval: Duration::from_secs(3),
+            },
+        }],
+    ),
+    spell(
+        430,
+        "Drink",
+        0.0,
+        0.0,
+        0,
+        SpellTarget::Caster,
+        &[SpellEffect::ApplyAura {
+            duration: Some(Duration::from_secs(18)),
+            effect: AuraEffect::PeriodicMana {
+                amount: 25,
+                interval: Duration::from_secs(3),
+            },
+        }],
+    ),
+    // Applied by spirit healers
+    Spell {
+        harmful: true,
+        global_cooldown: false,
+        ..spell(
+            15007,
+            "Resurrection Sickness",
+            0.0,
+            0.0,
+            0,
+            SpellTarget::Caster,
+            &[SpellEffect::ApplyAura {
+                duration: Some(Duration::from_secs(10 * 60)),
+                effect: AuraEffect::ModAllStats { percent: -75 },
+            }],
+        )
+    },
+];
+
+/// A spell cast by using an item.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ItemSpell {
+    pub item: u32,
+    pub spell: u32,
+    /// One item is removed from the stack every use.
+    pub consumed: bool,
+}
+
+const fn item_spell(item: u32, spell: u32, consumed: bool) -> ItemSpell {
+    ItemSpell {
+        item,
+        spell,
+        consumed,
+    }
+}
+
+const ITEM_SPELLS: &[ItemSpell] = &[
+    // Hearthstone
+    item_spell(6948, 8690, false),
+    // Tough Jerky
+    item_spell(117, 433, true),
+    // Darnassian Bleu
+    item_spell(2070, 433, true),
+    // Tough Hunk of Bread
+    item_spell(4540, 433, true),
+    // Forest Mushroom Cap
+    item_spell(4604, 433, true),
+    // Refreshing Spring Water
+    item_spell(159, 430, true),
+    // Elixir of Lion's Strength
+    item_spell(2454, 2367, true),
+];
+
+pub fn lookup_spell(id: u32) -> Option<&'static Spell> {
+    SPELLS.iter().find(|s| s.id == id)
+}
+
+pub fn lookup_item_spell(item: u32) -> Option<&'static ItemSpell> {
+    ITEM_SPELLS.iter().find(|s| s.item == item)
+}
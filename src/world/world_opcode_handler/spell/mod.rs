@@ -0,0 +1,415 @@
+use crate::world::world::client::Client;
+use crate::world::world::prepare_teleport;
+use crate::world::world_opcode_handler::aura::Aura;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::combat;
+use crate::world::world_opcode_handler::entities::{Entities, Entity};
+use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::movement::eye_position;
+use crate::world::world_opcode_handler::send_to_all;
+use crate::world::world_opcode_handler::spell::data::{
+    lookup_item_spell, lookup_spell, Spell, SpellEffect, SpellTarget,
+};
+use crate::world::DESIRED_TIMESTEP;
+use namigator::vanilla::VanillaMap;
+use rand::Rng;
+use std::time::Instant;
+use wow_world_messages::vanilla::{
+    Power, SMSG_SPELL_GO_CastFlags, SMSG_SPELL_START_CastFlags, SpellCastResult, SpellCastTargets,
+    SMSG_DESTROY_OBJECT, SMSG_SPELL_FAILURE, SMSG_SPELL_GO, SMSG_SPELL_START,
+    SMSG_UPDATE_AURA_DURATION,
+};
+use wow_world_messages::Guid;
+
+pub mod cooldown;
+pub mod data;
+
+/// A spell with a cast time that has been started but not finished.
+#[derive(Debug, Clone)]
+pub struct SpellCast {
+    pub spell: &'static Spell,
+    pub target: Guid,
+    /// Targets as sent by the client, echoed back in `SMSG_SPELL_START` and `SMSG_SPELL_GO`.
+    pub targets: SpellCastTargets,
+    /// The item the spell is cast from, if any.
+    pub item: Option<Item>,
+    /// Seconds until the cast finishes.
+    pub remaining: f32,
+}
+
+/// The unit targeted by the client, if any.
+fn unit_target(targets: &SpellCastTargets) -> Option<Guid> {
+    targets
+        .target_flags
+        .get_unit()
+        .map(|u| u.unit_target)
+        .filter(|guid| *guid != Guid::zero())
+}
+
+fn resolve_target(character: &Character, spell: &Spell, targets: &SpellCastTargets) -> Guid {
+    let selected = unit_target(targets);
+
+    match spell.target {
+        SpellTarget::Caster => character.guid,
+        SpellTarget::Enemy => selected.unwrap_or(character.target),
+        SpellTarget::Friendly => selected.unwrap_or(character.guid),
+    }
+}
+
+/// Checks that the target exists, is alive and is within range and line of sight.
+fn check_target(
+    client: &Client,
+    entities: &Entities,
+    pathfinding: Option<&VanillaMap>,
+    spell: &Spell,
+    target: Guid,
+) -> Result<(), SpellCastResult> {
+    if spell.target == SpellTarget::Caster {
+        return Ok(());
+    }
+
+    let caster = client.character();
+    if target == caster.guid {
+        return if spell.target == SpellTarget::Enemy {
+            Err(SpellCastResult::BadTargets)
+        } else {
+            Ok(())
+        };
+    }
+
+    let (alive, position) = match entities.find_guid(target) {
+        Some(Entity::Player(c)) => (c.character().is_alive(), c.position()),
+        Some(Entity::Creature(c)) if spell.target == SpellTarget::Enemy => {
+            (c.health > 0, c.position())
+        }
+        _ => return Err(SpellCastResult::BadTargets),
+    };
+
+    if !alive {
+        return Err(SpellCastResult::TargetsDead);
+    }
+
+    match client.distance_to_position(&position) {
+        Some(distance) if distance <= spell.range => {}
+        _ => return Err(SpellCastResult::OutOfRange),
+    }
+
+    if let Some(map) = pathfinding {
+        let from = eye_position(client.position());
+        let to = eye_position(position);
+
+        if let Ok(false) = map.line_of_sight(from.into(), to.into()) {
+            return Err(SpellCastResult::LineOfSight);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_cost(character: &Character, spell: &Spell) -> Result<(), SpellCastResult> {
+    if character.power < spell.power_cost {
+        return Err(SpellCastResult::NoPower);
+    }
+
+    for reagent in spell.reagents {
+        if character.inventory.count_items(reagent.item) < u32::from(reagent.amount) {
+            return Err(SpellCastResult::Reagents);
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_cast(
+    client: &Client,
+    entities: &Entities,
+    pathfinding: Option<&VanillaMap>,
+    spell_id: u32,
+    targets: SpellCastTargets,
+    item: Option<Item>,
+) -> Result<SpellCast, SpellCastResult> {
+    let character = client.character();
+
+    if !character.is_alive() {
+        return Err(SpellCastResult::CasterDead);
+    }
+    if character.casting.is_some() {
+        return Err(SpellCastResult::SpellInProgress);
+    }
+
+    // Spells from items are known by whoever has the item
+    let known = item.is_some() || character.race_class.starter_spells().contains(&spell_id);
+    let spell = lookup_spell(spell_id)
+        .filter(|_| known)
+        .ok_or(SpellCastResult::NotKnown)?;
+
+    if cooldown::is_on_cooldown(character, spell) {
+        return Err(SpellCastResult::NotReady);
+    }
+
+    let target = resolve_target(character, spell, &targets);
+    check_target(client, entities, pathfinding, spell, target)?;
+    check_cost(character, spell)?;
+
+    Ok(SpellCast {
+        spell,
+        target,
+        targets,
+        item,
+        remaining: spell.cast_time,
+    })
+}
+
+/// `CMSG_CAST_SPELL` and `CMSG_USE_ITEM`, starts casting or tells the caster why it failed.
+///
+/// Spells without a cast time are finished immediately.
+pub(crate) async fn cast_spell(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    pathfinding: Option<&VanillaMap>,
+    spell_id: u32,
+    targets: SpellCastTargets,
+    item: Option<Item>,
+) {
+    let cast = match validate_cast(client, entities, pathfinding, spell_id, targets, item) {
+        Ok(cast) => cast,
+        Err(result) => {
+            client
+                .send_message(SMSG_SPELL_FAILURE {
+                    guid: client.character().guid,
+                    spell: spell_id,
+                    result,
+                })
+                .await;
+            return;
+        }
+    };
+
+    let guid = client.character().guid;
+    send_to_all(
+        SMSG_SPELL_START {
+            cast_item: cast.item.map(|i| i.guid).unwrap_or(guid),
+            caster: guid,
+            spell: cast.spell.id,
+            flags: SMSG_SPELL_START_CastFlags::empty(),
+            timer: (cast.spell.cast_time * 1000.0) as u32,
+            targets: cast.targets.clone(),
+        },
+        client,
+        entities.clients(),
+    )
+    .await;
+
+    if cast.remaining <= 0.0 {
+        finish_cast(client, entities, cast).await;
+    } else {
+        client.character_mut().casting = Some(cast);
+    }
+}
+
+/// Counts down the current cast and finishes it once the cast time has passed.
+///
+/// The target is checked again, since it might have died or moved away during the cast.
+pub(crate) async fn update_spell_cast(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    pathfinding: Option<&VanillaMap>,
+) {
+    let Some(cast) = client.character_mut().casting.as_mut() else {
+        return;
+    };
+    cast.remaining -= DESIRED_TIMESTEP;
+    if cast.remaining > 0.0 {
+        return;
+    }
+
+    let Some(cast) = client.character_mut().casting.take() else {
+        return;
+    };
+
+    let result = check_target(client, entities, pathfinding, cast.spell, cast.target)
+        .and_then(|()| check_cost(client.character(), cast.spell));
+    if let Err(result) = result {
+        send_to_all(
+            SMSG_SPELL_FAILURE {
+                guid: client.character().guid,
+                spell: cast.spell.id,
+                result,
+            },
+            client,
+            entities.clients(),
+        )
+        .await;
+        return;
+    }
+
+    finish_cast(client, entities, cast).await;
+}
+
+/// Stops the current cast, if any, and tells everybody.
+pub(crate) async fn interrupt_cast(client: &mut Client, clients: &mut [Client]) {
+    let Some(cast) = client.character_mut().casting.take() else {
+        return;
+    };
+
+    send_to_all(
+        SMSG_SPELL_FAILURE {
+            guid: client.character().guid,
+            spell: cast.spell.id,
+            result: SpellCastResult::Interrupted,
+        },
+        client,
+        clients,
+    )
+    .await;
+}
+
+async fn remove_items(client: &mut Client, entry: u32, amount: u32) {
+    let changed = client.character_mut().inventory.remove_items(entry, amount);
+
+    for (position, removed) in changed {
+        client.character_mut().mark_position_dirty(position);
+
+        if let Some(item) = removed {
+            client
+                .send_message(SMSG_DESTROY_OBJECT { guid: item.guid })
+                .await;
+        }
+    }
+}
+
+async fn finish_cast(client: &mut Client, entities: &mut Entities<'_>, cast: SpellCast) {
+    let spell = cast.spell;
+
+    let character = client.character_mut();
+    if spell.power_cost > 0 {
+        character.set_power(character.power - spell.power_cost);
+
+        if character.power_type() == Power::Mana {
+            character.last_mana_use = Some(Instant::now());
+        }
+    }
+    for reagent in spell.reagents {
+        remove_items(client, reagent.item, u32::from(reagent.amount)).await;
+    }
+    if let Some(item) = cast.item {
+        let entry = item.item.entry();
+        if lookup_item_spell(entry).is_some_and(|s| s.consumed) {
+            remove_items(client, entry, 1).await;
+        }
+    }
+
+    let item = cast.item.map(|i| i.item.entry()).unwrap_or(0);
+    cooldown::start_cooldowns(client, spell, item).await;
+
+    let guid = client.character().guid;
+    send_to_all(
+        SMSG_SPELL_GO {
+            cast_item: cast.item.map(|i| i.guid).unwrap_or(guid),
+            caster: guid,
+            spell: spell.id,
+            flags: SMSG_SPELL_GO_CastFlags::empty(),
+            hits: vec![cast.target],
+            misses: vec![],
+            targets: cast.targets,
+        },
+        client,
+        entities.clients(),
+    )
+    .await;
+
+    debug!(
+        session: client.session(),
+        "{} cast {}",
+        client.character().name,
+        spell.name
+    );
+
+    for effect in spell.effects {
+        match *effect {
+            SpellEffect::SchoolDamage { min, max } => {
+                let mut rng = rand::thread_rng();
+                let damage = rng.gen_range(min..=max);
+                combat::deal_damage(
+                    client.character_mut(),
+                    entities,
+                    cast.target,
+                    damage,
+                    &mut rng,
+                );
+            }
+            SpellEffect::WeaponDamage { bonus } => {
+                let mut rng = rand::thread_rng();
+                let damage = combat::weapon_damage(client.character(), &mut rng) + bonus;
+                combat::deal_damage(
+                    client.character_mut(),
+                    entities,
+                    cast.target,
+                    damage,
+                    &mut rng,
+                );
+            }
+            SpellEffect::Heal { min, max } => {
+                let amount = rand::thread_rng().gen_range(min..=max);
+                heal(client, entities, cast.target, amount);
+            }
+            SpellEffect::TeleportHome => {
+                let home = client.character().race_class.starting_position();
+                prepare_teleport(home, client).await;
+            }
+            SpellEffect::ApplyAura { .. } => {
+                apply_aura(client, entities, spell, cast.target).await;
+            }
+        }
+    }
+}
+
+fn heal(client: &mut Client, entities: &mut Entities, target: Guid, amount: i32) {
+    let character = if target == client.character().guid {
+        client.character_mut()
+    } else if let Some(c) = entities.find_player_mut(target) {
+        c.character_mut()
+    } else {
+        return;
+    };
+
+    character.set_health(character.health + amount);
+}
+
+async fn apply_aura(client: &mut Client, entities: &mut Entities<'_>, spell: &Spell, target: Guid) {
+    let caster = client.character().guid;
+    let Some(aura) = Aura::from_spell(spell, caster) else {
+        return;
+    };
+
+    if let Some(creature) = entities.find_creature_mut(target) {
+        creature.apply_aura(aura, spell.max_stacks);
+        return;
+    }
+
+    let target = if target == caster {
+        client
+    } else if let Some(c) = entities.find_player_mut(target) {
+        c
+    } else {
+        return;
+    };
+
+    apply_player_aura(target, aura, spell.max_stacks).await;
+}
+
+/// Applies an aura to a player and tells the player how long it lasts.
+pub(crate) async fn apply_player_aura(client: &mut Client, aura: Aura, max_stacks: u8) {
+    let Some(aura) = client.character_mut().apply_aura(aura, max_stacks) else {
+        return;
+    };
+
+    if let Some(remaining) = aura.remaining {
+        client
+            .send_message(SMSG_UPDATE_AURA_DURATION {
+                aura_slot: aura.slot,
+                aura_duration: (remaining * 1000.0) as u32,
+            })
+            .await;
+    }
+}
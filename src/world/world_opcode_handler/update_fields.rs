@@ -0,0 +1,375 @@
+use crate::world::world_opcode_handler::aura::Auras;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::creature::Creature;
+use crate::world::world_opcode_handler::item::Item;
+use crate::world::world_opcode_handler::vendor::{BuybackItem, BUYBACK_SLOTS};
+use wow_world_base::vanilla::{ItemSlot, Skill};
+use wow_world_messages::vanilla::{
+    Object, Object_UpdateType, Power, SkillInfo, SkillInfoIndex, UpdateContainerBuilder,
+    UpdateItemBuilder, UpdateMask, UpdatePlayerBuilder, UpdateUnitBuilder, VisibleItem,
+    VisibleItemIndex,
+};
+use wow_world_messages::Guid;
+
+/// Update field that has changed since the last time updates were sent.
+///
+/// Fields that are not update fields, like the run speed, are also tracked here
+/// since they need to be sent out at the same time.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Field {
+    Level,
+    Health,
+    MaxHealth,
+    Target,
+    VisibleItems,
+    Stats,
+    RunSpeed,
+    StackCount,
+    Power,
+    MaxPower,
+    PlayerFlags,
+    Durability,
+    UnitFlags,
+    Auras,
+    Coinage,
+    Buyback,
+    DynamicFlags,
+    /// The bag an item is in, or the owner outside of bags.
+    Contained,
+    Skills,
+}
+
+impl Field {
+    const ALL: [Field; 19] = [
+        Field::Level,
+        Field::Health,
+        Field::MaxHealth,
+        Field::Target,
+        Field::VisibleItems,
+        Field::Stats,
+        Field::RunSpeed,
+        Field::StackCount,
+        Field::Power,
+        Field::MaxPower,
+        Field::PlayerFlags,
+        Field::Durability,
+        Field::UnitFlags,
+        Field::Auras,
+        Field::Coinage,
+        Field::Buyback,
+        Field::DynamicFlags,
+        Field::Contained,
+        Field::Skills,
+    ];
+
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// Public fields are sent to every observer, private fields only to the owner.
+    pub const fn is_public(self) -> bool {
+        match self {
+            Field::Level
+            | Field::Health
+            | Field::MaxHealth
+            | Field::Target
+            | Field::VisibleItems
+            | Field::RunSpeed
+            | Field::Power
+            | Field::MaxPower
+            | Field::PlayerFlags
+            | Field::UnitFlags
+            | Field::Auras
+            | Field::DynamicFlags => true,
+            Field::Stats
+            | Field::StackCount
+            | Field::Durability
+            | Field::Coinage
+            | Field::Buyback
+            | Field::Contained
+            | Field::Skills => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Visibility {
+    /// Fields visible to the owner of the object.
+    Owner,
+    /// Fields visible to everybody else.
+    Observer,
+}
+
+impl Visibility {
+    const fn includes(self, field: Field) -> bool {
+        match self {
+            Visibility::Owner => true,
+            Visibility::Observer => field.is_public(),
+        }
+    }
+}
+
+/// Set of fields changed during the current tick.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct DirtyFields {
+    fields: u32,
+    /// One bit per [`ItemSlot`], or per slot of a bag, inventory fields are always private.
+    inventory: u128,
+}
+
+impl DirtyFields {
+    pub fn mark(&mut self, field: Field) {
+        self.fields |= field.bit();
+    }
+
+    pub fn mark_inventory(&mut self, slot: ItemSlot) {
+        self.inventory |= 1 << slot.as_int();
+    }
+
+    pub fn mark_container_slot(&mut self, slot: u8) {
+        self.inventory |= 1 << slot;
+    }
+
+    pub const fn is_dirty(&self, field: Field) -> bool {
+        self.fields & field.bit() != 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn fields(&self, visibility: Visibility) -> impl Iterator<Item = Field> + '_ {
+        Field::ALL
+            .into_iter()
+            .filter(move |f| self.is_dirty(*f) && visibility.includes(*f))
+    }
+
+    fn inventory_slots(&self) -> impl Iterator<Item = ItemSlot> + '_ {
+        (0..u128::BITS)
+            .filter(|i| self.inventory & (1 << *i) != 0)
+            .filter_map(|i| ItemSlot::try_from(i as u8).ok())
+    }
+
+    fn container_slots(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..u128::BITS as u8).filter(|i| self.inventory & (1 << *i) != 0)
+    }
+}
+
+fn values(guid: Guid, mask: UpdateMask) -> Object {
+    Object {
+        update_type: Object_UpdateType::Values {
+            guid1: guid,
+            mask1: mask,
+        },
+    }
+}
+
+/// Creates a values update with the dirty fields of a player, if any are visible.
+pub fn player_values(character: &Character, visibility: Visibility) -> Option<Object> {
+    let dirty = &character.dirty;
+    let mut mask = UpdatePlayerBuilder::new();
+    let mut changed = false;
+
+    for field in dirty.fields(visibility) {
+        mask = match field {
+            Field::Level => mask.set_unit_level(character.level.as_int() as i32),
+            Field::Health => mask.set_unit_health(character.health),
+            Field::MaxHealth => mask.set_unit_maxhealth(character.max_health()),
+            Field::Target => mask.set_unit_target(character.target),
+            Field::VisibleItems => set_visible_items(mask, character),
+            Field::Stats => set_stats(mask, character),
+            Field::Power => set_power(mask, character.power_type(), character.power),
+            Field::MaxPower => set_max_power(mask, character.power_type(), character.max_power()),
+            Field::PlayerFlags => mask.set_player_flags(character.player_flags()),
+            Field::UnitFlags => mask.set_unit_flags(character.unit_flags()),
+            Field::Auras => set_auras(mask, &character.auras),
+            Field::Coinage => mask.set_player_field_coinage(character.money as i32),
+            Field::Buyback => set_buyback(mask, &character.buyback),
+            Field::Skills => set_skills(mask, &character.skills),
+            Field::RunSpeed
+            | Field::StackCount
+            | Field::Durability
+            | Field::DynamicFlags
+            | Field::Contained => continue,
+        };
+        changed = true;
+    }
+
+    if visibility == Visibility::Owner {
+        for slot in dirty.inventory_slots() {
+            let guid = character
+                .inventory
+                .get(slot)
+                .map(|a| a.guid)
+                .unwrap_or(Guid::zero());
+            mask = mask.set_player_field_inv(slot, guid);
+            changed = true;
+        }
+    }
+
+    changed.then(|| values(character.guid, UpdateMask::Player(mask.finalize())))
+}
+
+/// Sets the stats and the values calculated from them, which depend on equipment and auras.
+pub fn set_stats(mut mask: UpdatePlayerBuilder, character: &Character) -> UpdatePlayerBuilder {
+    mask = mask
+        .set_unit_base_health(character.base_health())
+        .set_unit_agility(character.agility())
+        .set_unit_strength(character.strength())
+        .set_unit_stamina(character.stamina())
+        .set_unit_intellect(character.intellect())
+        .set_unit_spirit(character.spirit())
+        .set_unit_attack_power(character.attack_power());
+
+    for (school, resistance) in character.resistances().into_iter().enumerate() {
+        mask = mask.set_unit_resistances(school, resistance);
+    }
+
+    mask
+}
+
+/// Sets the power field used by the power type, rage and energy have their own fields.
+pub fn set_power(mask: UpdatePlayerBuilder, power: Power, value: i32) -> UpdatePlayerBuilder {
+    match power {
+        Power::Mana => mask.set_unit_power1(value),
+        Power::Rage => mask.set_unit_power2(value),
+        Power::Focus => mask.set_unit_power3(value),
+        Power::Energy => mask.set_unit_power4(value),
+        Power::Happiness => mask.set_unit_power5(value),
+        _ => mask,
+    }
+}
+
+pub fn set_max_power(mask: UpdatePlayerBuilder, power: Power, value: i32) -> UpdatePlayerBuilder {
+    match power {
+        Power::Mana => mask.set_unit_maxpower1(value),
+        Power::Rage => mask.set_unit_maxpower2(value),
+        Power::Focus => mask.set_unit_maxpower3(value),
+        Power::Energy => mask.set_unit_maxpower4(value),
+        Power::Happiness => mask.set_unit_maxpower5(value),
+        _ => mask,
+    }
+}
+
+/// Sets every aura slot, empty slots are cleared.
+pub fn set_auras(mut mask: UpdatePlayerBuilder, auras: &Auras) -> UpdatePlayerBuilder {
+    for (slot, spell) in auras.slots() {
+        mask = mask.set_unit_aura(slot.into(), spell as i32);
+    }
+
+    mask
+}
+
+/// Sets a skill slot for every skill, in the order they were learned.
+pub fn set_skills(mut mask: UpdatePlayerBuilder, skills: &[Skill]) -> UpdatePlayerBuilder {
+    for (i, skill) in skills.iter().enumerate() {
+        mask = mask.set_player_skill_info(
+            SkillInfo::new(*skill, 0, 295, 300, 0, 2),
+            SkillInfoIndex::try_from(i as u32).unwrap(),
+        );
+    }
+
+    mask
+}
+
+/// Sets every buyback slot, empty slots are cleared.
+pub fn set_buyback(mut mask: UpdatePlayerBuilder, buyback: &[BuybackItem]) -> UpdatePlayerBuilder {
+    for slot in 0..BUYBACK_SLOTS {
+        let (guid, price, timestamp) = match buyback.get(slot) {
+            Some(b) => (b.item.guid, b.price as i32, b.timestamp()),
+            None => (Guid::zero(), 0, 0),
+        };
+
+        mask = mask
+            .set_player_field_vendorbuyback_slot(slot, guid)
+            .set_player_field_buyback_price(slot, price)
+            .set_player_field_buyback_timestamp(slot, timestamp);
+    }
+
+    mask
+}
+
+fn set_visible_items(mut mask: UpdatePlayerBuilder, character: &Character) -> UpdatePlayerBuilder {
+    for (i, (item, _)) in character.inventory.equipment().iter().enumerate() {
+        let (item, random_property, creator) = if let Some(item) = item {
+            (
+                item.item.entry(),
+                item.item.random_property() as u32,
+                item.creator,
+            )
+        } else {
+            (0, 0, Guid::zero())
+        };
+        if let Ok(index) = VisibleItemIndex::try_from(i) {
+            let visible_item = VisibleItem::new(creator, item, [0, 0], random_property, 0);
+            mask = mask.set_player_visible_item(visible_item, index);
+        }
+    }
+
+    mask
+}
+
+/// Creates a values update with the dirty fields of a creature, if any are visible.
+pub fn unit_values(creature: &Creature) -> Option<Object> {
+    let mut mask = UpdateUnitBuilder::new();
+    let mut changed = false;
+
+    for field in creature.dirty.fields(Visibility::Observer) {
+        mask = match field {
+            Field::Level => mask.set_unit_level(creature.level.into()),
+            Field::Health => mask.set_unit_health(creature.health),
+            Field::MaxHealth => mask.set_unit_maxhealth(creature.max_health),
+            Field::Target => mask.set_unit_target(creature.target),
+            Field::Power => mask.set_unit_power1(creature.power),
+            Field::MaxPower => mask.set_unit_maxpower1(creature.max_power),
+            Field::DynamicFlags => mask.set_unit_dynamic_flags(creature.dynamic_flags()),
+            Field::Auras => {
+                for (slot, spell) in creature.auras.slots() {
+                    mask = mask.set_unit_aura(slot.into(), spell as i32);
+                }
+                mask
+            }
+            _ => continue,
+        };
+        changed = true;
+    }
+
+    changed.then(|| values(creature.guid, UpdateMask::Unit(mask.finalize())))
+}
+
+/// Creates a values update with the changed slots of a bag, if any.
+pub fn container_values(bag: &Item, contents: &[Option<Item>]) -> Option<Object> {
+    let mut mask = UpdateContainerBuilder::new();
+    let mut changed = false;
+
+    for slot in bag.dirty.container_slots() {
+        let guid = contents
+            .get(usize::from(slot))
+            .and_then(Option::as_ref)
+            .map(|i| i.guid)
+            .unwrap_or(Guid::zero());
+        mask = mask.set_container_slot(usize::from(slot), guid);
+        changed = true;
+    }
+
+    changed.then(|| values(bag.guid, mask.finalize().into()))
+}
+
+/// Creates a values update with the dirty fields of an item, items are only visible to the owner.
+/// `contained` is the bag holding the item, or the owner for items outside of bags.
+pub fn item_values(item: &Item, contained: Guid) -> Option<Object> {
+    let mut mask = UpdateItemBuilder::new();
+    let mut changed = false;
+
+    for field in item.dirty.fields(Visibility::Owner) {
+        mask = match field {
+            Field::StackCount => mask.set_item_stack_count(item.amount as i32),
+            Field::Durability => mask.set_item_durability(item.durability),
+            Field::Contained => mask.set_item_contained(contained),
+            _ => continue,
+        };
+        changed = true;
+    }
+
+    changed.then(|| values(item.guid, mask.finalize().into()))
+}
@@ -0,0 +1,551 @@
+use crate::world::database::WorldDatabase;
+use crate::world::world::client::Client;
+use crate::world::world_opcode_handler::character::Character;
+use crate::world::world_opcode_handler::entities::Entities;
+use crate::world::world_opcode_handler::equip::{check_equip, send_inventory_failure, EquipError};
+use crate::world::world_opcode_handler::inventory::{ItemPosition, CHARACTER_BAG};
+use crate::world::world_opcode_handler::item::{
+    award_item, max_stack, send_item_push_result, Item,
+};
+use crate::world::DESIRED_TIMESTEP;
+use std::time::SystemTime;
+use wow_items::vanilla::lookup_item;
+use wow_world_base::vanilla::NewItemSource;
+use wow_world_messages::vanilla::{
+    BuyResult, Gold, ListInventoryItem, SellItemResult, SMSG_BUY_FAILED, SMSG_BUY_ITEM,
+    SMSG_DESTROY_OBJECT, SMSG_LIST_INVENTORY, SMSG_SELL_ITEM,
+};
+use wow_world_messages::Guid;
+
+/// Furthest distance a player can be from an NPC while trading with it.
+const INTERACTION_RANGE: f32 = 10.0;
+
+pub(crate) const UNIT_NPC_FLAG_VENDOR: i32 = 0x0004;
+pub(crate) const UNIT_NPC_FLAG_REPAIR: i32 = 0x4000;
+
+/// Sold items that can be bought back, older items are lost.
+pub(crate) const BUYBACK_SLOTS: usize = 12;
+/// Inventory slot the client uses for the first buyback slot.
+const BUYBACK_SLOT_START: u32 = 69;
+
+/// Stock sent for items that never run out.
+const UNLIMITED_STOCK: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub struct VendorItemTemplate {
+    pub item: u32,
+    /// `None` never runs out.
+    pub max_count: Option<u8>,
+    /// Seconds after the first sale before limited items are back in full.
+    pub restock_time: f32,
+}
+
+#[derive(Debug)]
+pub struct VendorTemplate {
+    /// Entry of the creature selling the items.
+    pub entry: u32,
+    pub repairs: bool,
+    pub items: &'static [VendorItemTemplate],
+}
+
+const fn unlimited(item: u32) -> VendorItemTemplate {
+    VendorItemTemplate {
+        item,
+        max_count: None,
+        restock_time: 0.0,
+    }
+}
+
+const fn limited(item: u32, max_count: u8, restock_time: f32) -> VendorItemTemplate {
+    VendorItemTemplate {
+        item,
+        max_count: Some(max_count),
+        restock_time,
+    }
+}
+
+const VENDORS: &[VendorTemplate] = &[
+    // Brother Danil
+    VendorTemplate {
+        entry: 152,
+        repairs: false,
+        items: &[
+            unlimited(159),
+            unlimited(117),
+            unlimited(4540),
+            limited(4496, 2, 900.0),
+        ],
+    },
+    // Godric Rothgar
+    VendorTemplate {
+        entry: 1213,
+        repairs: true,
+        items: &[unlimited(25), limited(2362, 1, 1800.0)],
+    },
+];
+
+pub fn lookup_vendor(entry: u32) -> Option<&'static VendorTemplate> {
+    VENDORS.iter().find(|v| v.entry == entry)
+}
+
+#[derive(Debug)]
+pub struct VendorItem {
+    pub template: &'static VendorItemTemplate,
+    pub item: &'static wow_world_base::vanilla::Item,
+    /// What is left of limited items.
+    pub count: Option<u8>,
+    restock_timer: f32,
+}
+
+impl VendorItem {
+    /// Items received for each purchase.
+    fn buy_count(&self) -> u8 {
+        u8::try_from(self.item.buy_count()).unwrap_or(1).max(1)
+    }
+
+    /// Copper for buying the item an amount of times.
+    fn price(&self, amount: u8) -> u32 {
+        u32::try_from(self.item.buy_price()).unwrap_or(0) * u32::from(amount)
+    }
+
+    fn sell(&mut self, amount: u8) {
+        let Some(count) = self.count else {
+            return;
+        };
+
+        if self.count == self.template.max_count {
+            self.restock_timer = self.template.restock_time;
+        }
+        self.count = Some(count.saturating_sub(amount));
+    }
+
+    fn to_list_inventory_item(&self) -> ListInventoryItem {
+        let durability = self.item.max_durability().max(0) as u32;
+
+        ListInventoryItem {
+            item_stack_count: u32::from(self.buy_count()),
+            item: self.item.entry(),
+            item_display_id: self.item.display_id(),
+            max_items: self.count.map_or(UNLIMITED_STOCK, u32::from),
+            price: Gold::new(self.price(1)),
+            max_durability: durability,
+            durability,
+        }
+    }
+}
+
+/// The stock of a single vendor creature.
+#[derive(Debug)]
+pub struct Vendor {
+    pub template: &'static VendorTemplate,
+    pub items: Vec<VendorItem>,
+}
+
+impl Vendor {
+    /// `None` if creatures with the entry do not sell anything.
+    pub fn new(entry: u32) -> Option<Self> {
+        let template = lookup_vendor(entry)?;
+
+        let items = template
+            .items
+            .iter()
+            .filter_map(|t| {
+                Some(VendorItem {
+                    template: t,
+                    item: lookup_item(t.item)?,
+                    count: t.max_count,
+                    restock_timer: 0.0,
+                })
+            })
+            .collect();
+
+        Some(Self { template, items })
+    }
+
+    pub fn npc_flags(&self) -> i32 {
+        if self.template.repairs {
+            UNIT_NPC_FLAG_VENDOR | UNIT_NPC_FLAG_REPAIR
+        } else {
+            UNIT_NPC_FLAG_VENDOR
+        }
+    }
+
+    /// Restocks limited items in full once their timer runs out.
+    pub fn update(&mut self) {
+        for item in &mut self.items {
+            if item.count == item.template.max_count {
+                continue;
+            }
+
+            item.restock_timer -= DESIRED_TIMESTEP;
+            if item.restock_timer <= 0.0 {
+                item.count = item.template.max_count;
+            }
+        }
+    }
+}
+
+/// An item sold to a vendor that can be bought back for what it was sold for.
+#[derive(Debug, Copy, Clone)]
+pub struct BuybackItem {
+    pub item: Item,
+    pub price: u32,
+    pub sold: SystemTime,
+}
+
+impl BuybackItem {
+    pub fn timestamp(&self) -> i32 {
+        self.sold
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i32)
+            .unwrap_or(0)
+    }
+}
+
+fn vendor_in_range<'a>(
+    client: &Client,
+    entities: &'a mut Entities,
+    guid: Guid,
+) -> Option<&'a mut Vendor> {
+    let npc = entities
+        .find_creature_mut(guid)
+        .filter(|npc| npc.is_alive())?;
+
+    let in_range = client
+        .distance_to_position(&npc.position())
+        .is_some_and(|d| d <= INTERACTION_RANGE);
+
+    npc.vendor.as_mut().filter(|_| in_range)
+}
+
+async fn send_buy_failed(client: &mut Client, vendor: Guid, item: u32, result: BuyResult) {
+    client
+        .send_message(SMSG_BUY_FAILED {
+            guid: vendor,
+            item,
+            result,
+        })
+        .await;
+}
+
+async fn send_sell_failed(client: &mut Client, vendor: Guid, item: Guid, result: SellItemResult) {
+    client
+        .send_message(SMSG_SELL_ITEM {
+            guid: vendor,
+            item,
+            result,
+        })
+        .await;
+}
+
+/// `CMSG_LIST_INVENTORY`
+pub(crate) async fn list_inventory(client: &mut Client, entities: &mut Entities<'_>, npc: Guid) {
+    let Some(vendor) = vendor_in_range(client, entities, npc) else {
+        return;
+    };
+
+    let items = vendor
+        .items
+        .iter()
+        .map(VendorItem::to_list_inventory_item)
+        .collect();
+
+    client
+        .send_message(SMSG_LIST_INVENTORY { vendor: npc, items })
+        .await;
+}
+
+/// The position of a slot in a bag given by guid, the character itself is the backpack.
+pub(crate) fn bag_position(character: &Character, bag: Guid, slot: u8) -> Option<ItemPosition> {
+    if bag == character.guid {
+        return Some(ItemPosition::new(CHARACTER_BAG, slot));
+    }
+
+    let bag = character.inventory.find(bag)?.item_slot()?;
+    Some(ItemPosition::new(bag.as_int(), slot))
+}
+
+/// Checks that a new item can be put in an empty position chosen by the client.
+fn check_place(
+    character: &Character,
+    item: &Item,
+    position: ItemPosition,
+) -> Result<(), EquipError> {
+    let inventory = &character.inventory;
+
+    if !inventory.is_valid(position) || inventory.get_at(position).is_some() {
+        Err(EquipError::WrongSlot)
+    } else if !inventory.can_hold(position, item) {
+        Err(EquipError::WrongBag)
+    } else {
+        match position.item_slot() {
+            Some(slot) => check_equip(character, item, slot),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Puts a new item in a position that passed [`check_place`].
+async fn place_item(
+    item: Item,
+    position: ItemPosition,
+    client: &mut Client,
+    clients: &mut [Client],
+) {
+    let character = client.character();
+    let owner = character.guid;
+    let contained = character.inventory.bag_guid(position).unwrap_or(owner);
+    client.queue_object(item.to_create_item_object(owner, contained, &[]));
+
+    let character = client.character_mut();
+    character.inventory.set(position, item);
+    character.mark_position_dirty(position);
+
+    client.flush_objects().await;
+    send_item_push_result(&item, position, NewItemSource::FromNpc, client, clients).await;
+}
+
+/// `CMSG_BUY_ITEM` and `CMSG_BUY_ITEM_IN_SLOT`, the item is stored anywhere without a position.
+///
+/// The amount is the number of purchases, some items come in stacks of more than one.
+pub(crate) async fn buy_item(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    db: &mut WorldDatabase,
+    npc: Guid,
+    entry: u32,
+    amount: u8,
+    position: Option<ItemPosition>,
+) {
+    let Some(vendor) = vendor_in_range(client, entities, npc) else {
+        send_buy_failed(client, npc, entry, BuyResult::DistanceTooFar).await;
+        return;
+    };
+
+    let Some(vendor_slot) = vendor.items.iter().position(|i| i.item.entry() == entry) else {
+        send_buy_failed(client, npc, entry, BuyResult::CantFindItem).await;
+        return;
+    };
+    let vendor_item = &vendor.items[vendor_slot];
+
+    let amount = amount.max(1);
+    if vendor_item.count.is_some_and(|count| count < amount) {
+        send_buy_failed(client, npc, entry, BuyResult::ItemSoldOut).await;
+        return;
+    }
+
+    let price = vendor_item.price(amount);
+    if price > client.character().money {
+        send_buy_failed(client, npc, entry, BuyResult::NotEnoughtMoney).await;
+        return;
+    }
+
+    let item = vendor_item.item;
+    let stack = amount
+        .checked_mul(vendor_item.buy_count())
+        .filter(|stack| *stack <= max_stack(item));
+    let Some(stack) = stack else {
+        send_buy_failed(client, npc, entry, BuyResult::CantCarryMore).await;
+        return;
+    };
+
+    // Items that do not fit must not take a guid
+    let item = Item::with_guid(item, Guid::zero(), Guid::zero(), stack);
+    let fits = match position {
+        Some(position) => check_place(client.character(), &item, position),
+        None if client.character().inventory.has_room(&item) => Ok(()),
+        None => Err(EquipError::BagFull),
+    };
+    if let Err(e) = fits {
+        send_inventory_failure(client, e, Guid::zero()).await;
+        return;
+    }
+
+    let item = Item {
+        guid: db.new_guid().into(),
+        ..item
+    };
+    match position {
+        Some(position) => place_item(item, position, client, entities.clients()).await,
+        None => {
+            if !award_item(item, NewItemSource::FromNpc, client, entities.clients()).await {
+                return;
+            }
+        }
+    }
+
+    let character = client.character_mut();
+    character.set_money(character.money - price);
+
+    let Some(vendor) = vendor_in_range(client, entities, npc) else {
+        return;
+    };
+    let vendor_item = &mut vendor.items[vendor_slot];
+    vendor_item.sell(amount);
+    let amount_for_sale = vendor_item.count.map_or(UNLIMITED_STOCK, u32::from);
+
+    client
+        .send_message(SMSG_BUY_ITEM {
+            guid: npc,
+            vendor_slot: vendor_slot as u32 + 1,
+            amount_for_sale,
+            amount_bought: amount.into(),
+        })
+        .await;
+}
+
+/// `CMSG_SELL_ITEM`, an amount of zero sells the whole stack.
+pub(crate) async fn sell_item(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    db: &mut WorldDatabase,
+    npc: Guid,
+    guid: Guid,
+    amount: u8,
+) {
+    if vendor_in_range(client, entities, npc).is_none() {
+        send_sell_failed(client, npc, guid, SellItemResult::CantFindVendor).await;
+        return;
+    }
+
+    let inventory = &client.character().inventory;
+    let found = inventory
+        .find(guid)
+        .and_then(|position| Some((position, *inventory.get_at(position)?)));
+    let Some((position, item)) = found else {
+        send_sell_failed(client, npc, guid, SellItemResult::CantFindItem).await;
+        return;
+    };
+
+    let amount = if amount == 0 { item.amount } else { amount };
+    let sell_price = u32::try_from(item.item.sell_price()).unwrap_or(0);
+    if sell_price == 0 || amount > item.amount {
+        send_sell_failed(client, npc, guid, SellItemResult::CantSellItem).await;
+        return;
+    }
+
+    let bag_not_empty = position
+        .item_slot()
+        .is_some_and(|slot| inventory.bag_contents(slot).iter().any(Option::is_some));
+    if bag_not_empty {
+        send_sell_failed(client, npc, guid, SellItemResult::OnlyEmptyBag).await;
+        return;
+    }
+
+    let owner = client.character().guid;
+    let sold = if amount < item.amount {
+        // The rest of the stack stays, the sold part becomes a new item
+        let sold = Item::new(item.item, item.creator, amount, db);
+        client.queue_object(sold.to_create_item_object(owner, owner, &[]));
+
+        if let Some(item) = client.character_mut().inventory.get_at_mut(position) {
+            item.set_amount(item.amount - amount);
+        }
+        sold
+    } else {
+        let character = client.character_mut();
+        character.inventory.remove(position);
+        character.mark_position_dirty(position);
+        item
+    };
+
+    let price = sell_price * u32::from(amount);
+    let character = client.character_mut();
+    character.set_money(character.money.saturating_add(price));
+
+    let oldest = character.add_buyback(BuybackItem {
+        item: sold,
+        price,
+        sold: SystemTime::now(),
+    });
+    if let Some(oldest) = oldest {
+        client
+            .send_message(SMSG_DESTROY_OBJECT { guid: oldest.guid })
+            .await;
+    }
+}
+
+/// `CMSG_BUYBACK_ITEM`, `slot` is the inventory slot of the buyback slot.
+pub(crate) async fn buyback_item(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    npc: Guid,
+    slot: u32,
+) {
+    if vendor_in_range(client, entities, npc).is_none() {
+        send_buy_failed(client, npc, 0, BuyResult::DistanceTooFar).await;
+        return;
+    }
+
+    let index = slot.checked_sub(BUYBACK_SLOT_START).map(|i| i as usize);
+    let buyback = index.and_then(|i| Some((i, *client.character().buyback.get(i)?)));
+    let Some((index, buyback)) = buyback else {
+        send_buy_failed(client, npc, 0, BuyResult::CantFindItem).await;
+        return;
+    };
+
+    let entry = buyback.item.item.entry();
+    if buyback.price > client.character().money {
+        send_buy_failed(client, npc, entry, BuyResult::NotEnoughtMoney).await;
+        return;
+    }
+
+    if !award_item(
+        buyback.item,
+        NewItemSource::FromNpc,
+        client,
+        entities.clients(),
+    )
+    .await
+    {
+        return;
+    }
+
+    let character = client.character_mut();
+    character.take_buyback(index);
+    character.set_money(character.money - buyback.price);
+
+    // Added to other stacks completely
+    let guid = buyback.item.guid;
+    if character.inventory.find(guid).is_none() {
+        client.send_message(SMSG_DESTROY_OBJECT { guid }).await;
+    }
+}
+
+/// `CMSG_REPAIR_ITEM`, an item of zero repairs everything the character carries.
+pub(crate) async fn repair_item(
+    client: &mut Client,
+    entities: &mut Entities<'_>,
+    npc: Guid,
+    item: Guid,
+) {
+    let repairs = vendor_in_range(client, entities, npc).is_some_and(|v| v.template.repairs);
+    if !repairs {
+        return;
+    }
+
+    let item = Some(item).filter(|guid| *guid != Guid::zero());
+
+    let character = client.character();
+    if let Some(guid) = item {
+        if character.inventory.find(guid).is_none() {
+            send_inventory_failure(client, EquipError::ItemNotFound, guid).await;
+            return;
+        }
+    }
+
+    let cost = character.repair_cost(item);
+    if cost > character.money {
+        send_inventory_failure(
+            client,
+            EquipError::NotEnoughMoney,
+            item.unwrap_or(Guid::zero()),
+        )
+        .await;
+        return;
+    }
+
+    let character = client.character_mut();
+    character.set_money(character.money - cost);
+    character.repair(item);
+}